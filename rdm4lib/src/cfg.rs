@@ -0,0 +1,796 @@
+use std::{
+    fmt::{self, Write},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use nalgebra::{Matrix4, Quaternion, UnitQuaternion, Vector3};
+use roxmltree::Node;
+
+use crate::rda;
+use crate::rdm_material::{self, texture_set_name, RdMaterial, TextureSet};
+
+#[derive(Debug)]
+pub enum CfgError {
+    Io(io::Error),
+    Xml(roxmltree::Error),
+    /// The cfg has no `<Models>`.
+    NoModel,
+    /// A glTF material without cfg material of the same name.
+    UnknownMaterial {
+        name: Option<String>,
+        available: Vec<String>,
+    },
+    /// A `<Transformer>` with an unreadable position, rotation or scale.
+    Transform {
+        model: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for CfgError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CfgError::Io(e) => write!(f, "{}", e),
+            CfgError::Xml(e) => write!(f, "invalid cfg: {}", e),
+            CfgError::NoModel => write!(f, "the cfg has no model"),
+            CfgError::UnknownMaterial { name, available } => write!(
+                f,
+                "no cfg material named {:?}. Available: {:?}",
+                name, available
+            ),
+            CfgError::Transform { model, reason } => {
+                write!(f, "invalid <Transformer> of model {:?}: {}", model, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CfgError {}
+
+impl From<io::Error> for CfgError {
+    fn from(e: io::Error) -> Self {
+        CfgError::Io(e)
+    }
+}
+
+impl From<roxmltree::Error> for CfgError {
+    fn from(e: roxmltree::Error) -> Self {
+        CfgError::Xml(e)
+    }
+}
+
+/// A `<Materials>` entry. Its position is the material index used by the rdm.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct CfgMaterial {
+    pub name: Option<String>,
+    pub shader_id: Option<u32>,
+    pub vertex_format: Option<String>,
+    /// `cModelDiffTex`, `cModelNormalTex`, ... with the texture path in the game data, in file order.
+    pub textures: Vec<(String, String)>,
+    /// Upper case shader switches like `DIFFUSE_ENABLED` with their value, in file order.
+    pub flags: Vec<(String, String)>,
+}
+
+impl CfgMaterial {
+    pub fn texture(&self, element: &str) -> Option<&str> {
+        self.textures
+            .iter()
+            .find(|(e, _)| e == element)
+            .map(|(_, path)| path.as_str())
+    }
+
+    /// `true` if the switch `name` exists and is not 0.
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags
+            .iter()
+            .any(|(f, value)| f == name && value != "0")
+    }
+
+    /// Alpha tested material: an `*ALPHA_TEST*` or `*CUTOUT*` switch is set.
+    pub fn is_cutout(&self) -> bool {
+        self.flags
+            .iter()
+            .any(|(f, value)| (f.contains("ALPHA_TEST") || f.contains("CUTOUT")) && value != "0")
+    }
+
+    /// Material `name` with the textures of `set` relative to `dir`, the enable switch of each texture and
+    /// `ALPHA_TEST_ENABLED` if the set has an alpha cutoff.
+    pub fn from_texture_set(name: Option<String>, set: &TextureSet, dir: &Path) -> Self {
+        let mut material = CfgMaterial {
+            name,
+            ..Default::default()
+        };
+        let textures = [
+            ("cModelDiffTex", "DIFFUSE_ENABLED", &set.diff),
+            ("cModelNormalTex", "NORMAL_ENABLED", &set.norm),
+            ("cModelMetallicTex", "METALLIC_TEX_ENABLED", &set.metal),
+            ("cModelHeightTex", "HEIGHT_MAP_ENABLED", &set.height),
+        ];
+        for (element, flag, path) in textures {
+            if let Some(path) = path {
+                let path = path.strip_prefix(dir).unwrap_or(path);
+                let path = path.to_string_lossy().replace('\\', "/");
+                material.textures.push((element.to_owned(), path));
+                material.flags.push((flag.to_owned(), String::from("1")));
+            }
+        }
+        if set.alpha_cutoff.is_some() {
+            material
+                .flags
+                .push((String::from("ALPHA_TEST_ENABLED"), String::from("1")));
+        }
+        material
+    }
+}
+
+/// An `<Animations>` entry of a model.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CfgAnimation {
+    pub file_name: String,
+    pub loop_count: Option<i32>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct CfgModel {
+    pub name: Option<String>,
+    /// rdm of LOD 0 in the game data.
+    pub file_name: String,
+    pub materials: Vec<CfgMaterial>,
+    pub animations: Vec<CfgAnimation>,
+    /// Position, rotation and scale of the model, if the cfg transforms it.
+    pub transform: Option<Matrix4<f32>>,
+}
+
+impl CfgModel {
+    /// `file_name` of LOD `level`: `foo_lod0.rdm` becomes `foo_lod<level>.rdm`.
+    pub fn lod_file_name(&self, level: u32) -> String {
+        let file = Path::new(&self.file_name);
+        let name = texture_set_name(file);
+        if file.file_stem().and_then(|s| s.to_str()) == Some(name.as_str()) {
+            // no LOD suffix
+            return self.file_name.clone();
+        }
+        let lod = format!("{}_lod{}.rdm", name, level);
+        match self.file_name.rfind(['/', '\\']) {
+            Some(i) => format!("{}{}", &self.file_name[..=i], lod),
+            None => lod,
+        }
+    }
+}
+
+/// One animation of a sequence: `<AnimID>` of model `<ModelID>`, played until `<EndTime>` (ms).
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CfgTrack {
+    pub model_id: Option<usize>,
+    pub anim_id: usize,
+    pub end_time: Option<u32>,
+}
+
+/// An `<AnimSequences>` entry.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CfgAnimSequence {
+    pub sequence_id: Option<i32>,
+    pub tracks: Vec<CfgTrack>,
+}
+
+/// Model description of an Anno asset (`.cfg`, XML).
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    /// Directory of the cfg, paths are also searched relative to it.
+    pub dir: PathBuf,
+    pub models: Vec<CfgModel>,
+    pub anim_sequences: Vec<CfgAnimSequence>,
+}
+
+impl Cfg {
+    /// Cfg of a single converted `model` with its `materials` and `animations` (file, length in ms), all paths
+    /// relative to the directory of `model`. Every animation gets a sequence of its own, numbered from 1000.
+    pub fn for_model(
+        model: &Path,
+        materials: Vec<CfgMaterial>,
+        animations: &[(PathBuf, u32)],
+    ) -> Self {
+        let dir = model.parent().unwrap_or_else(|| Path::new(""));
+        let relative = |path: &Path| {
+            let path = path.strip_prefix(dir).unwrap_or(path);
+            path.to_string_lossy().replace('\\', "/")
+        };
+        let model = CfgModel {
+            name: Some(texture_set_name(model)),
+            file_name: relative(model),
+            materials,
+            animations: animations
+                .iter()
+                .map(|(file, _)| CfgAnimation {
+                    file_name: relative(file),
+                    loop_count: Some(0),
+                })
+                .collect(),
+            transform: None,
+        };
+        let anim_sequences = animations
+            .iter()
+            .enumerate()
+            .map(|(i, (_, time_max))| CfgAnimSequence {
+                sequence_id: Some(1000 + i as i32),
+                tracks: vec![CfgTrack {
+                    model_id: Some(0),
+                    anim_id: i,
+                    end_time: Some(*time_max),
+                }],
+            })
+            .collect();
+        Cfg {
+            dir: dir.to_path_buf(),
+            models: vec![model],
+            anim_sequences,
+        }
+    }
+
+    pub fn open(path: &Path) -> Result<Self, CfgError> {
+        let text = String::from_utf8(rda::read(path)?)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let dir = path.parent().unwrap_or_else(|| Path::new(""));
+        Self::parse(&text, dir)
+    }
+
+    pub fn parse(text: &str, dir: &Path) -> Result<Self, CfgError> {
+        let mut body = text.trim_start_matches('\u{feff}').trim_start();
+        if body.starts_with("<?xml") {
+            body = body.split_once("?>").map_or("", |(_, rest)| rest);
+        }
+        // some cfg files have more than one top level element
+        let wrapped = format!("<cfg>{}</cfg>", body);
+        let doc = roxmltree::Document::parse(&wrapped)?;
+
+        let models: Vec<CfgModel> = list_configs(doc.root_element(), "Models")
+            .map(read_model)
+            .collect::<Result<_, _>>()?;
+        if models.is_empty() {
+            return Err(CfgError::NoModel);
+        }
+        let anim_sequences = list_configs(doc.root_element(), "AnimSequences")
+            .map(read_anim_sequence)
+            .collect();
+
+        Ok(Cfg {
+            dir: dir.to_path_buf(),
+            models,
+            anim_sequences,
+        })
+    }
+
+    /// The model of `rdm`, matched by name without LOD suffix. A cfg with a single model always matches.
+    pub fn model_for(&self, rdm: &Path) -> Option<&CfgModel> {
+        let name = texture_set_name(rdm);
+        self.models
+            .iter()
+            .find(|m| texture_set_name(Path::new(&m.file_name)) == name)
+            .or_else(|| (self.models.len() == 1).then(|| &self.models[0]))
+    }
+
+    /// Finds the game file `path` (rdm, animation) below `data_root`, or by file name next to the cfg and in
+    /// its `rdm` and `anim` directories.
+    pub fn resolve(&self, path: &str, data_root: Option<&Path>) -> Option<PathBuf> {
+        let path = PathBuf::from(path.replace('\\', "/"));
+        let file_name = path.file_name()?;
+        let mut candidates = Vec::new();
+        if let Some(root) = data_root {
+            candidates.push(root.join(&path));
+        }
+        candidates.push(self.dir.join(&path));
+        for sub in ["", "rdm", "anim"] {
+            candidates.push(self.dir.join(sub).join(file_name));
+        }
+        candidates.into_iter().find(|p| rda::is_file(p))
+    }
+
+    /// Textures of every material of `model`, found like [`RdMaterial::discover`] finds the rdm textures, also
+    /// in the `maps` directory next to the cfg. Cutout materials get an alpha cutoff of 0.5.
+    pub fn material(&self, model: &CfgModel, data_root: Option<&Path>) -> RdMaterial {
+        let maps = self.dir.join("maps");
+        let find = |material: &CfgMaterial, element: &str| {
+            let path = material.texture(element)?;
+            rdm_material::resolve_texture(path, &self.dir, data_root)
+                .or_else(|| rdm_material::resolve_texture(path, &maps, data_root))
+        };
+        let textures = model
+            .materials
+            .iter()
+            .map(|m| TextureSet {
+                diff: find(m, "cModelDiffTex"),
+                norm: find(m, "cModelNormalTex"),
+                metal: find(m, "cModelMetallicTex"),
+                height: find(m, "cModelHeightTex"),
+                alpha_cutoff: m.is_cutout().then_some(0.5),
+            })
+            .collect();
+        RdMaterial::from_texture_sets(textures)
+    }
+
+    /// The cfg as XML, in the layout [`parse`](Self::parse) reads.
+    pub fn to_xml(&self) -> String {
+        let mut xml = XmlWriter::default();
+        xml.line(r#"<?xml version="1.0" encoding="utf-8"?>"#);
+        xml.open("Config");
+        xml.value("ConfigType", "MAIN");
+        xml.open("Models");
+        for model in &self.models {
+            write_model(&mut xml, model);
+        }
+        xml.close("Models");
+        if !self.anim_sequences.is_empty() {
+            xml.open("AnimSequences");
+            for sequence in &self.anim_sequences {
+                write_anim_sequence(&mut xml, sequence);
+            }
+            xml.close("AnimSequences");
+        }
+        xml.close("Config");
+        xml.out
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_xml())
+    }
+
+    /// Material index of `model` for each glTF material name, e.g. for `overide_mesh_idx`.
+    pub fn material_order(
+        model: &CfgModel,
+        names: &[Option<String>],
+    ) -> Result<Vec<u32>, CfgError> {
+        let available: Vec<&str> = model
+            .materials
+            .iter()
+            .map(|m| m.name.as_deref().unwrap_or_default())
+            .collect();
+        names
+            .iter()
+            .map(|name| {
+                let found = name.as_deref().and_then(|name| {
+                    available
+                        .iter()
+                        .position(|a| *a == name)
+                        .or_else(|| available.iter().position(|a| a.eq_ignore_ascii_case(name)))
+                });
+                found
+                    .map(|i| i as u32)
+                    .ok_or_else(|| CfgError::UnknownMaterial {
+                        name: name.clone(),
+                        available: available.iter().map(|a| a.to_string()).collect(),
+                    })
+            })
+            .collect()
+    }
+}
+
+fn child<'a, 'input>(node: Node<'a, 'input>, name: &str) -> Option<Node<'a, 'input>> {
+    node.children().find(|n| n.has_tag_name(name))
+}
+
+fn child_text<'a>(node: Node<'a, '_>, name: &str) -> Option<&'a str> {
+    child(node, name).and_then(|n| n.text()).map(str::trim)
+}
+
+fn child_value<T: std::str::FromStr>(node: Node, name: &str) -> Option<T> {
+    child_text(node, name).and_then(|t| t.parse().ok())
+}
+
+/// `<Config>` children of every `<list>` below `node`.
+fn list_configs<'a, 'input>(
+    node: Node<'a, 'input>,
+    list: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.descendants()
+        .filter(move |n| n.has_tag_name(list))
+        .flat_map(|n| n.children().filter(|c| c.has_tag_name("Config")))
+}
+
+fn read_model(node: Node) -> Result<CfgModel, CfgError> {
+    let file_name = child_text(node, "FileName").unwrap_or_default().to_owned();
+    let transform = read_transform(node).map_err(|reason| CfgError::Transform {
+        model: file_name.clone(),
+        reason,
+    })?;
+    Ok(CfgModel {
+        name: child_text(node, "Name").map(String::from),
+        file_name,
+        materials: list_configs(node, "Materials").map(read_material).collect(),
+        animations: list_configs(node, "Animations")
+            .map(|a| CfgAnimation {
+                file_name: child_text(a, "FileName").unwrap_or_default().to_owned(),
+                loop_count: child_value(a, "LoopCount"),
+            })
+            .collect(),
+        transform,
+    })
+}
+
+fn read_material(node: Node) -> CfgMaterial {
+    let mut material = CfgMaterial {
+        name: child_text(node, "Name").map(String::from),
+        shader_id: child_value(node, "ShaderID"),
+        vertex_format: child_text(node, "VertexFormat").map(String::from),
+        ..Default::default()
+    };
+    for element in node.children().filter(|n| n.is_element()) {
+        let tag = element.tag_name().name();
+        let value = match element.text().map(str::trim) {
+            Some(value) if !value.is_empty() => value.to_owned(),
+            _ => continue,
+        };
+        if tag.ends_with("Tex") {
+            material.textures.push((tag.to_owned(), value));
+        } else if tag.starts_with(|c: char| c.is_ascii_uppercase())
+            && !tag.contains(|c: char| c.is_ascii_lowercase())
+        {
+            material.flags.push((tag.to_owned(), value));
+        }
+    }
+    material
+}
+
+/// `Position.*`, `Rotation.*` (quaternion) and `Scale` of the `<Transformer>` of the model. Position and scale
+/// default to 0 and 1, the rotation is required.
+fn read_transform(node: Node) -> Result<Option<Matrix4<f32>>, String> {
+    let config = match child(node, "Transformer").and_then(|t| child(t, "Config")) {
+        Some(config) => config,
+        None => return Ok(None),
+    };
+    let value = |name: &str| match child_text(config, name) {
+        Some(text) => match text.parse::<f32>() {
+            Ok(v) if v.is_finite() => Ok(Some(v)),
+            _ => Err(format!("{} is not a number: {:?}", name, text)),
+        },
+        None => Ok(None),
+    };
+
+    let position = Vector3::new(
+        value("Position.x")?.unwrap_or(0.0),
+        value("Position.y")?.unwrap_or(0.0),
+        value("Position.z")?.unwrap_or(0.0),
+    );
+    let mut q = [0.0; 4];
+    for (v, axis) in q.iter_mut().zip(["x", "y", "z", "w"]) {
+        let name = format!("Rotation.{}", axis);
+        *v = value(&name)?.ok_or_else(|| format!("{} is missing", name))?;
+    }
+    let rotation = UnitQuaternion::try_new(Quaternion::new(q[3], q[0], q[1], q[2]), 1.0e-6)
+        .ok_or_else(|| format!("rotation {:?} is not a valid quaternion", q))?;
+    let scale = value("Scale")?.unwrap_or(1.0);
+    if scale <= 0.0 {
+        return Err(format!("scale {} is not positive", scale));
+    }
+    Ok(Some(
+        Matrix4::new_translation(&position)
+            * rotation.to_homogeneous()
+            * Matrix4::new_scaling(scale),
+    ))
+}
+
+fn read_anim_sequence(node: Node) -> CfgAnimSequence {
+    let tracks = node
+        .descendants()
+        .filter(|n| child(*n, "AnimID").is_some())
+        .filter_map(|n| {
+            let model_id = n.ancestors().find_map(|a| child_value(a, "ModelID"));
+            Some(CfgTrack {
+                model_id,
+                anim_id: child_value(n, "AnimID")?,
+                end_time: child_value(n, "EndTime"),
+            })
+        })
+        .collect();
+    CfgAnimSequence {
+        sequence_id: child_value(node, "SequenceID"),
+        tracks,
+    }
+}
+
+/// Indented XML output of [`Cfg::to_xml`].
+#[derive(Default)]
+struct XmlWriter {
+    out: String,
+    depth: usize,
+}
+
+impl XmlWriter {
+    fn line(&mut self, text: &str) {
+        writeln!(self.out, "{:indent$}{}", "", text, indent = 2 * self.depth).unwrap();
+    }
+
+    fn open(&mut self, tag: &str) {
+        self.line(&format!("<{}>", tag));
+        self.depth += 1;
+    }
+
+    fn close(&mut self, tag: &str) {
+        self.depth -= 1;
+        self.line(&format!("</{}>", tag));
+    }
+
+    fn value(&mut self, tag: &str, value: impl fmt::Display) {
+        let value = value
+            .to_string()
+            .replace('&', "&amp;")
+            .replace('<', "&lt;")
+            .replace('>', "&gt;");
+        self.line(&format!("<{0}>{1}</{0}>", tag, value));
+    }
+}
+
+fn write_model(xml: &mut XmlWriter, model: &CfgModel) {
+    xml.open("Config");
+    xml.value("ConfigType", "MODEL");
+    if let Some(name) = &model.name {
+        xml.value("Name", name);
+    }
+    xml.value("FileName", &model.file_name);
+    xml.open("Materials");
+    for material in &model.materials {
+        xml.open("Config");
+        xml.value("ConfigType", "MATERIAL");
+        if let Some(name) = &material.name {
+            xml.value("Name", name);
+        }
+        if let Some(shader_id) = material.shader_id {
+            xml.value("ShaderID", shader_id);
+        }
+        if let Some(vertex_format) = &material.vertex_format {
+            xml.value("VertexFormat", vertex_format);
+        }
+        for (tag, value) in material.flags.iter().chain(&material.textures) {
+            xml.value(tag, value);
+        }
+        xml.close("Config");
+    }
+    xml.close("Materials");
+    if !model.animations.is_empty() {
+        xml.open("Animations");
+        for animation in &model.animations {
+            xml.open("Config");
+            xml.value("ConfigType", "ANIMATION");
+            xml.value("FileName", &animation.file_name);
+            if let Some(loop_count) = animation.loop_count {
+                xml.value("LoopCount", loop_count);
+            }
+            xml.close("Config");
+        }
+        xml.close("Animations");
+    }
+    if let Some(transform) = model.transform {
+        write_transform(xml, &transform);
+    }
+    xml.close("Config");
+}
+
+/// Inverse of [`read_transform`] for a uniformly scaled transform.
+fn write_transform(xml: &mut XmlWriter, transform: &Matrix4<f32>) {
+    let scale = transform.fixed_slice::<3, 1>(0, 0).norm();
+    let rotation = UnitQuaternion::from_matrix(&(transform.fixed_slice::<3, 3>(0, 0) / scale));
+    xml.open("Transformer");
+    xml.open("Config");
+    xml.value("ConfigType", "ORIENTATION_TRANSFORM");
+    for (axis, value) in ["x", "y", "z"]
+        .iter()
+        .zip(transform.fixed_slice::<3, 1>(0, 3).iter())
+    {
+        xml.value(&format!("Position.{}", axis), value);
+    }
+    for (axis, value) in ["x", "y", "z", "w"].iter().zip(rotation.coords.iter()) {
+        xml.value(&format!("Rotation.{}", axis), value);
+    }
+    xml.value("Scale", scale);
+    xml.close("Config");
+    xml.close("Transformer");
+}
+
+fn write_anim_sequence(xml: &mut XmlWriter, sequence: &CfgAnimSequence) {
+    xml.open("Config");
+    xml.value("ConfigType", "ANIMSEQUENCE");
+    if let Some(sequence_id) = sequence.sequence_id {
+        xml.value("SequenceID", sequence_id);
+    }
+    for track in &sequence.tracks {
+        xml.open("Track");
+        if let Some(model_id) = track.model_id {
+            xml.value("ModelID", model_id);
+        }
+        xml.open("Anim");
+        xml.value("AnimID", track.anim_id);
+        if let Some(end_time) = track.end_time {
+            xml.value("EndTime", end_time);
+        }
+        xml.close("Anim");
+        xml.close("Track");
+    }
+    xml.close("Config");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CFG: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<Config>
+  <ConfigType>MAIN</ConfigType>
+  <Models>
+    <Config>
+      <ConfigType>MODEL</ConfigType>
+      <Name>mill</Name>
+      <FileName>data/graphics/buildings/mill/rdm/mill_lod0.rdm</FileName>
+      <Materials>
+        <Config>
+          <ConfigType>MATERIAL</ConfigType>
+          <Name>wood</Name>
+          <ShaderID>8</ShaderID>
+          <VertexFormat>P4h_N4b_G4b_B4b_T2h</VertexFormat>
+          <DIFFUSE_ENABLED>1</DIFFUSE_ENABLED>
+          <cModelDiffTex>data/graphics/buildings/mill/maps/mill_diff.psd</cModelDiffTex>
+          <cModelNormalTex></cModelNormalTex>
+          <ALPHA_TEST_ENABLED>1</ALPHA_TEST_ENABLED>
+        </Config>
+        <Config>
+          <ConfigType>MATERIAL</ConfigType>
+          <Name>stone</Name>
+          <ALPHA_TEST_ENABLED>0</ALPHA_TEST_ENABLED>
+        </Config>
+      </Materials>
+      <Animations>
+        <Config>
+          <ConfigType>ANIMATION</ConfigType>
+          <FileName>data/graphics/buildings/mill/anim/mill_work01.rdm</FileName>
+          <LoopCount>0</LoopCount>
+        </Config>
+      </Animations>
+      <Transformer>
+        <Config>
+          <ConfigType>ORIENTATION_TRANSFORM</ConfigType>
+          <Position.x>2</Position.x>
+          <Position.y>0</Position.y>
+          <Position.z>-1</Position.z>
+          <Rotation.x>0</Rotation.x>
+          <Rotation.y>0.7071068</Rotation.y>
+          <Rotation.z>0</Rotation.z>
+          <Rotation.w>0.7071068</Rotation.w>
+        </Config>
+      </Transformer>
+    </Config>
+  </Models>
+  <AnimSequences>
+    <Config>
+      <ConfigType>ANIMSEQUENCE</ConfigType>
+      <SequenceID>1000</SequenceID>
+      <Track>
+        <ModelID>0</ModelID>
+        <Anim>
+          <AnimID>0</AnimID>
+          <EndTime>2000</EndTime>
+        </Anim>
+      </Track>
+    </Config>
+  </AnimSequences>
+</Config>
+"#;
+
+    #[test]
+    fn parse_cfg() {
+        let cfg = Cfg::parse(CFG, Path::new("")).unwrap();
+        assert_eq!(cfg.models.len(), 1);
+        let model = &cfg.models[0];
+        assert_eq!(model.name.as_deref(), Some("mill"));
+        assert_eq!(
+            model.lod_file_name(2),
+            "data/graphics/buildings/mill/rdm/mill_lod2.rdm"
+        );
+
+        let wood = &model.materials[0];
+        assert_eq!(wood.shader_id, Some(8));
+        assert_eq!(
+            wood.texture("cModelDiffTex"),
+            Some("data/graphics/buildings/mill/maps/mill_diff.psd")
+        );
+        // empty elements are skipped
+        assert_eq!(wood.textures.len(), 1);
+        assert!(wood.flag("DIFFUSE_ENABLED"));
+        assert!(wood.is_cutout());
+        assert!(!model.materials[1].is_cutout());
+
+        assert_eq!(model.animations[0].loop_count, Some(0));
+        let moved = model
+            .transform
+            .unwrap()
+            .transform_point(&[1.0, 0.0, 0.0].into());
+        assert_relative_eq!(moved.x, 2.0, epsilon = 1e-5);
+        assert_relative_eq!(moved.z, -2.0, epsilon = 1e-5);
+
+        let track = &cfg.anim_sequences[0].tracks[0];
+        assert_eq!(cfg.anim_sequences[0].sequence_id, Some(1000));
+        assert_eq!(
+            (track.model_id, track.anim_id, track.end_time),
+            (Some(0), 0, Some(2000))
+        );
+    }
+
+    #[test]
+    fn invalid_transform() {
+        let parse = |cfg: &str| Cfg::parse(cfg, Path::new(""));
+        let rotation = |y: &str, w: &str| {
+            CFG.replace(
+                "<Rotation.y>0.7071068</Rotation.y>",
+                &format!("<Rotation.y>{}</Rotation.y>", y),
+            )
+            .replace(
+                "<Rotation.w>0.7071068</Rotation.w>",
+                &format!("<Rotation.w>{}</Rotation.w>", w),
+            )
+        };
+        let invalid = |cfg: &str| matches!(parse(cfg), Err(CfgError::Transform { .. }));
+        assert!(invalid(&rotation("0", "0")));
+        assert!(invalid(&rotation("0", "NaN")));
+        assert!(invalid(
+            &CFG.replace("<Rotation.w>0.7071068</Rotation.w>", "")
+        ));
+
+        // only the Transformer of the model counts
+        let nested = CFG
+            .replace("<Transformer>", "<Other><Transformer>")
+            .replace("</Transformer>", "</Transformer></Other>");
+        assert!(parse(&nested).unwrap().models[0].transform.is_none());
+    }
+
+    #[test]
+    fn material_order_by_name() {
+        let cfg = Cfg::parse(CFG, Path::new("")).unwrap();
+        let names = [Some(String::from("Stone")), Some(String::from("wood"))];
+        assert_eq!(
+            Cfg::material_order(&cfg.models[0], &names).unwrap(),
+            vec![1, 0]
+        );
+        assert!(Cfg::material_order(&cfg.models[0], &[None]).is_err());
+    }
+
+    #[test]
+    fn write_parse_roundtrip() {
+        let cfg = Cfg::parse(CFG, Path::new("")).unwrap();
+        let written = Cfg::parse(&cfg.to_xml(), Path::new("")).unwrap();
+        let (model, read) = (&cfg.models[0], &written.models[0]);
+        assert_eq!(read.materials, model.materials);
+        assert_eq!(read.animations, model.animations);
+        let (a, b) = (read.transform.unwrap(), model.transform.unwrap());
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert_relative_eq!(a, b, epsilon = 1e-5);
+        }
+        assert_eq!(written.anim_sequences, cfg.anim_sequences);
+
+        let dir = Path::new("out");
+        let set = TextureSet {
+            diff: Some(dir.join("house_diff_0.dds")),
+            height: Some(dir.join("house_height_0.dds")),
+            alpha_cutoff: Some(0.5),
+            ..Default::default()
+        };
+        let material = CfgMaterial::from_texture_set(Some(String::from("a & b")), &set, dir);
+        let anims = [(dir.join("house_lod0_walk.rdm"), 1500)];
+        let cfg = Cfg::for_model(&dir.join("house_lod0.rdm"), vec![material], &anims);
+        let read = Cfg::parse(&cfg.to_xml(), dir).unwrap();
+        let model = &read.models[0];
+        assert_eq!(model.file_name, "house_lod0.rdm");
+        assert_eq!(model.materials[0].name.as_deref(), Some("a & b"));
+        assert_eq!(
+            model.materials[0].texture("cModelDiffTex"),
+            Some("house_diff_0.dds")
+        );
+        assert_eq!(
+            model.materials[0].texture("cModelHeightTex"),
+            Some("house_height_0.dds")
+        );
+        assert!(model.materials[0]
+            .flags
+            .contains(&(String::from("HEIGHT_MAP_ENABLED"), String::from("1"))));
+        assert!(model.materials[0].is_cutout());
+        assert_eq!(model.animations[0].file_name, "house_lod0_walk.rdm");
+        assert_eq!(read.anim_sequences[0].tracks[0].end_time, Some(1500));
+    }
+}
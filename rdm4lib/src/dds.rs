@@ -0,0 +1,817 @@
+use crate::rda;
+use std::{fmt, io, path::Path};
+
+const DDS_MAGIC: &[u8; 4] = b"DDS ";
+// magic + DDS_HEADER
+const HEADER_LEN: usize = 4 + 124;
+const DX10_HEADER_LEN: usize = 20;
+
+const DDPF_ALPHAPIXELS: u32 = 0x1;
+const DDPF_ALPHA: u32 = 0x2;
+const DDPF_FOURCC: u32 = 0x4;
+const DDPF_RGB: u32 = 0x40;
+const DDPF_LUMINANCE: u32 = 0x2_0000;
+
+#[derive(Debug)]
+pub enum DdsError {
+    Io(io::Error),
+    Png(png::EncodingError),
+    /// Missing `DDS ` magic or a header shorter than 128 bytes.
+    NotDds,
+    Unsupported(String),
+    /// Less pixel data than the header announces.
+    Truncated {
+        expected: usize,
+        found: usize,
+    },
+    MipLevel {
+        level: u32,
+        count: u32,
+    },
+}
+
+impl fmt::Display for DdsError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DdsError::Io(e) => write!(f, "{}", e),
+            DdsError::Png(e) => write!(f, "{}", e),
+            DdsError::NotDds => write!(f, "not a DDS file"),
+            DdsError::Unsupported(format) => write!(f, "unsupported DDS format: {}", format),
+            DdsError::Truncated { expected, found } => write!(
+                f,
+                "DDS pixel data truncated: expected {} bytes, found {}",
+                expected, found
+            ),
+            DdsError::MipLevel { level, count } => write!(
+                f,
+                "mipmap level {} does not exist, the texture has {} levels",
+                level, count
+            ),
+        }
+    }
+}
+
+impl std::error::Error for DdsError {}
+
+impl From<io::Error> for DdsError {
+    fn from(e: io::Error) -> Self {
+        DdsError::Io(e)
+    }
+}
+
+impl From<png::EncodingError> for DdsError {
+    fn from(e: png::EncodingError) -> Self {
+        DdsError::Png(e)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DdsFormat {
+    Bc1,
+    Bc2,
+    Bc3,
+    Bc4,
+    Bc5,
+    Bc7,
+    /// `bytes` per pixel with RGBA bit masks. Luminance formats store gray in the red mask.
+    Uncompressed {
+        bytes: u32,
+        masks: [u32; 4],
+        luminance: bool,
+    },
+}
+
+impl DdsFormat {
+    const RGBA8: DdsFormat = DdsFormat::Uncompressed {
+        bytes: 4,
+        masks: [0xff, 0xff00, 0xff_0000, 0xff00_0000],
+        luminance: false,
+    };
+    const BGRA8: DdsFormat = DdsFormat::Uncompressed {
+        bytes: 4,
+        masks: [0xff_0000, 0xff00, 0xff, 0xff00_0000],
+        luminance: false,
+    };
+    const BGRX8: DdsFormat = DdsFormat::Uncompressed {
+        bytes: 4,
+        masks: [0xff_0000, 0xff00, 0xff, 0],
+        luminance: false,
+    };
+    const R8: DdsFormat = DdsFormat::Uncompressed {
+        bytes: 1,
+        masks: [0xff, 0, 0, 0],
+        luminance: true,
+    };
+
+    fn from_fourcc(fourcc: &[u8]) -> Option<Self> {
+        match fourcc {
+            b"DXT1" => Some(DdsFormat::Bc1),
+            b"DXT2" | b"DXT3" => Some(DdsFormat::Bc2),
+            b"DXT4" | b"DXT5" => Some(DdsFormat::Bc3),
+            b"ATI1" | b"BC4U" => Some(DdsFormat::Bc4),
+            b"ATI2" | b"BC5U" => Some(DdsFormat::Bc5),
+            _ => None,
+        }
+    }
+
+    fn from_dxgi(dxgi: u32) -> Option<Self> {
+        match dxgi {
+            28 | 29 => Some(DdsFormat::RGBA8),
+            61 => Some(DdsFormat::R8),
+            71 | 72 => Some(DdsFormat::Bc1),
+            74 | 75 => Some(DdsFormat::Bc2),
+            77 | 78 => Some(DdsFormat::Bc3),
+            80 => Some(DdsFormat::Bc4),
+            83 => Some(DdsFormat::Bc5),
+            87 | 91 => Some(DdsFormat::BGRA8),
+            88 | 93 => Some(DdsFormat::BGRX8),
+            98 | 99 => Some(DdsFormat::Bc7),
+            _ => None,
+        }
+    }
+
+    fn block_len(&self) -> Option<usize> {
+        match self {
+            DdsFormat::Bc1 | DdsFormat::Bc4 => Some(8),
+            DdsFormat::Bc2 | DdsFormat::Bc3 | DdsFormat::Bc5 | DdsFormat::Bc7 => Some(16),
+            DdsFormat::Uncompressed { .. } => None,
+        }
+    }
+
+    fn surface_len(&self, width: u32, height: u32) -> usize {
+        match (self.block_len(), self) {
+            (Some(block_len), _) => {
+                width.div_ceil(4) as usize * height.div_ceil(4) as usize * block_len
+            }
+            (None, DdsFormat::Uncompressed { bytes, .. }) => {
+                width as usize * height as usize * *bytes as usize
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+/// 8 bit RGBA pixels, rows top to bottom.
+#[derive(Clone, Debug)]
+pub struct RgbaImage {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    pub fn write_png(&self, writer: impl io::Write) -> Result<(), DdsError> {
+        let mut encoder = png::Encoder::new(writer, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.pixels)?;
+        writer.finish()?;
+        Ok(())
+    }
+
+    pub fn to_png(&self) -> Result<Vec<u8>, DdsError> {
+        let mut buffer = Vec::new();
+        self.write_png(&mut buffer)?;
+        Ok(buffer)
+    }
+}
+
+/// A 2D texture. Of cube maps and arrays only the first surface is kept.
+#[derive(Clone, Debug)]
+pub struct Dds {
+    width: u32,
+    height: u32,
+    mip_count: u32,
+    format: DdsFormat,
+    data: Vec<u8>,
+}
+
+impl Dds {
+    pub fn open(path: &Path) -> Result<Self, DdsError> {
+        Dds::from_bytes(rda::read(path)?)
+    }
+
+    pub fn from_bytes(mut bytes: Vec<u8>) -> Result<Self, DdsError> {
+        if bytes.len() < HEADER_LEN || &bytes[0..4] != DDS_MAGIC {
+            return Err(DdsError::NotDds);
+        }
+        let u32_at = |offset: usize| {
+            u32::from_le_bytes([
+                bytes[offset],
+                bytes[offset + 1],
+                bytes[offset + 2],
+                bytes[offset + 3],
+            ])
+        };
+        let height = u32_at(12);
+        let width = u32_at(16);
+        let mip_count = u32_at(28).max(1);
+        let pf_flags = u32_at(80);
+        let fourcc = &bytes[84..88];
+
+        let mut data_offset = HEADER_LEN;
+        let format = if pf_flags & DDPF_FOURCC != 0 {
+            if fourcc == b"DX10" {
+                if bytes.len() < HEADER_LEN + DX10_HEADER_LEN {
+                    return Err(DdsError::NotDds);
+                }
+                data_offset += DX10_HEADER_LEN;
+                let dxgi = u32_at(HEADER_LEN);
+                DdsFormat::from_dxgi(dxgi)
+                    .ok_or_else(|| DdsError::Unsupported(format!("DXGI format {}", dxgi)))?
+            } else {
+                DdsFormat::from_fourcc(fourcc).ok_or_else(|| {
+                    DdsError::Unsupported(format!("FourCC {:?}", String::from_utf8_lossy(fourcc)))
+                })?
+            }
+        } else if pf_flags & (DDPF_RGB | DDPF_LUMINANCE | DDPF_ALPHA) != 0 {
+            let bit_count = u32_at(88);
+            if !matches!(bit_count, 8 | 16 | 24 | 32) {
+                return Err(DdsError::Unsupported(format!(
+                    "{} bits per pixel",
+                    bit_count
+                )));
+            }
+            let has_alpha = pf_flags & (DDPF_ALPHAPIXELS | DDPF_ALPHA) != 0;
+            DdsFormat::Uncompressed {
+                bytes: bit_count / 8,
+                masks: [
+                    u32_at(92),
+                    u32_at(96),
+                    u32_at(100),
+                    if has_alpha { u32_at(104) } else { 0 },
+                ],
+                luminance: pf_flags & DDPF_LUMINANCE != 0,
+            }
+        } else {
+            return Err(DdsError::Unsupported(format!(
+                "pixel format flags {:#x}",
+                pf_flags
+            )));
+        };
+
+        bytes.drain(..data_offset);
+        Ok(Dds {
+            width,
+            height,
+            mip_count,
+            format,
+            data: bytes,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn mip_count(&self) -> u32 {
+        self.mip_count
+    }
+
+    pub fn format(&self) -> DdsFormat {
+        self.format
+    }
+
+    /// Width and height of mipmap `level`.
+    pub fn mip_size(&self, level: u32) -> (u32, u32) {
+        let shift = |size: u32| size.checked_shr(level).unwrap_or(0).max(1);
+        (shift(self.width), shift(self.height))
+    }
+
+    /// Decodes mipmap `level`, 0 is the full resolution.
+    pub fn decode(&self, level: u32) -> Result<RgbaImage, DdsError> {
+        if level >= self.mip_count {
+            return Err(DdsError::MipLevel {
+                level,
+                count: self.mip_count,
+            });
+        }
+        let offset: usize = (0..level)
+            .map(|l| {
+                let (w, h) = self.mip_size(l);
+                self.format.surface_len(w, h)
+            })
+            .sum();
+        let (width, height) = self.mip_size(level);
+        let expected = offset + self.format.surface_len(width, height);
+        if self.data.len() < expected {
+            return Err(DdsError::Truncated {
+                expected,
+                found: self.data.len(),
+            });
+        }
+        let data = &self.data[offset..expected];
+
+        let pixels = match self.format {
+            DdsFormat::Bc1 => decode_blocks(data, width, height, 8, |b| bc1(b, false)),
+            DdsFormat::Bc2 => decode_blocks(data, width, height, 16, bc2),
+            DdsFormat::Bc3 => decode_blocks(data, width, height, 16, bc3),
+            DdsFormat::Bc4 => {
+                decode_blocks(data, width, height, 8, |b| bc4(b).map(|v| [v, v, v, 255]))
+            }
+            DdsFormat::Bc5 => decode_blocks(data, width, height, 16, bc5),
+            DdsFormat::Bc7 => decode_blocks(data, width, height, 16, bc7),
+            DdsFormat::Uncompressed {
+                bytes,
+                masks,
+                luminance,
+            } => decode_uncompressed(data, bytes as usize, masks, luminance),
+        };
+        Ok(RgbaImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+}
+
+/// Decodes `path` at mipmap `level` and encodes it as PNG.
+pub fn convert_to_png(path: &Path, level: u32) -> Result<Vec<u8>, DdsError> {
+    Dds::open(path)?.decode(level)?.to_png()
+}
+
+fn decode_blocks(
+    data: &[u8],
+    width: u32,
+    height: u32,
+    block_len: usize,
+    decode: impl Fn(&[u8]) -> [[u8; 4]; 16],
+) -> Vec<u8> {
+    let (width, height) = (width as usize, height as usize);
+    let blocks_x = width.div_ceil(4);
+    let mut pixels = vec![0; width * height * 4];
+    for (i, block) in data.chunks_exact(block_len).enumerate() {
+        let (bx, by) = (i % blocks_x * 4, i / blocks_x * 4);
+        for (p, rgba) in decode(block).iter().enumerate() {
+            let (x, y) = (bx + p % 4, by + p / 4);
+            if x < width && y < height {
+                let o = (y * width + x) * 4;
+                pixels[o..o + 4].copy_from_slice(rgba);
+            }
+        }
+    }
+    pixels
+}
+
+fn decode_uncompressed(data: &[u8], bytes: usize, masks: [u32; 4], luminance: bool) -> Vec<u8> {
+    // scales the masked bits to 0..=255, missing channels read as None
+    let channel = |px: u32, mask: u32| {
+        if mask == 0 {
+            return None;
+        }
+        let shift = mask.trailing_zeros();
+        let max = u64::from(mask >> shift);
+        let v = u64::from((px & mask) >> shift);
+        Some(((v * 255 + max / 2) / max) as u8)
+    };
+    let mut pixels = Vec::with_capacity(data.len() / bytes * 4);
+    for px in data.chunks_exact(bytes) {
+        let mut le = [0; 4];
+        le[..bytes].copy_from_slice(px);
+        let px = u32::from_le_bytes(le);
+        let a = channel(px, masks[3]).unwrap_or(255);
+        if luminance {
+            let l = channel(px, masks[0]).unwrap_or(0);
+            pixels.extend_from_slice(&[l, l, l, a]);
+        } else {
+            pixels.extend_from_slice(&[
+                channel(px, masks[0]).unwrap_or(0),
+                channel(px, masks[1]).unwrap_or(0),
+                channel(px, masks[2]).unwrap_or(0),
+                a,
+            ]);
+        }
+    }
+    pixels
+}
+
+fn rgb565(c: u16) -> [u8; 4] {
+    let r = ((c >> 11) & 0x1f) as u8;
+    let g = ((c >> 5) & 0x3f) as u8;
+    let b = (c & 0x1f) as u8;
+    [r << 3 | r >> 2, g << 2 | g >> 4, b << 3 | b >> 2, 255]
+}
+
+/// BC2 and BC3 always use four colors, BC1 switches to three colors and transparent black if `c0 <= c1`.
+pub(crate) fn bc1_palette(c0: u16, c1: u16, four_colors: bool) -> [[u8; 4]; 4] {
+    let (e0, e1) = (rgb565(c0), rgb565(c1));
+    let mix = |w0: u16, w1: u16| {
+        let mut c = [255; 4];
+        for i in 0..3 {
+            c[i] = ((w0 * u16::from(e0[i]) + w1 * u16::from(e1[i])) / (w0 + w1)) as u8;
+        }
+        c
+    };
+    if c0 > c1 || four_colors {
+        [e0, e1, mix(2, 1), mix(1, 2)]
+    } else {
+        [e0, e1, mix(1, 1), [0; 4]]
+    }
+}
+
+fn bc1(block: &[u8], four_colors: bool) -> [[u8; 4]; 16] {
+    let c0 = u16::from_le_bytes([block[0], block[1]]);
+    let c1 = u16::from_le_bytes([block[2], block[3]]);
+    let palette = bc1_palette(c0, c1, four_colors);
+    let indices = u32::from_le_bytes([block[4], block[5], block[6], block[7]]);
+    std::array::from_fn(|i| palette[(indices >> (2 * i) & 3) as usize])
+}
+
+fn bc2(block: &[u8]) -> [[u8; 4]; 16] {
+    let alpha = u64::from_le_bytes(block[0..8].try_into().unwrap());
+    let mut out = bc1(&block[8..16], true);
+    for (i, px) in out.iter_mut().enumerate() {
+        px[3] = (alpha >> (4 * i) & 0xf) as u8 * 17;
+    }
+    out
+}
+
+fn bc3(block: &[u8]) -> [[u8; 4]; 16] {
+    let alpha = bc4(&block[0..8]);
+    let mut out = bc1(&block[8..16], true);
+    for (px, a) in out.iter_mut().zip(alpha) {
+        px[3] = a;
+    }
+    out
+}
+
+/// Eight values if `a0 > a1`, otherwise six values, 0 and 255.
+pub(crate) fn bc4_palette(a0: u8, a1: u8) -> [u8; 8] {
+    let (a0, a1) = (u16::from(a0), u16::from(a1));
+    let mut palette = [0; 8];
+    palette[0] = a0 as u8;
+    palette[1] = a1 as u8;
+    if a0 > a1 {
+        for k in 1..7 {
+            palette[k + 1] = (((7 - k as u16) * a0 + k as u16 * a1) / 7) as u8;
+        }
+    } else {
+        for k in 1..5 {
+            palette[k + 1] = (((5 - k as u16) * a0 + k as u16 * a1) / 5) as u8;
+        }
+        palette[6] = 0;
+        palette[7] = 255;
+    }
+    palette
+}
+
+/// Single channel block, also the alpha block of BC3.
+fn bc4(block: &[u8]) -> [u8; 16] {
+    let palette = bc4_palette(block[0], block[1]);
+    let mut le = [0; 8];
+    le[..6].copy_from_slice(&block[2..8]);
+    let indices = u64::from_le_bytes(le);
+    std::array::from_fn(|i| palette[(indices >> (3 * i) & 7) as usize])
+}
+
+/// Two BC4 blocks for red and green, blue is 0.
+fn bc5(block: &[u8]) -> [[u8; 4]; 16] {
+    let r = bc4(&block[0..8]);
+    let g = bc4(&block[8..16]);
+    std::array::from_fn(|i| [r[i], g[i], 0, 255])
+}
+
+struct Bc7Mode {
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index2_bits: u32,
+}
+
+#[allow(clippy::too_many_arguments)]
+const fn bc7_mode(
+    subsets: usize,
+    partition_bits: u32,
+    rotation_bits: u32,
+    index_selection_bits: u32,
+    color_bits: u32,
+    alpha_bits: u32,
+    endpoint_pbits: bool,
+    shared_pbits: bool,
+    index_bits: u32,
+    index2_bits: u32,
+) -> Bc7Mode {
+    Bc7Mode {
+        subsets,
+        partition_bits,
+        rotation_bits,
+        index_selection_bits,
+        color_bits,
+        alpha_bits,
+        endpoint_pbits,
+        shared_pbits,
+        index_bits,
+        index2_bits,
+    }
+}
+
+#[rustfmt::skip]
+const BC7_MODES: [Bc7Mode; 8] = [
+    bc7_mode(3, 4, 0, 0, 4, 0, true, false, 3, 0),
+    bc7_mode(2, 6, 0, 0, 6, 0, false, true, 3, 0),
+    bc7_mode(3, 6, 0, 0, 5, 0, false, false, 2, 0),
+    bc7_mode(2, 6, 0, 0, 7, 0, true, false, 2, 0),
+    bc7_mode(1, 0, 2, 1, 5, 6, false, false, 2, 3),
+    bc7_mode(1, 0, 2, 0, 7, 8, false, false, 2, 2),
+    bc7_mode(1, 0, 0, 0, 7, 7, true, false, 4, 0),
+    bc7_mode(2, 6, 0, 0, 5, 5, true, false, 2, 0),
+];
+
+// bit i is the subset of pixel i
+#[rustfmt::skip]
+const BC7_PARTITIONS2: [u16; 64] = [
+    0xcccc, 0x8888, 0xeeee, 0xecc8, 0xc880, 0xfeec, 0xfec8, 0xec80,
+    0xc800, 0xffec, 0xfe80, 0xe800, 0xffe8, 0xff00, 0xfff0, 0xf000,
+    0xf710, 0x008e, 0x7100, 0x08ce, 0x008c, 0x7310, 0x3100, 0x8cce,
+    0x088c, 0x3110, 0x6666, 0x366c, 0x17e8, 0x0ff0, 0x718e, 0x399c,
+    0xaaaa, 0xf0f0, 0x5a5a, 0x33cc, 0x3c3c, 0x55aa, 0x9696, 0xa55a,
+    0x73ce, 0x13c8, 0x324c, 0x3bdc, 0x6996, 0xc33c, 0x9966, 0x0660,
+    0x0272, 0x04e4, 0x4e40, 0x2720, 0xc936, 0x936c, 0x39c6, 0x639c,
+    0x9336, 0x9cc6, 0x817e, 0xe718, 0xccf0, 0x0fcc, 0x7744, 0xee22,
+];
+
+#[rustfmt::skip]
+const BC7_PARTITIONS3: [[u8; 16]; 64] = [
+    [0,0,1,1,0,0,1,1,0,2,2,1,2,2,2,2], [0,0,0,1,0,0,1,1,2,2,1,1,2,2,2,1],
+    [0,0,0,0,2,0,0,1,2,2,1,1,2,2,1,1], [0,2,2,2,0,0,2,2,0,0,1,1,0,1,1,1],
+    [0,0,0,0,0,0,0,0,1,1,2,2,1,1,2,2], [0,0,1,1,0,0,1,1,0,0,2,2,0,0,2,2],
+    [0,0,2,2,0,0,2,2,1,1,1,1,1,1,1,1], [0,0,1,1,0,0,1,1,2,2,1,1,2,2,1,1],
+    [0,0,0,0,0,0,0,0,1,1,1,1,2,2,2,2], [0,0,0,0,1,1,1,1,1,1,1,1,2,2,2,2],
+    [0,0,0,0,1,1,1,1,2,2,2,2,2,2,2,2], [0,0,1,2,0,0,1,2,0,0,1,2,0,0,1,2],
+    [0,1,1,2,0,1,1,2,0,1,1,2,0,1,1,2], [0,1,2,2,0,1,2,2,0,1,2,2,0,1,2,2],
+    [0,0,1,1,0,1,1,2,1,1,2,2,1,2,2,2], [0,0,1,1,2,0,0,1,2,2,0,0,2,2,2,0],
+    [0,0,0,1,0,0,1,1,0,1,1,2,1,1,2,2], [0,1,1,1,0,0,1,1,2,0,0,1,2,2,0,0],
+    [0,0,0,0,1,1,2,2,1,1,2,2,1,1,2,2], [0,0,2,2,0,0,2,2,0,0,2,2,1,1,1,1],
+    [0,1,1,1,0,1,1,1,0,2,2,2,0,2,2,2], [0,0,0,1,0,0,0,1,2,2,2,1,2,2,2,1],
+    [0,0,0,0,0,0,1,1,0,1,2,2,0,1,2,2], [0,0,0,0,1,1,0,0,2,2,1,0,2,2,1,0],
+    [0,1,2,2,0,1,2,2,0,0,1,1,0,0,0,0], [0,0,1,2,0,0,1,2,1,1,2,2,2,2,2,2],
+    [0,1,1,0,1,2,2,1,1,2,2,1,0,1,1,0], [0,0,0,0,0,1,1,0,1,2,2,1,1,2,2,1],
+    [0,0,2,2,1,1,0,2,1,1,0,2,0,0,2,2], [0,1,1,0,0,1,1,0,2,0,0,2,2,2,2,2],
+    [0,0,1,1,0,1,2,2,0,1,2,2,0,0,1,1], [0,0,0,0,2,0,0,0,2,2,1,1,2,2,2,1],
+    [0,0,0,0,0,0,0,2,1,1,2,2,1,2,2,2], [0,2,2,2,0,0,2,2,0,0,1,2,0,0,1,1],
+    [0,0,1,1,0,0,1,2,0,0,2,2,0,2,2,2], [0,1,2,0,0,1,2,0,0,1,2,0,0,1,2,0],
+    [0,0,0,0,1,1,1,1,2,2,2,2,0,0,0,0], [0,1,2,0,1,2,0,1,2,0,1,2,0,1,2,0],
+    [0,1,2,0,2,0,1,2,1,2,0,1,0,1,2,0], [0,0,1,1,2,2,0,0,1,1,2,2,0,0,1,1],
+    [0,0,1,1,1,1,2,2,2,2,0,0,0,0,1,1], [0,1,0,1,0,1,0,1,2,2,2,2,2,2,2,2],
+    [0,0,0,0,0,0,0,0,2,1,2,1,2,1,2,1], [0,0,2,2,1,1,2,2,0,0,2,2,1,1,2,2],
+    [0,0,2,2,0,0,1,1,0,0,2,2,0,0,1,1], [0,2,2,0,1,2,2,1,0,2,2,0,1,2,2,1],
+    [0,1,0,1,2,2,2,2,2,2,2,2,0,1,0,1], [0,0,0,0,2,1,2,1,2,1,2,1,2,1,2,1],
+    [0,1,0,1,0,1,0,1,0,1,0,1,2,2,2,2], [0,2,2,2,0,1,1,1,0,2,2,2,0,1,1,1],
+    [0,0,0,2,1,1,1,2,0,0,0,2,1,1,1,2], [0,0,0,0,2,1,1,2,2,1,1,2,2,1,1,2],
+    [0,2,2,2,0,1,1,1,0,1,1,1,0,2,2,2], [0,0,0,2,1,1,1,2,1,1,1,2,0,0,0,2],
+    [0,1,1,0,0,1,1,0,0,1,1,0,2,2,2,2], [0,0,0,0,0,0,0,0,2,1,1,2,2,1,1,2],
+    [0,1,1,0,0,1,1,0,2,2,2,2,2,2,2,2], [0,0,2,2,0,0,1,1,0,0,1,1,0,0,2,2],
+    [0,0,2,2,1,1,2,2,1,1,2,2,0,0,2,2], [0,0,0,0,0,0,0,0,0,0,0,0,2,1,1,2],
+    [0,0,0,2,0,0,0,1,0,0,0,2,0,0,0,1], [0,2,2,2,1,2,2,2,0,2,2,2,1,2,2,2],
+    [0,1,0,1,2,2,2,2,2,2,2,2,2,2,2,2], [0,1,1,1,2,0,1,1,2,2,0,1,2,2,2,0],
+];
+
+// anchor pixel of subset 1 with two subsets
+#[rustfmt::skip]
+const BC7_ANCHORS2: [u8; 64] = [
+    15,15,15,15,15,15,15,15, 15,15,15,15,15,15,15,15,
+    15, 2, 8, 2, 2, 8, 8,15,  2, 8, 2, 2, 8, 8, 2, 2,
+    15,15, 6, 8, 2, 8,15,15,  2, 8, 2, 2, 2,15,15, 6,
+     6, 2, 6, 8,15,15, 2, 2, 15,15,15,15,15, 2, 2,15,
+];
+
+// anchor pixels of subsets 1 and 2 with three subsets
+#[rustfmt::skip]
+const BC7_ANCHORS3: [[u8; 2]; 64] = [
+    [3,15], [3,8], [15,8], [15,3], [8,15], [3,15], [15,3], [15,8],
+    [8,15], [8,15], [6,15], [6,15], [6,15], [5,15], [3,15], [3,8],
+    [3,15], [3,8], [8,15], [15,3], [3,15], [3,8], [6,15], [10,8],
+    [5,3], [8,15], [8,6], [6,10], [8,15], [5,15], [15,10], [15,8],
+    [8,15], [15,3], [3,15], [5,10], [6,10], [10,8], [8,9], [15,10],
+    [15,6], [3,15], [15,8], [5,15], [15,3], [15,6], [15,6], [15,8],
+    [3,15], [15,3], [5,15], [5,15], [5,15], [8,15], [5,15], [10,15],
+    [5,15], [10,15], [8,15], [13,15], [15,3], [12,15], [3,15], [3,8],
+];
+
+const BC7_WEIGHTS2: [u16; 4] = [0, 21, 43, 64];
+const BC7_WEIGHTS3: [u16; 8] = [0, 9, 18, 27, 37, 46, 55, 64];
+const BC7_WEIGHTS4: [u16; 16] = [0, 4, 9, 13, 17, 21, 26, 30, 34, 38, 43, 47, 51, 55, 60, 64];
+
+fn bc7_subset(subsets: usize, partition: usize, pixel: usize) -> usize {
+    match subsets {
+        1 => 0,
+        2 => (BC7_PARTITIONS2[partition] >> pixel & 1) as usize,
+        _ => BC7_PARTITIONS3[partition][pixel] as usize,
+    }
+}
+
+fn bc7_is_anchor(subsets: usize, partition: usize, pixel: usize) -> bool {
+    pixel == 0
+        || match subsets {
+            2 => BC7_ANCHORS2[partition] as usize == pixel,
+            3 => BC7_ANCHORS3[partition].contains(&(pixel as u8)),
+            _ => false,
+        }
+}
+
+pub(crate) fn bc7_interpolate(e0: u8, e1: u8, index_bits: u32, index: u32) -> u8 {
+    let w = match index_bits {
+        2 => BC7_WEIGHTS2[index as usize],
+        3 => BC7_WEIGHTS3[index as usize],
+        _ => BC7_WEIGHTS4[index as usize],
+    };
+    (((64 - w) * u16::from(e0) + w * u16::from(e1) + 32) >> 6) as u8
+}
+
+pub(crate) fn bc7(block: &[u8]) -> [[u8; 4]; 16] {
+    let bits = u128::from_le_bytes(block.try_into().unwrap());
+    let mut pos = 0;
+    let mut take = |n: u32| {
+        let v = (bits >> pos) as u32 & ((1u64 << n) - 1) as u32;
+        pos += n;
+        v
+    };
+
+    let mode_idx = block[0].trailing_zeros();
+    if mode_idx >= 8 {
+        // reserved mode
+        return [[0; 4]; 16];
+    }
+    take(mode_idx + 1);
+    let mode = &BC7_MODES[mode_idx as usize];
+    let partition = take(mode.partition_bits) as usize;
+    let rotation = take(mode.rotation_bits);
+    let index_selection = take(mode.index_selection_bits);
+
+    let endpoint_count = mode.subsets * 2;
+    let mut endpoints = [[0u32; 4]; 6];
+    for c in 0..3 {
+        for e in endpoints.iter_mut().take(endpoint_count) {
+            e[c] = take(mode.color_bits);
+        }
+    }
+    if mode.alpha_bits > 0 {
+        for e in endpoints.iter_mut().take(endpoint_count) {
+            e[3] = take(mode.alpha_bits);
+        }
+    }
+    let pbit = u32::from(mode.endpoint_pbits || mode.shared_pbits);
+    if mode.endpoint_pbits {
+        for e in endpoints.iter_mut().take(endpoint_count) {
+            let p = take(1);
+            e.iter_mut().for_each(|c| *c = *c << 1 | p);
+        }
+    } else if mode.shared_pbits {
+        for pair in endpoints.chunks_exact_mut(2).take(mode.subsets) {
+            let p = take(1);
+            pair.iter_mut().flatten().for_each(|c| *c = *c << 1 | p);
+        }
+    }
+
+    let expand = |v: u32, precision: u32| {
+        let v = v << (8 - precision);
+        (v | v >> precision) as u8
+    };
+    let endpoints: [[u8; 4]; 6] = std::array::from_fn(|e| {
+        let c = endpoints[e];
+        [
+            expand(c[0], mode.color_bits + pbit),
+            expand(c[1], mode.color_bits + pbit),
+            expand(c[2], mode.color_bits + pbit),
+            if mode.alpha_bits > 0 {
+                expand(c[3], mode.alpha_bits + pbit)
+            } else {
+                255
+            },
+        ]
+    });
+
+    // anchor pixels store their index without the most significant bit
+    let mut indices = [0; 16];
+    for (i, index) in indices.iter_mut().enumerate() {
+        let anchor = bc7_is_anchor(mode.subsets, partition, i);
+        *index = take(mode.index_bits - u32::from(anchor));
+    }
+    let mut indices2 = [0; 16];
+    if mode.index2_bits > 0 {
+        for (i, index) in indices2.iter_mut().enumerate() {
+            *index = take(mode.index2_bits - u32::from(i == 0));
+        }
+    }
+
+    let (color, alpha) = match (mode.index2_bits, index_selection) {
+        (0, _) => ((&indices, mode.index_bits), (&indices, mode.index_bits)),
+        (_, 0) => ((&indices, mode.index_bits), (&indices2, mode.index2_bits)),
+        _ => ((&indices2, mode.index2_bits), (&indices, mode.index_bits)),
+    };
+
+    std::array::from_fn(|i| {
+        let s = bc7_subset(mode.subsets, partition, i);
+        let (e0, e1) = (endpoints[2 * s], endpoints[2 * s + 1]);
+        let mut px = [0; 4];
+        for c in 0..3 {
+            px[c] = bc7_interpolate(e0[c], e1[c], color.1, color.0[i]);
+        }
+        px[3] = bc7_interpolate(e0[3], e1[3], alpha.1, alpha.0[i]);
+        if rotation > 0 {
+            px.swap(rotation as usize - 1, 3);
+        }
+        px
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bc7_anchors_lie_in_their_subset() {
+        for p in 0..64 {
+            assert_eq!(bc7_subset(2, p, BC7_ANCHORS2[p] as usize), 1, "{}", p);
+            assert_eq!(bc7_subset(3, p, BC7_ANCHORS3[p][0] as usize), 1, "{}", p);
+            assert_eq!(bc7_subset(3, p, BC7_ANCHORS3[p][1] as usize), 2, "{}", p);
+        }
+    }
+
+    #[test]
+    fn bc7_mode_bits_fill_block() {
+        for (m, mode) in BC7_MODES.iter().enumerate() {
+            let endpoints = mode.subsets as u32 * 2;
+            let pbits = if mode.endpoint_pbits {
+                endpoints
+            } else if mode.shared_pbits {
+                mode.subsets as u32
+            } else {
+                0
+            };
+            let index2 = if mode.index2_bits > 0 {
+                16 * mode.index2_bits - 1
+            } else {
+                0
+            };
+            let total = m as u32
+                + 1
+                + mode.partition_bits
+                + mode.rotation_bits
+                + mode.index_selection_bits
+                + endpoints * (3 * mode.color_bits + mode.alpha_bits)
+                + pbits
+                + 16 * mode.index_bits
+                - mode.subsets as u32
+                + index2;
+            assert_eq!(total, 128, "mode {}", m);
+        }
+    }
+
+    #[test]
+    fn bc1_three_color_mode() {
+        // c0 = black < c1 = white: index 2 is the average, index 3 transparent
+        let block = [0, 0, 0xff, 0xff, 0b1110_0100, 0, 0, 0];
+        let px = bc1(&block, false);
+        assert_eq!(px[0], [0, 0, 0, 255]);
+        assert_eq!(px[1], [255, 255, 255, 255]);
+        assert_eq!(px[2], [127, 127, 127, 255]);
+        assert_eq!(px[3], [0, 0, 0, 0]);
+        assert_eq!(bc1(&block, true)[3], [170, 170, 170, 255]);
+    }
+
+    #[test]
+    fn bc4_palettes() {
+        // indices 1, 2 and 7
+        let block = [200, 100, 0b1101_0001, 0b0000_0011, 0, 0, 0, 0];
+        assert_eq!(bc4(&block)[..3], [100, 185, 114]);
+        // six value palette: indices 7, 7 and 3
+        let block = [100, 200, 0b1111_1111, 0, 0, 0, 0, 0];
+        assert_eq!(bc4(&block)[..3], [255, 255, 140]);
+    }
+
+    #[test]
+    fn uncompressed_masks() {
+        let bgra = decode_uncompressed(&[1, 2, 3, 4], 4, [0xff_0000, 0xff00, 0xff, 0], false);
+        assert_eq!(bgra, [3, 2, 1, 255]);
+        // R5G6B5
+        let rgb565 = decode_uncompressed(&[0x1f, 0xf8], 2, [0xf800, 0x7e0, 0x1f, 0], false);
+        assert_eq!(rgb565, [255, 0, 255, 255]);
+        let gray = decode_uncompressed(&[7], 1, [0xff, 0, 0, 0], true);
+        assert_eq!(gray, [7, 7, 7, 255]);
+    }
+
+    #[test]
+    fn mip_size_beyond_32_levels() {
+        let dds = Dds {
+            width: 256,
+            height: 64,
+            mip_count: 40,
+            format: DdsFormat::Bc1,
+            data: Vec::new(),
+        };
+        assert_eq!(dds.mip_size(7), (2, 1));
+        assert_eq!(dds.mip_size(35), (1, 1));
+    }
+}
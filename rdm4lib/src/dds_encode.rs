@@ -0,0 +1,495 @@
+use std::{fs, path::Path, str::FromStr};
+
+use crate::dds::{self, DdsError, RgbaImage};
+
+/// Block compression used for textures written by the glTF import.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BcEncoding {
+    /// RGB with 1 bit alpha, 4 bits per pixel.
+    Bc1,
+    /// RGB and interpolated alpha, 8 bits per pixel.
+    Bc3,
+    /// RGBA, 8 bits per pixel. Written as DXGI_FORMAT_BC7_UNORM like the textures of the game.
+    Bc7,
+}
+
+impl FromStr for BcEncoding {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "bc1" | "dxt1" => Ok(BcEncoding::Bc1),
+            "bc3" | "dxt5" => Ok(BcEncoding::Bc3),
+            "bc7" => Ok(BcEncoding::Bc7),
+            _ => Err(format!(
+                "Invalid value for BcEncoding: {}, Only bc1/bc3/bc7 are allowed value",
+                input
+            )),
+        }
+    }
+}
+
+impl BcEncoding {
+    fn block_len(&self) -> usize {
+        match self {
+            BcEncoding::Bc1 => 8,
+            BcEncoding::Bc3 | BcEncoding::Bc7 => 16,
+        }
+    }
+}
+
+const DDSD_CAPS: u32 = 0x1;
+const DDSD_HEIGHT: u32 = 0x2;
+const DDSD_WIDTH: u32 = 0x4;
+const DDSD_PIXELFORMAT: u32 = 0x1000;
+const DDSD_MIPMAPCOUNT: u32 = 0x2_0000;
+const DDSD_LINEARSIZE: u32 = 0x8_0000;
+const DDPF_FOURCC: u32 = 0x4;
+const DDSCAPS_COMPLEX: u32 = 0x8;
+const DDSCAPS_TEXTURE: u32 = 0x1000;
+const DDSCAPS_MIPMAP: u32 = 0x40_0000;
+const DXGI_FORMAT_BC7_UNORM: u32 = 98;
+const D3D10_RESOURCE_DIMENSION_TEXTURE2D: u32 = 3;
+
+/// `image` followed by box filtered mipmaps down to 1x1.
+pub fn mip_chain(image: &RgbaImage) -> Vec<RgbaImage> {
+    let mut chain = vec![image.clone()];
+    while let Some(last) = chain.last().filter(|m| m.width > 1 || m.height > 1) {
+        let width = (last.width / 2).max(1);
+        let height = (last.height / 2).max(1);
+        let src = |x: u32, y: u32, c: usize| {
+            let (x, y) = (x.min(last.width - 1), y.min(last.height - 1));
+            u32::from(last.pixels[((y * last.width + x) * 4) as usize + c])
+        };
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for y in 0..height {
+            for x in 0..width {
+                for c in 0..4 {
+                    let sum = src(2 * x, 2 * y, c)
+                        + src(2 * x + 1, 2 * y, c)
+                        + src(2 * x, 2 * y + 1, c)
+                        + src(2 * x + 1, 2 * y + 1, c);
+                    pixels.push(((sum + 2) / 4) as u8);
+                }
+            }
+        }
+        chain.push(RgbaImage {
+            width,
+            height,
+            pixels,
+        });
+    }
+    chain
+}
+
+/// DDS file with a full mip chain of `image`.
+pub fn encode_dds(image: &RgbaImage, encoding: BcEncoding) -> Vec<u8> {
+    let chain = mip_chain(image);
+    let surface_len = |m: &RgbaImage| {
+        m.width.div_ceil(4) as usize * m.height.div_ceil(4) as usize * encoding.block_len()
+    };
+
+    let mut header = [0u32; 31];
+    header[0] = 124;
+    header[1] = DDSD_CAPS
+        | DDSD_HEIGHT
+        | DDSD_WIDTH
+        | DDSD_PIXELFORMAT
+        | DDSD_MIPMAPCOUNT
+        | DDSD_LINEARSIZE;
+    header[2] = image.height;
+    header[3] = image.width;
+    header[4] = surface_len(image) as u32;
+    header[6] = chain.len() as u32;
+    // DDS_PIXELFORMAT
+    header[18] = 32;
+    header[19] = DDPF_FOURCC;
+    header[20] = u32::from_le_bytes(*match encoding {
+        BcEncoding::Bc1 => b"DXT1",
+        BcEncoding::Bc3 => b"DXT5",
+        BcEncoding::Bc7 => b"DX10",
+    });
+    header[26] = DDSCAPS_TEXTURE | DDSCAPS_MIPMAP | DDSCAPS_COMPLEX;
+
+    let mut out = Vec::with_capacity(148 + chain.iter().map(surface_len).sum::<usize>() * 4 / 3);
+    out.extend_from_slice(b"DDS ");
+    out.extend(header.iter().flat_map(|v| v.to_le_bytes()));
+    if encoding == BcEncoding::Bc7 {
+        let dx10 = [
+            DXGI_FORMAT_BC7_UNORM,
+            D3D10_RESOURCE_DIMENSION_TEXTURE2D,
+            0,
+            1,
+            0,
+        ];
+        out.extend(dx10.iter().flat_map(|v| v.to_le_bytes()));
+    }
+    for mip in chain.iter() {
+        encode_blocks(mip, encoding, &mut out);
+    }
+    out
+}
+
+pub fn write_dds(path: &Path, image: &RgbaImage, encoding: BcEncoding) -> Result<(), DdsError> {
+    fs::write(path, encode_dds(image, encoding))?;
+    Ok(())
+}
+
+fn encode_blocks(image: &RgbaImage, encoding: BcEncoding, out: &mut Vec<u8>) {
+    for by in (0..image.height).step_by(4) {
+        for bx in (0..image.width).step_by(4) {
+            // partial blocks repeat the edge pixels
+            let block: [[u8; 4]; 16] = std::array::from_fn(|i| {
+                let x = (bx + i as u32 % 4).min(image.width - 1);
+                let y = (by + i as u32 / 4).min(image.height - 1);
+                let o = ((y * image.width + x) * 4) as usize;
+                image.pixels[o..o + 4].try_into().unwrap()
+            });
+            match encoding {
+                BcEncoding::Bc1 => out.extend_from_slice(&bc1_block(&block, false)),
+                BcEncoding::Bc3 => {
+                    out.extend_from_slice(&bc4_block(&block.map(|px| px[3])));
+                    out.extend_from_slice(&bc1_block(&block, true));
+                }
+                BcEncoding::Bc7 => out.extend_from_slice(&bc7_block(&block)),
+            }
+        }
+    }
+}
+
+/// End points of the principal axis through `points`.
+fn principal_endpoints<const N: usize>(points: &[[f32; N]]) -> ([f32; N], [f32; N]) {
+    let n = points.len() as f32;
+    let mut mean = [0.0; N];
+    for p in points {
+        for c in 0..N {
+            mean[c] += p[c] / n;
+        }
+    }
+    let mut cov = [[0.0f32; N]; N];
+    for p in points {
+        for i in 0..N {
+            for j in 0..N {
+                cov[i][j] += (p[i] - mean[i]) * (p[j] - mean[j]);
+            }
+        }
+    }
+    // power iteration
+    let mut axis = [1.0; N];
+    for _ in 0..8 {
+        let mut next = [0.0; N];
+        for i in 0..N {
+            for j in 0..N {
+                next[i] += cov[i][j] * axis[j];
+            }
+        }
+        let len = next.iter().map(|v| v * v).sum::<f32>().sqrt();
+        if len < 1.0e-6 {
+            return (mean, mean);
+        }
+        axis = next.map(|v| v / len);
+    }
+    let project = |p: &[f32; N]| (0..N).map(|c| (p[c] - mean[c]) * axis[c]).sum::<f32>();
+    let (min, max) = points
+        .iter()
+        .map(project)
+        .fold((f32::MAX, f32::MIN), |(lo, hi), t| (lo.min(t), hi.max(t)));
+    let at = |t: f32| std::array::from_fn(|c| (mean[c] + t * axis[c]).clamp(0.0, 255.0));
+    (at(min), at(max))
+}
+
+fn nearest<const N: usize>(palette: &[[u8; N]], px: &[u8; N], channels: usize) -> usize {
+    let error = |p: &[u8; N]| {
+        (0..channels)
+            .map(|c| (i32::from(p[c]) - i32::from(px[c])).pow(2))
+            .sum::<i32>()
+    };
+    (0..palette.len())
+        .min_by_key(|&i| error(&palette[i]))
+        .unwrap()
+}
+
+fn to_rgb565(c: [f32; 3]) -> u16 {
+    let r = (c[0] * 31.0 / 255.0).round() as u16;
+    let g = (c[1] * 63.0 / 255.0).round() as u16;
+    let b = (c[2] * 31.0 / 255.0).round() as u16;
+    r << 11 | g << 5 | b
+}
+
+/// Color block of BC1, or of BC3 with `four_colors`. Without `four_colors` pixels with alpha below 128 become transparent.
+fn bc1_block(px: &[[u8; 4]; 16], four_colors: bool) -> [u8; 8] {
+    let transparent = |p: &[u8; 4]| !four_colors && p[3] < 128;
+    let has_transparent = px.iter().any(transparent);
+    let points: Vec<[f32; 3]> = px
+        .iter()
+        .filter(|p| !transparent(p))
+        .map(|p| [p[0], p[1], p[2]].map(f32::from))
+        .collect();
+
+    let (mut c0, mut c1) = if points.is_empty() {
+        (0, 0)
+    } else {
+        let (lo, hi) = principal_endpoints(&points);
+        (to_rgb565(hi), to_rgb565(lo))
+    };
+    // c0 > c1 selects four colors, c0 <= c1 three colors and transparent black
+    if has_transparent == (c0 > c1) {
+        std::mem::swap(&mut c0, &mut c1);
+    }
+    let palette = dds::bc1_palette(c0, c1, four_colors);
+    let three_colors = !four_colors && c0 <= c1;
+
+    let mut indices = 0u32;
+    for (i, p) in px.iter().enumerate() {
+        let index = if transparent(p) {
+            3
+        } else if three_colors {
+            nearest(&palette[..3], p, 3)
+        } else {
+            nearest(&palette, p, 3)
+        };
+        indices |= (index as u32) << (2 * i);
+    }
+    let mut block = [0; 8];
+    block[0..2].copy_from_slice(&c0.to_le_bytes());
+    block[2..4].copy_from_slice(&c1.to_le_bytes());
+    block[4..8].copy_from_slice(&indices.to_le_bytes());
+    block
+}
+
+/// Single channel block with eight interpolated values.
+fn bc4_block(values: &[u8; 16]) -> [u8; 8] {
+    let a0 = *values.iter().max().unwrap();
+    let a1 = *values.iter().min().unwrap();
+    let palette = dds::bc4_palette(a0, a1).map(|v| [v]);
+    let mut indices = 0u64;
+    for (i, v) in values.iter().enumerate() {
+        indices |= (nearest(&palette, &[*v], 1) as u64) << (3 * i);
+    }
+    let mut block = [0; 8];
+    block[0] = a0;
+    block[1] = a1;
+    block[2..8].copy_from_slice(&indices.to_le_bytes()[..6]);
+    block
+}
+
+/// Bit writer for BC7 blocks, least significant bit first.
+struct BlockBits {
+    bits: u128,
+    pos: u32,
+}
+
+impl BlockBits {
+    fn new(mode: u32) -> Self {
+        let mut b = BlockBits { bits: 0, pos: 0 };
+        b.put(1 << mode, mode + 1);
+        b
+    }
+
+    fn put(&mut self, v: u32, n: u32) {
+        self.bits |= u128::from(v) << self.pos;
+        self.pos += n;
+    }
+
+    fn finish(self) -> [u8; 16] {
+        assert_eq!(self.pos, 128);
+        self.bits.to_le_bytes()
+    }
+}
+
+/// The BC7 mode 6 or mode 5 block closest to `px`.
+fn bc7_block(px: &[[u8; 4]; 16]) -> [u8; 16] {
+    let error = |block: &[u8; 16]| {
+        dds::bc7(block)
+            .iter()
+            .zip(px)
+            .flat_map(|(a, b)| (0..4).map(|c| (i32::from(a[c]) - i32::from(b[c])).pow(2)))
+            .sum::<i32>()
+    };
+    [bc7_mode6(px), bc7_mode5(px)]
+        .into_iter()
+        .min_by_key(error)
+        .unwrap()
+}
+
+/// BC7 mode 5: 7 bit RGB and 8 bit alpha end points with separate 2 bit indices.
+fn bc7_mode5(px: &[[u8; 4]; 16]) -> [u8; 16] {
+    let points: Vec<[f32; 3]> = px
+        .iter()
+        .map(|p| [p[0], p[1], p[2]].map(f32::from))
+        .collect();
+    let (lo, hi) = principal_endpoints(&points);
+    let mut color_ends = [lo, hi].map(|e| e.map(|v| (v / 2.0).round().min(127.0) as u32));
+    let expand = |q: [u32; 3]| q.map(|v| (v << 1 | v >> 6) as u8);
+    let color_palette: [[u8; 3]; 4] = {
+        let (e0, e1) = (expand(color_ends[0]), expand(color_ends[1]));
+        std::array::from_fn(|i| {
+            std::array::from_fn(|c| dds::bc7_interpolate(e0[c], e1[c], 2, i as u32))
+        })
+    };
+    let mut color = px.map(|p| nearest(&color_palette, &[p[0], p[1], p[2]], 3) as u32);
+
+    let mut alpha_ends = [
+        px.iter().map(|p| p[3]).min().unwrap(),
+        px.iter().map(|p| p[3]).max().unwrap(),
+    ];
+    let alpha_palette: [[u8; 1]; 4] = std::array::from_fn(|i| {
+        [dds::bc7_interpolate(
+            alpha_ends[0],
+            alpha_ends[1],
+            2,
+            i as u32,
+        )]
+    });
+    let mut alpha = px.map(|p| nearest(&alpha_palette, &[p[3]], 1) as u32);
+
+    // the anchor indices have no most significant bit
+    if color[0] >= 2 {
+        color_ends.swap(0, 1);
+        color = color.map(|i| 3 - i);
+    }
+    if alpha[0] >= 2 {
+        alpha_ends.swap(0, 1);
+        alpha = alpha.map(|i| 3 - i);
+    }
+
+    let mut bits = BlockBits::new(5);
+    // no rotation
+    bits.put(0, 2);
+    for (e0, e1) in color_ends[0].iter().zip(&color_ends[1]) {
+        bits.put(*e0, 7);
+        bits.put(*e1, 7);
+    }
+    bits.put(u32::from(alpha_ends[0]), 8);
+    bits.put(u32::from(alpha_ends[1]), 8);
+    for indices in [color, alpha] {
+        bits.put(indices[0], 1);
+        for index in &indices[1..] {
+            bits.put(*index, 2);
+        }
+    }
+    bits.finish()
+}
+
+/// BC7 mode 6: one subset, 7 bit RGBA end points with a p-bit each, 4 bit indices.
+fn bc7_mode6(px: &[[u8; 4]; 16]) -> [u8; 16] {
+    let points: Vec<[f32; 4]> = px.iter().map(|p| p.map(f32::from)).collect();
+    let (lo, hi) = principal_endpoints(&points);
+
+    // 7 bit values and the p-bit with the lowest error
+    let quantize = |e: [f32; 4]| {
+        (0..2u32)
+            .map(|p| {
+                let q = e.map(|v| ((v - p as f32) / 2.0).round().clamp(0.0, 127.0) as u32);
+                let error: f32 = (0..4)
+                    .map(|c| ((q[c] << 1 | p) as f32 - e[c]).powi(2))
+                    .sum();
+                (q, p, error)
+            })
+            .min_by(|a, b| a.2.total_cmp(&b.2))
+            .map(|(q, p, _)| (q, p))
+            .unwrap()
+    };
+    let mut ends = [quantize(lo), quantize(hi)];
+    let color = |(q, p): ([u32; 4], u32)| q.map(|v| (v << 1 | p) as u8);
+
+    let palette: [[u8; 4]; 16] = {
+        let (e0, e1) = (color(ends[0]), color(ends[1]));
+        std::array::from_fn(|i| {
+            std::array::from_fn(|c| dds::bc7_interpolate(e0[c], e1[c], 4, i as u32))
+        })
+    };
+    let mut indices = px.map(|p| nearest(&palette, &p, 4) as u32);
+    // the anchor index has no most significant bit
+    if indices[0] >= 8 {
+        ends.swap(0, 1);
+        indices = indices.map(|i| 15 - i);
+    }
+
+    let mut bits = BlockBits::new(6);
+    for c in 0..4 {
+        bits.put(ends[0].0[c], 7);
+        bits.put(ends[1].0[c], 7);
+    }
+    bits.put(ends[0].1, 1);
+    bits.put(ends[1].1, 1);
+    bits.put(indices[0], 3);
+    for index in &indices[1..] {
+        bits.put(*index, 4);
+    }
+    bits.finish()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dds::Dds;
+
+    // the right half is transparent if `cutout`
+    fn gradient(width: u32, height: u32, cutout: bool) -> RgbaImage {
+        let mut pixels = Vec::new();
+        for y in 0..height {
+            for x in 0..width {
+                pixels.extend_from_slice(&[
+                    (x * 255 / width) as u8,
+                    (y * 255 / height) as u8,
+                    128,
+                    if cutout && x >= width / 2 { 0 } else { 255 },
+                ]);
+            }
+        }
+        RgbaImage {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    fn max_error(a: &RgbaImage, b: &RgbaImage) -> u8 {
+        a.pixels
+            .iter()
+            .zip(b.pixels.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn mip_chain_sizes() {
+        let chain = mip_chain(&gradient(6, 3, false));
+        let sizes: Vec<_> = chain.iter().map(|m| (m.width, m.height)).collect();
+        assert_eq!(sizes, [(6, 3), (3, 1), (1, 1)]);
+    }
+
+    #[test]
+    fn encode_decode_round_trip() {
+        // a 2D gradient inside each block: no single end point line fits exactly
+        for (encoding, cutout, tolerance) in [
+            (BcEncoding::Bc1, false, 24),
+            (BcEncoding::Bc3, true, 24),
+            (BcEncoding::Bc7, true, 24),
+        ] {
+            let image = gradient(20, 12, cutout);
+            let dds = Dds::from_bytes(encode_dds(&image, encoding)).unwrap();
+            assert_eq!(dds.mip_count(), 5);
+            let decoded = dds.decode(0).unwrap();
+            let error = max_error(&image, &decoded);
+            assert!(error <= tolerance, "{:?}: {}", encoding, error);
+            assert_eq!(dds.decode(4).unwrap().pixels.len(), 4);
+        }
+    }
+
+    #[test]
+    fn bc1_transparent_pixels() {
+        let image = gradient(8, 4, true);
+        let dds = Dds::from_bytes(encode_dds(&image, BcEncoding::Bc1)).unwrap();
+        let decoded = dds.decode(0).unwrap();
+        for (src, dst) in image
+            .pixels
+            .chunks_exact(4)
+            .zip(decoded.pixels.chunks_exact(4))
+        {
+            assert_eq!(src[3], dst[3]);
+        }
+    }
+}
@@ -1,13 +1,16 @@
+use crate::dds::{self, DdsError};
+use crate::rda;
+use crate::rdm_material::{TextureExport, TextureSet};
+use crate::vertex_transform::UnitConversion;
 use crate::{rdm_data_main::MeshInfo, rdm_material::RdMaterial, vertex::*, RdJoint, RdModell};
 use gltf::json::validation::USize64;
 use gltf::{json, json::validation::Checked::Valid, mesh::Semantic};
 use std::{
     borrow::Cow,
     collections::{BTreeMap, HashMap},
-    env,
-    fs::{self, File, OpenOptions},
-    io::{self, Read, Write},
-    path::PathBuf,
+    fs::{self, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
     str::FromStr,
 };
 
@@ -20,6 +23,92 @@ struct Vertex {
     position: [f32; 3],
 }
 
+const MSFT_TEXTURE_DDS: &str = "MSFT_texture_dds";
+
+/// Where the bytes of a glTF image come from.
+enum ImageSource {
+    /// Decoded from the DDS texture.
+    Png(PathBuf),
+    /// The DDS texture as is.
+    Dds(PathBuf),
+    /// glTF normal map of an Anno normal map.
+    Normal(PathBuf),
+    /// glTF occlusion, roughness and metallic of the metal and height textures.
+    OcclusionRoughnessMetallic(TextureSet),
+}
+
+impl ImageSource {
+    fn file_name(&self) -> String {
+        match self {
+            ImageSource::Png(p) => {
+                format!("{}{}", p.file_stem().unwrap().to_str().unwrap(), ".PNG")
+            }
+            ImageSource::Dds(p) => p.file_name().unwrap().to_str().unwrap().to_string(),
+            ImageSource::Normal(p) => {
+                format!("{}{}", p.file_stem().unwrap().to_str().unwrap(), ".PNG")
+            }
+            ImageSource::OcclusionRoughnessMetallic(set) => {
+                let p = set.metal.as_ref().or(set.height.as_ref()).unwrap();
+                format!("{}{}", p.file_stem().unwrap().to_str().unwrap(), "_orm.PNG")
+            }
+        }
+    }
+
+    fn mime_type(&self) -> &'static str {
+        match self {
+            ImageSource::Dds(_) => "image/vnd-ms.dds",
+            _ => "image/png",
+        }
+    }
+
+    /// Opens the DDS textures the image is made of.
+    fn probe(&self) -> Result<(), DdsError> {
+        let paths: Vec<&PathBuf> = match self {
+            ImageSource::Png(p) | ImageSource::Dds(p) | ImageSource::Normal(p) => vec![p],
+            ImageSource::OcclusionRoughnessMetallic(set) => {
+                set.metal.iter().chain(set.height.iter()).collect()
+            }
+        };
+        for p in paths {
+            dds::Dds::open(p)?;
+        }
+        Ok(())
+    }
+
+    fn bytes(&self, mat: &RdMaterial) -> Result<Vec<u8>, DdsError> {
+        match self {
+            ImageSource::Png(p) => mat.to_png(p),
+            ImageSource::Dds(p) => Ok(rda::read(p)?),
+            ImageSource::Normal(p) => mat.normal_map(p)?.to_png(),
+            ImageSource::OcclusionRoughnessMetallic(set) => {
+                mat.occlusion_roughness_metallic(set)?.unwrap().to_png()
+            }
+        }
+    }
+
+    fn write(&self, mat: &RdMaterial, dir: &Path) -> Result<(), DdsError> {
+        let dst = dir.join(self.file_name());
+        if let ImageSource::Dds(p) = self {
+            // the texture may already be in the output directory
+            if dst.canonicalize().ok() == p.canonicalize().ok() {
+                return Ok(());
+            }
+        }
+        debug!("write {:?}", &dst);
+        fs::write(&dst, self.bytes(mat)?)?;
+        Ok(())
+    }
+}
+
+fn texture_info(index: json::Index<json::Texture>) -> json::texture::Info {
+    json::texture::Info {
+        index,
+        tex_coord: 0,
+        extensions: None,
+        extras: None,
+    }
+}
+
 #[derive(Debug, Eq, Hash, PartialEq)]
 #[allow(dead_code)]
 pub enum JointOption {
@@ -43,7 +132,10 @@ struct RdGltfBuilder {
     material_vec: Vec<json::Material>,
     texture_vec: Vec<json::Texture>,
     image_vec: Vec<json::Image>,
+    image_src: Vec<ImageSource>,
     sampler_vec: Vec<json::texture::Sampler>,
+    extensions_used: Vec<String>,
+    extensions_required: Vec<String>,
 }
 
 impl RdGltfBuilder {
@@ -63,7 +155,10 @@ impl RdGltfBuilder {
             material_vec: vec![],
             texture_vec: vec![],
             image_vec: vec![],
+            image_src: vec![],
             sampler_vec: vec![],
+            extensions_used: vec![],
+            extensions_required: vec![],
         }
     }
 
@@ -666,57 +761,66 @@ impl RdGltfBuilder {
     fn put_material(&mut self) {
         let material_len = MeshInfo::get_max_material(&self.rdm.mesh_info) as usize + 1;
         // get_max_material returns the max value used to index the material vec
-        let mut texture_info_descriptors = vec![None; material_len];
-        if let Some(mats) = self.rdm.mat.as_ref() {
-            for (i, (image_path, dst)) in mats
-                .into_iter()
-                .zip(texture_info_descriptors.iter_mut())
-                .enumerate()
-            {
-                let sampler = Default::default();
-                self.sampler_vec.push(sampler);
-
-                let fname = image_path.file_stem().unwrap().to_str().unwrap();
-                let image = json::Image {
-                    uri: Some(format!("{}{}", fname, ".PNG")),
-                    buffer_view: None,
-                    mime_type: None,
-                    extensions: None,
-                    extras: None,
-                    name: None,
-                };
-                self.image_vec.push(image);
-
-                let texture = json::Texture {
-                    sampler: Some(json::Index::new(i as u32)),
-                    source: json::Index::new(i as u32),
-                    extensions: None,
-                    extras: None,
-                    name: None,
-                };
-                self.texture_vec.push(texture);
-
-                *dst = Some(gltf::json::texture::Info {
-                    index: json::Index::new(i as u32),
-                    tex_coord: 0,
-                    extensions: None,
-                    extras: None,
-                });
+        let mut pbr_vec = vec![json::material::PbrMetallicRoughness::default(); material_len];
+        let mut normal_vec = vec![None; material_len];
+        let mut occlusion_vec = vec![None; material_len];
+        let mut alpha_vec = vec![None; material_len];
+        if let Some(mats) = self.rdm.mat.clone() {
+            for (i, set) in mats.texture_sets().iter().take(material_len).enumerate() {
+                if let Some(index) = set.diff.as_ref().and_then(|d| self.put_texture(d)) {
+                    pbr_vec[i].base_color_texture = Some(texture_info(index));
+                }
+                let norm = set.norm.as_ref();
+                if let Some(index) =
+                    norm.and_then(|n| self.put_png_texture(ImageSource::Normal(n.clone())))
+                {
+                    normal_vec[i] = Some(json::material::NormalTexture {
+                        index,
+                        scale: 1.0,
+                        tex_coord: 0,
+                        extensions: None,
+                        extras: None,
+                    });
+                }
+                // one image: occlusion (R) from height, roughness (G) and metallic (B) from metal
+                let orm = (set.metal.is_some() || set.height.is_some())
+                    .then(|| ImageSource::OcclusionRoughnessMetallic(set.clone()));
+                if let Some(index) = orm.and_then(|src| self.put_png_texture(src)) {
+                    if set.metal.is_some() {
+                        pbr_vec[i].metallic_roughness_texture = Some(texture_info(index));
+                    }
+                    if set.height.is_some() {
+                        occlusion_vec[i] = Some(json::material::OcclusionTexture {
+                            index,
+                            strength: json::material::StrengthFactor(1.0),
+                            tex_coord: 0,
+                            extensions: None,
+                            extras: None,
+                        });
+                    }
+                }
+                alpha_vec[i] = set.alpha_cutoff;
             }
         }
 
         let mut material_idx_vec = Vec::with_capacity(material_len);
         assert!(self.material_vec.is_empty());
-        for itex in texture_info_descriptors {
-            let pbr = json::material::PbrMetallicRoughness {
-                base_color_texture: itex,
-                ..Default::default()
+        for (((pbr, normal_texture), occlusion_texture), cutoff) in pbr_vec
+            .into_iter()
+            .zip(normal_vec)
+            .zip(occlusion_vec)
+            .zip(alpha_vec)
+        {
+            let alpha_mode = match cutoff {
+                Some(_) => json::material::AlphaMode::Mask,
+                None => json::material::AlphaMode::Opaque,
             };
-
             let map = json::Material {
-                alpha_cutoff: None,
-                alpha_mode: Valid(json::material::AlphaMode::Opaque),
+                alpha_cutoff: cutoff.map(json::material::AlphaCutoff),
+                alpha_mode: Valid(alpha_mode),
                 pbr_metallic_roughness: pbr,
+                normal_texture,
+                occlusion_texture,
                 ..Default::default()
             };
 
@@ -728,33 +832,122 @@ impl RdGltfBuilder {
         self.material_idx = Some(material_idx_vec);
     }
 
-    fn run_dds(&mut self, embed_image_buffer: bool) {
-        if let Some(mats) = self.rdm.mat.as_ref() {
-            let dir = env::temp_dir();
-            mats.run_dds_converter(&dir);
-
-            if embed_image_buffer {
-                let tmp_dir = env::temp_dir();
-                for e in self.image_vec.iter_mut() {
-                    let src_dds_file = tmp_dir.join(e.uri.as_ref().unwrap());
-                    let mut f = File::open(src_dds_file).unwrap();
-                    // prealloc 10 mebibytes for the png
-                    let mut buffer = Vec::with_capacity(10 * 1024 * 1024);
-
-                    // read the whole file
-                    f.read_to_end(&mut buffer).unwrap();
-
-                    e.uri = None;
-                    e.mime_type = Some(json::image::MimeType("image/png".to_string()));
-                    let buffer_view_idx = RdGltfBuilder::put_buffer_and_view(
-                        &mut self.obj,
-                        BufferContainer::U8(buffer),
-                        &mut self.buffers,
-                        &mut self.buffer_views,
-                        None,
-                    );
-                    e.buffer_view = Some(json::Index::new(buffer_view_idx));
+    /// Adds a texture with its own sampler for an image decoded to PNG. `None` if a DDS texture of `src` can not
+    /// be read, the material slot is left empty then.
+    fn put_png_texture(&mut self, src: ImageSource) -> Option<json::Index<json::Texture>> {
+        if let Err(e) = src.probe() {
+            warn!("skipping texture {}: {}", src.file_name(), e);
+            return None;
+        }
+        let sampler = Default::default();
+        self.sampler_vec.push(sampler);
+        let texture = json::Texture {
+            sampler: Some(json::Index::new(self.sampler_vec.len() as u32 - 1)),
+            source: self.put_image(src),
+            extensions: None,
+            extras: None,
+            name: None,
+        };
+        self.texture_vec.push(texture);
+        Some(json::Index::new(self.texture_vec.len() as u32 - 1))
+    }
+
+    /// Adds a texture with its own sampler for the DDS texture `image_path`, stored as `self.rdm.mat` requests.
+    /// `None` if the texture is only exported as PNG and can not be read.
+    fn put_texture(&mut self, image_path: &Path) -> Option<json::Index<json::Texture>> {
+        let export = self.rdm.mat.as_ref().unwrap().texture_export;
+        if export == TextureExport::Png {
+            let src = ImageSource::Png(image_path.to_path_buf());
+            if let Err(e) = src.probe() {
+                warn!("skipping texture {}: {}", src.file_name(), e);
+                return None;
+            }
+        }
+        let sampler = Default::default();
+        self.sampler_vec.push(sampler);
+
+        let png_fallback = match export {
+            TextureExport::Png => true,
+            TextureExport::Dds => match dds::Dds::open(image_path) {
+                Ok(_) => true,
+                Err(e) => {
+                    warn!("no PNG fallback for {:?}: {}", image_path, e);
+                    false
                 }
+            },
+            TextureExport::DdsOnly => false,
+        };
+
+        let mut texture = json::Texture {
+            sampler: Some(json::Index::new(self.sampler_vec.len() as u32 - 1)),
+            // u32::MAX is not serialized, MSFT_texture_dds provides the only source
+            source: json::Index::new(u32::MAX),
+            extensions: None,
+            extras: None,
+            name: None,
+        };
+        if png_fallback {
+            texture.source = self.put_image(ImageSource::Png(image_path.to_path_buf()));
+        } else if !self
+            .extensions_required
+            .iter()
+            .any(|e| e == MSFT_TEXTURE_DDS)
+        {
+            self.extensions_required.push(MSFT_TEXTURE_DDS.to_string());
+        }
+        if export != TextureExport::Png {
+            let dds_source = self.put_image(ImageSource::Dds(image_path.to_path_buf()));
+            let mut ext = json::extensions::texture::Texture::default();
+            ext.others.insert(
+                MSFT_TEXTURE_DDS.to_string(),
+                serde_json::json!({ "source": dds_source.value() }),
+            );
+            texture.extensions = Some(ext);
+            if !self.extensions_used.iter().any(|e| e == MSFT_TEXTURE_DDS) {
+                self.extensions_used.push(MSFT_TEXTURE_DDS.to_string());
+            }
+        }
+        self.texture_vec.push(texture);
+        Some(json::Index::new(self.texture_vec.len() as u32 - 1))
+    }
+
+    fn put_image(&mut self, src: ImageSource) -> json::Index<json::Image> {
+        let image = json::Image {
+            uri: Some(src.file_name()),
+            buffer_view: None,
+            mime_type: None,
+            extensions: None,
+            extras: None,
+            name: None,
+        };
+        self.image_vec.push(image);
+        self.image_src.push(src);
+        json::Index::new(self.image_vec.len() as u32 - 1)
+    }
+
+    /// Embeds all images into the buffer.
+    fn run_dds(&mut self) {
+        let image_src = std::mem::take(&mut self.image_src);
+        if let Some(mats) = self.rdm.mat.as_ref() {
+            for (e, src) in self.image_vec.iter_mut().zip(image_src) {
+                let buffer = match src.bytes(mats) {
+                    Ok(buffer) => buffer,
+                    Err(err) => {
+                        warn!("skipping image {:?}: {}", src.file_name(), err);
+                        continue;
+                    }
+                };
+
+                e.uri = None;
+                e.mime_type = Some(json::image::MimeType(src.mime_type().to_string()));
+                let buffer_view_idx = RdGltfBuilder::put_buffer_and_view(
+                    &mut self.obj,
+                    BufferContainer::U8(buffer),
+                    &mut self.buffers,
+                    &mut self.buffer_views,
+                    None,
+                );
+                e.buffer_view = Some(json::Index::new(buffer_view_idx));
             }
         }
     }
@@ -1001,10 +1194,13 @@ impl RdGltfBuilder {
             textures: self.texture_vec,
             images: self.image_vec,
             samplers: self.sampler_vec,
+            extensions_used: self.extensions_used,
+            extensions_required: self.extensions_required,
             ..Default::default()
         };
 
         self.obj.root = Some(root);
+        self.obj.images = self.image_src;
 
         self.obj
     }
@@ -1079,10 +1275,23 @@ impl From<RdModell> for RdGltfBuilder {
     }
 }
 
-pub fn build(rdm: RdModell, dir: Option<PathBuf>, create_new: bool, config: GltfExportFormat) {
+pub fn build(
+    mut rdm: RdModell,
+    dir: Option<PathBuf>,
+    create_new: bool,
+    config: GltfExportFormat,
+    unit_conversion: UnitConversion,
+) {
+    if !unit_conversion.is_identity() {
+        info!("unit conversion: {:?}", unit_conversion);
+        rdm.transform(unit_conversion.matrix().try_inverse().unwrap())
+            .expect("UnitConversion::new checks the scale");
+    }
     let mat_opt = rdm.mat.clone();
     let mut b = RdGltfBuilder::from(rdm);
-    b.run_dds(config == GltfExportFormat::Glb);
+    if config == GltfExportFormat::Glb {
+        b.run_dds();
+    }
     if config == GltfExportFormat::Glb || config == GltfExportFormat::GltfSeparateMinimise {
         b.merge_buffers();
         if config == GltfExportFormat::Glb {
@@ -1099,6 +1308,8 @@ pub fn build(rdm: RdModell, dir: Option<PathBuf>, create_new: bool, config: Gltf
 struct RdGltf {
     buffers: Vec<BufferContainer>,
     root: Option<json::Root>,
+    // images that are not embedded
+    images: Vec<ImageSource>,
 }
 enum BufferContainer {
     U8(Vec<u8>),
@@ -1175,6 +1386,7 @@ impl RdGltf {
         RdGltf {
             buffers: vec![],
             root: None,
+            images: vec![],
         }
     }
 
@@ -1251,16 +1463,12 @@ impl RdGltf {
                     bin.to_writer(&mut writer).unwrap();
                 }
 
-                // copy converted png from tmp to dest
+                // write images next to the gltf
                 if let Some(mat) = optmat.as_ref() {
-                    let tmp_dir = env::temp_dir();
-                    for e in mat.into_iter() {
-                        let mut src = tmp_dir.join(e.file_stem().unwrap());
-                        src.set_extension("PNG");
-                        let mut dst_file = udir.join(e.file_stem().unwrap());
-                        dst_file.set_extension("PNG");
-                        debug!("copy: {:?} to {:?}", &src, &dst_file);
-                        fs::copy(src, &dst_file).unwrap();
+                    for image in self.images.iter() {
+                        if let Err(e) = image.write(mat, &udir) {
+                            warn!("skipping image {:?}: {}", image.file_name(), e);
+                        }
                     }
                 }
             }
@@ -1,25 +1,29 @@
+use crate::dds::{DdsError, RgbaImage};
+use crate::dds_encode::{self, BcEncoding};
 use crate::rdm_data_anim::Frame;
-use crate::rdm_data_main::MeshInfo;
+use crate::rdm_data_main::{MeshInfo, RdmFileHeader};
+use crate::rdm_material::TextureSet;
 use crate::vertex::*;
 use crate::vertex_transform::*;
 use crate::RdModell;
 use crate::{gltf_reader_vertex::PutVertex, RdJoint};
 use crate::{vertex::TargetVertexFormat, Triangle};
 
-use gltf::animation::Channel;
 use gltf::Node;
 use nalgebra::*;
 
 use bytes::BytesMut;
 
+use crate::gltf_reader_anim::*;
 use crate::rdm_anim::*;
 use gltf::animation::util::ReadOutputs::*;
 
 use crate::VertexFormat2;
-use std::collections::hash_map::DefaultHasher;
+use std::collections::hash_map::{DefaultHasher, Entry};
 use std::collections::HashSet;
+use std::fmt;
 use std::hash::{Hash, Hasher};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
 use std::{
     collections::HashMap,
@@ -30,6 +34,8 @@ use std::{
 pub enum ResolveNodeName {
     UnstableIndex,
     UniqueName,
+    /// Node name, made unique where needed: see [`ImportedGltf::renamed_nodes`].
+    Disambiguate,
 }
 
 impl FromStr for ResolveNodeName {
@@ -39,36 +45,340 @@ impl FromStr for ResolveNodeName {
         match input.to_ascii_lowercase().as_str() {
             "uniquename" | "n" => Ok(ResolveNodeName::UniqueName),
             "unstableindex" | "i" => Ok(ResolveNodeName::UnstableIndex),
+            "disambiguate" | "d" => Ok(ResolveNodeName::Disambiguate),
             _ => Err(format!("Invalid value for ResolveNodeName: {}", input)),
         }
     }
 }
 
+#[derive(Debug)]
+pub enum GltfImportError {
+    Gltf(gltf::Error),
+    /// No `kind` (mesh, node, skin, animation) called `name`.
+    NotFound {
+        kind: &'static str,
+        name: String,
+        available: Vec<String>,
+    },
+    /// More than one `kind` called `name`.
+    Ambiguous {
+        kind: &'static str,
+        name: String,
+        available: Vec<String>,
+    },
+    /// Animated scale that `mode` can not import, one line per joint.
+    ScaleChannels {
+        animation: String,
+        mode: ScaleChannels,
+        joints: Vec<String>,
+    },
+    /// A node transform without inverse.
+    Transform(TransformError),
+    /// `anim_range` does not overlap the animation.
+    TimeRange {
+        animation: String,
+        start: f32,
+        end: f32,
+        t_max: f32,
+    },
+}
+
+impl fmt::Display for GltfImportError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            GltfImportError::Gltf(e) => write!(f, "{}", e),
+            GltfImportError::Transform(e) => write!(f, "{}", e),
+            GltfImportError::NotFound {
+                kind,
+                name,
+                available,
+            } => write!(
+                f,
+                "no {} named {:?}. Available: {:?}",
+                kind, name, available
+            ),
+            GltfImportError::Ambiguous {
+                kind,
+                name,
+                available,
+            } => write!(
+                f,
+                "{} name {:?} is ambiguous. Available: {:?}",
+                kind, name, available
+            ),
+            GltfImportError::ScaleChannels {
+                animation,
+                mode,
+                joints,
+            } => {
+                let hint = match mode {
+                    ScaleChannels::Reject => "animated scale is not supported",
+                    ScaleChannels::Bake => "only uniform scale can be baked",
+                };
+                write!(f, "animation {:?}: {}:", animation, hint)?;
+                for joint in joints {
+                    write!(f, "\n  {}", joint)?;
+                }
+                Ok(())
+            }
+            GltfImportError::TimeRange {
+                animation,
+                start,
+                end,
+                t_max,
+            } => write!(
+                f,
+                "time range {}..{} is outside of animation {:?} (0..{})",
+                start, end, animation, t_max
+            ),
+        }
+    }
+}
+
+impl std::error::Error for GltfImportError {}
+
+impl From<gltf::Error> for GltfImportError {
+    fn from(e: gltf::Error) -> Self {
+        GltfImportError::Gltf(e)
+    }
+}
+
+impl From<TransformError> for GltfImportError {
+    fn from(e: TransformError) -> Self {
+        GltfImportError::Transform(e)
+    }
+}
+
+/// Which mesh `ImportedGltf` reads.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MeshSelector {
+    /// glTF mesh index, read from the first node instantiating it.
+    Index(u32),
+    /// glTF mesh name, read from the first node instantiating it.
+    MeshName(String),
+    /// Name of the node instantiating the mesh.
+    NodeName(String),
+}
+
+impl From<u32> for MeshSelector {
+    fn from(idx: u32) -> Self {
+        MeshSelector::Index(idx)
+    }
+}
+
+/// Index of the single item called `name`. Unnamed items are listed as `#index`.
+fn find_by_name<'a>(
+    kind: &'static str,
+    name: &str,
+    items: impl Iterator<Item = (usize, Option<&'a str>)>,
+) -> Result<usize, GltfImportError> {
+    let items: Vec<(usize, Option<&str>)> = items.collect();
+    let mut found = items.iter().filter(|(_, n)| *n == Some(name));
+    let available = || {
+        items
+            .iter()
+            .map(|(i, n)| n.map(String::from).unwrap_or_else(|| format!("#{}", i)))
+            .collect()
+    };
+    match (found.next(), found.next()) {
+        (Some((idx, _)), None) => Ok(*idx),
+        (None, _) => Err(GltfImportError::NotFound {
+            kind,
+            name: name.to_owned(),
+            available: available(),
+        }),
+        (Some(_), Some(_)) => Err(GltfImportError::Ambiguous {
+            kind,
+            name: name.to_owned(),
+            available: available(),
+        }),
+    }
+}
+
+/// How a glTF is split into several rdm files.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SplitMode {
+    /// One rdm per mesh, read from the first node instantiating it.
+    Mesh,
+    /// One rdm per mesh node of the default scene.
+    Node,
+}
+
+impl FromStr for SplitMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "mesh" | "m" => Ok(SplitMode::Mesh),
+            "node" | "n" => Ok(SplitMode::Node),
+            _ => Err(format!("Invalid value for SplitMode: {}", input)),
+        }
+    }
+}
+
+/// Where the origin of the imported rdm is placed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Pivot {
+    /// Keep the origin of the glTF.
+    Keep,
+    /// Centre of the bounding box.
+    BBoxCentre,
+    /// Centre of the bounding box floor (min y): ground level of a building footprint.
+    BBoxBottomCentre,
+    /// This point (in rdm space) becomes the new origin.
+    Offset([f32; 3]),
+}
+
+impl FromStr for Pivot {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "keep" => Ok(Pivot::Keep),
+            "bbox-centre" | "bbox-center" => Ok(Pivot::BBoxCentre),
+            "bbox-bottom-centre" | "bbox-bottom-center" => Ok(Pivot::BBoxBottomCentre),
+            v => {
+                let values: Vec<f32> = v
+                    .split(',')
+                    .map(|x| x.trim().parse::<f32>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| format!("Invalid value for Pivot: {}", input))?;
+                match values[..] {
+                    [x, y, z] => Ok(Pivot::Offset([x, y, z])),
+                    _ => Err(format!("Invalid value for Pivot: {}", input)),
+                }
+            }
+        }
+    }
+}
+
+impl Pivot {
+    /// Translation that moves the pivot point of `positions` to the origin.
+    fn resolve(&self, positions: &[[f32; 3]]) -> Vector3<f32> {
+        let bbox = || {
+            let mut min = Vector3::repeat(f32::MAX);
+            let mut max = Vector3::repeat(f32::MIN);
+            for p in positions {
+                let p = Vector3::from(*p);
+                min = min.inf(&p);
+                max = max.sup(&p);
+            }
+            (min, max)
+        };
+        match self {
+            Pivot::Keep => Vector3::zeros(),
+            Pivot::BBoxCentre => {
+                let (min, max) = bbox();
+                -(min + max) / 2.0
+            }
+            Pivot::BBoxBottomCentre => {
+                let (min, max) = bbox();
+                let centre = (min + max) / 2.0;
+                -Vector3::new(centre.x, min.y, centre.z)
+            }
+            Pivot::Offset(p) => -Vector3::from(*p),
+        }
+    }
+}
+
+/// Transforms `gltf_to_rdm` baked into a model. `read_animation` applies them to the keys of the same skeleton.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BakedTransform {
+    /// Node transform baked into the skeleton, `None` if no skin was loaded.
+    pub skin: Option<Matrix4<f32>>,
+    /// Translation resolved from `pivot`, `None` for `Pivot::Keep`.
+    pub pivot_offset: Option<Vector3<f32>>,
+}
+
+impl BakedTransform {
+    /// For a skeleton that was not read by `gltf_to_rdm`. Only `Pivot::Offset` can be resolved without the mesh.
+    pub fn without_mesh(pivot: Pivot) -> Self {
+        let pivot_offset = match pivot {
+            Pivot::Keep => None,
+            Pivot::Offset(_) => Some(pivot.resolve(&[])),
+            Pivot::BBoxCentre | Pivot::BBoxBottomCentre => {
+                warn!(
+                    "pivot {:?} depends on the mesh. Animation is not moved!",
+                    pivot
+                );
+                None
+            }
+        };
+        BakedTransform {
+            skin: None,
+            pivot_offset,
+        }
+    }
+}
+
 pub struct ImportedGltf {
     gltf: gltf::Document,
     buffers: Vec<gltf::buffer::Data>,
+    images: Vec<gltf::image::Data>,
     pub name_setting: ResolveNodeName,
+    pub unit_conversion: UnitConversion,
+    pub pivot: Pivot,
+    /// Read every mesh node of the default scene (with baked node transforms) instead of a single mesh.
+    pub merge_scene: bool,
+    /// Resample every animated joint at this frame rate. Otherwise only joints that need it are resampled.
+    pub anim_fps: Option<f32>,
+    /// Only import the animation between start and end (seconds). The clip is resampled and starts at 0.
+    pub anim_range: Option<(f32, f32)>,
+    /// Handling of non-identity scale channels.
+    pub scale_channels: ScaleChannels,
     mesh_idx: u32,
     mesh_node: u32,
+    // skin selected by name, otherwise the skin of mesh_node
+    skin_idx: Option<usize>,
+    // node names for ResolveNodeName::Disambiguate, by node index
+    unique_names: Vec<String>,
 }
 
 impl<'a> TryFrom<&'a Path> for ImportedGltf {
-    type Error = gltf::Error;
-    fn try_from(f_path: &'a Path) -> Result<ImportedGltf, gltf::Error> {
+    type Error = GltfImportError;
+    fn try_from(f_path: &'a Path) -> Result<ImportedGltf, GltfImportError> {
         self::ImportedGltf::try_import(f_path, 0, ResolveNodeName::UniqueName)
     }
 }
 
-fn node_get_local_transform(target_node: &Node) -> Isometry3<f32> {
-    let target_mat = target_node.transform().matrix();
-    let mut mat = Matrix4::from_fn(|i, j| target_mat[j][i]);
-    mat.m44 = 1.0;
-    let similarity: Similarity3<f32> = nalgebra::try_convert(mat).unwrap();
-    debug!("similarity.scaling: {}", similarity.scaling());
+fn node_matrix(node: &Node) -> Matrix4<f32> {
+    let target_mat = node.transform().matrix();
+    Matrix4::from_fn(|i, j| target_mat[j][i])
+}
+
+type Trs = (Translation3<f32>, UnitQuaternion<f32>, Vector3<f32>);
+
+/// Splits `mat` into translation, rotation and scale per axis. Shear is lost.
+/// A negative determinant ends up in the x scale. Fails for a zero scale axis, which has no rotation.
+fn decompose_trs(mat: &Matrix4<f32>) -> Result<Trs, TransformError> {
+    if !mat.iter().all(|v| v.is_finite()) {
+        return Err(TransformError::NotFinite(*mat));
+    }
+    let mut rot = mat.fixed_resize::<3, 3>(0.0);
+    let mut scale = Vector3::new(
+        rot.column_mut(0).normalize_mut(),
+        rot.column_mut(1).normalize_mut(),
+        rot.column_mut(2).normalize_mut(),
+    );
+    if scale.iter().any(|s| *s == 0.0) || !rot.iter().all(|v| v.is_finite()) {
+        return Err(TransformError::Singular(*mat));
+    }
+    if rot.determinant() < 0.0 {
+        rot.column_mut(0).neg_mut();
+        scale.x = -scale.x;
+    }
+    let rotation = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix_unchecked(rot));
+    Ok((
+        Translation3::new(mat.m14, mat.m24, mat.m34),
+        rotation,
+        scale,
+    ))
+}
 
-    let mut isometry: Isometry3<f32> = similarity.isometry;
-    isometry.rotation = isometry.rotation.inverse();
-    isometry
+fn node_get_local_transform(target_node: &Node) -> Result<Isometry3<f32>, TransformError> {
+    let (translation, rotation, scale) = decompose_trs(&node_matrix(target_node))?;
+    debug!("scale: {}", scale);
+    Ok(Isometry3::from_parts(translation, rotation.inverse()))
 }
 
 fn extract_rotations(
@@ -104,46 +414,154 @@ fn extract_translations(
     frames
 }
 
-fn read_animation_channel(buffers: &[gltf::buffer::Data], channel: Channel) -> Vec<Frame> {
-    let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
-    let time = reader.read_inputs().unwrap();
-    let output = reader.read_outputs().unwrap();
-
-    match output {
-        Rotations(rot) => {
-            let rot_iter = rot.into_f32();
-            extract_rotations(time, Default::default(), rot_iter)
-        }
-        Translations(trans) => extract_translations(time, Default::default(), trans),
-        _ => unreachable!(),
-    }
-}
-
 impl<'a> ImportedGltf {
     pub fn try_import(
         f_path: &'a Path,
-        mesh_idx: u32,
+        mesh: impl Into<MeshSelector>,
         joint_name_src: ResolveNodeName,
-    ) -> Result<ImportedGltf, gltf::Error> {
+    ) -> Result<ImportedGltf, GltfImportError> {
+        let mut res = Self::try_import_without_mesh(f_path, joint_name_src)?;
+        res.select_mesh(mesh.into())?;
+        Ok(res)
+    }
+
+    /// Import for [`read_animation`](Self::read_animation) only, e.g. a glTF holding just a clip.
+    /// No mesh is selected: do not call `gltf_to_rdm`.
+    pub fn try_import_without_mesh(
+        f_path: &'a Path,
+        joint_name_src: ResolveNodeName,
+    ) -> Result<ImportedGltf, GltfImportError> {
         info!("gltf::import start!");
-        let (gltf, buffers, _) = gltf::import(f_path)?;
-        let mut res = Self {
+        let (gltf, buffers, images) = gltf::import(f_path)?;
+        let unique_names = unique_node_names(&gltf);
+        let res = Self {
             gltf,
             buffers,
+            images,
             name_setting: joint_name_src,
+            unit_conversion: UnitConversion::default(),
+            pivot: Pivot::Keep,
+            merge_scene: false,
+            anim_fps: None,
+            anim_range: None,
+            scale_channels: ScaleChannels::Reject,
             mesh_idx: 0,
             mesh_node: 0,
+            skin_idx: None,
+            unique_names,
         };
-        res.change_mesh_index(mesh_idx);
         info!("gltf::import end!");
         Ok(res)
     }
 
+    pub fn select_mesh(&mut self, mesh: MeshSelector) -> Result<(), GltfImportError> {
+        let mesh_nodes = || {
+            self.gltf
+                .nodes()
+                .filter(|n| n.mesh().is_some())
+                .map(|n| (n.index(), n.name()))
+        };
+        let instantiated_meshes = || {
+            self.gltf
+                .meshes()
+                .filter(|m| find_first_mesh_instantiating_node(&self.gltf, m.index()).is_some())
+                .map(|m| (m.index(), m.name()))
+        };
+        match mesh {
+            MeshSelector::Index(idx) => {
+                if !instantiated_meshes().any(|(i, _)| i == idx as usize) {
+                    return Err(GltfImportError::NotFound {
+                        kind: "instantiated mesh index",
+                        name: idx.to_string(),
+                        available: instantiated_meshes()
+                            .map(|(i, n)| format!("{} ({})", i, n.unwrap_or("unnamed")))
+                            .collect(),
+                    });
+                }
+                self.change_mesh_index(idx);
+            }
+            MeshSelector::MeshName(name) => {
+                let idx = find_by_name("instantiated mesh", &name, instantiated_meshes())?;
+                self.change_mesh_index(idx as u32);
+            }
+            MeshSelector::NodeName(name) => {
+                let idx = find_by_name("mesh node", &name, mesh_nodes())?;
+                self.change_mesh_node(idx as u32);
+            }
+        }
+        Ok(())
+    }
+
+    /// Uses the skin called `name` instead of the skin of the mesh node.
+    pub fn select_skin(&mut self, name: &str) -> Result<(), GltfImportError> {
+        let idx = find_by_name(
+            "skin",
+            name,
+            self.gltf.skins().map(|s| (s.index(), s.name())),
+        )?;
+        self.skin_idx = Some(idx);
+        Ok(())
+    }
+
     pub fn change_mesh_index(&mut self, idx: u32) {
         self.mesh_idx = idx;
         self.set_mesh_node();
     }
 
+    /// Selects the mesh instantiated by node `idx`. Unlike `change_mesh_index` this uses exactly that node.
+    pub fn change_mesh_node(&mut self, idx: u32) {
+        let node = self.gltf.nodes().nth(idx as usize).unwrap();
+        self.mesh_idx = node.mesh().expect("node has no mesh").index() as u32;
+        self.mesh_node = idx;
+    }
+
+    pub fn has_skin(&self) -> bool {
+        self.skin_index().is_some()
+    }
+
+    /// Mesh indices (`SplitMode::Mesh`) or node indices (`SplitMode::Node`) to iterate for a split import.
+    pub fn split_targets(&self, mode: SplitMode) -> Vec<u32> {
+        match mode {
+            SplitMode::Mesh => self
+                .gltf
+                .meshes()
+                .filter(|m| find_first_mesh_instantiating_node(&self.gltf, m.index()).is_some())
+                .map(|m| m.index() as u32)
+                .collect(),
+            SplitMode::Node => scene_mesh_nodes(&self.gltf)
+                .iter()
+                .map(|n| n.index() as u32)
+                .collect(),
+        }
+    }
+
+    /// File name for the current mesh selection: `{mesh}`, `{node}` and `{index}` in `template` are replaced
+    /// by the glTF mesh name, the node name and `index`. Characters unfit for a file name become `_`.
+    /// A name already in `taken` (ignoring case) gets the suffix `_{index}`.
+    pub fn output_name(&self, template: &str, index: usize, taken: &[String]) -> String {
+        let mesh = self.gltf.meshes().nth(self.mesh_idx as usize).unwrap();
+        let node = self.gltf.nodes().nth(self.mesh_node as usize).unwrap();
+        let mesh_name = mesh
+            .name()
+            .map(String::from)
+            .unwrap_or_else(|| format!("mesh{}", mesh.index()));
+        let node_name = node
+            .name()
+            .map(String::from)
+            .unwrap_or_else(|| format!("node{}", node.index()));
+
+        let name = template
+            .replace("{mesh}", &mesh_name)
+            .replace("{node}", &node_name)
+            .replace("{index}", &index.to_string());
+        // no dots: the writer sets the extension
+        let mut name = sanitize_file_name(&name).replace('.', "_");
+        while taken.iter().any(|t| t.eq_ignore_ascii_case(&name)) {
+            name = format!("{}_{}", name, index);
+        }
+        name
+    }
+
     fn set_mesh_node(&mut self) {
         let mesh = self
             .gltf
@@ -160,6 +578,7 @@ impl<'a> ImportedGltf {
         if self.name_setting == ResolveNodeName::UniqueName {
             let error_msg = "
             This converter by default matches gltf node names to rdm bone names and therefore requires that the gltf node.name property exists and that it is unique. 
+            To rename duplicates use option `-u=Disambiguate, --gltf-node-joint-name-src=Disambiguate`.
             To instead use gltf node index as a source for rdm bone name use option `-u=UnstableIndex, --gltf-node-joint-name-src=UnstableIndex`";
             let len = self.gltf.nodes().len();
             let no_dupes: HashSet<&str> = self
@@ -184,27 +603,249 @@ impl<'a> ImportedGltf {
         match self.name_setting {
             ResolveNodeName::UnstableIndex => format!("UnnamedGltfNode{}", target_node.index()),
             ResolveNodeName::UniqueName => target_node.name().unwrap().to_owned(),
+            ResolveNodeName::Disambiguate => self.unique_names[target_node.index()].clone(),
+        }
+    }
+
+    /// (node index, glTF name, rdm name) of every node `ResolveNodeName::Disambiguate` renames.
+    /// Duplicate names get their parent's name as prefix (`Armature.001/Bone`), remaining clashes
+    /// `~1`, `~2`, … in node order. Unnamed nodes are called `node<index>`.
+    pub fn renamed_nodes(&self) -> Vec<(usize, Option<&str>, &str)> {
+        self.gltf
+            .nodes()
+            .filter(|n| n.name() != Some(self.unique_names[n.index()].as_str()))
+            .map(|n| (n.index(), n.name(), self.unique_names[n.index()].as_str()))
+            .collect()
+    }
+
+    /// Writes [`renamed_nodes`](Self::renamed_nodes) as JSON.
+    pub fn write_node_name_map(&self, file: &Path) -> std::io::Result<()> {
+        let nodes: Vec<serde_json::Value> = self
+            .renamed_nodes()
+            .into_iter()
+            .map(|(index, gltf_name, rdm_name)| {
+                serde_json::json!({ "node": index, "gltf": gltf_name, "rdm": rdm_name })
+            })
+            .collect();
+        let json = serde_json::json!({ "nodes": nodes });
+        std::fs::write(file, serde_json::to_string_pretty(&json).unwrap())
+    }
+
+    /// glTF material index (`None` for the default material) of every material slot, numbered like `gltf_to_rdm`
+    /// numbers them before `overide_mesh_idx` is applied.
+    pub fn material_slots(&self) -> Vec<Option<usize>> {
+        self.slot_materials().iter().map(|m| m.index()).collect()
+    }
+
+    /// glTF material name of every material slot, see [`material_slots`](Self::material_slots).
+    pub fn material_names(&self) -> Vec<Option<String>> {
+        self.slot_materials()
+            .iter()
+            .map(|m| m.name().map(String::from))
+            .collect()
+    }
+
+    /// glTF material name of every rdm material, i.e. after `overide_mesh_idx`. The first slot of a material wins.
+    pub fn rdm_material_names(&self, overide_mesh_idx: Option<&[u32]>) -> Vec<Option<String>> {
+        let mut names: Vec<Option<String>> = Vec::new();
+        for (slot, name) in self.material_names().into_iter().enumerate() {
+            let idx = overide_mesh_idx.map_or(slot, |v| v[slot] as usize);
+            if names.len() <= idx {
+                names.resize(idx + 1, None);
+            }
+            if names[idx].is_none() {
+                names[idx] = name;
+            }
         }
+        names
     }
 
+    fn slot_materials(&self) -> Vec<gltf::Material<'_>> {
+        if self.merge_scene {
+            let mut materials: Vec<gltf::Material> = Vec::new();
+            for node in scene_mesh_nodes(&self.gltf) {
+                for primitive in node.mesh().unwrap().primitives() {
+                    let material = primitive.material();
+                    if !materials.iter().any(|m| m.index() == material.index()) {
+                        materials.push(material);
+                    }
+                }
+            }
+            materials
+        } else {
+            let mesh = self.gltf.meshes().nth(self.mesh_idx as usize).unwrap();
+            mesh.primitives().map(|p| p.material()).collect()
+        }
+    }
+
+    /// Encodes the base color, normal and metallic-roughness image of every material slot as DDS with a full mip
+    /// chain into `dir`: `{name}_diff_0.dds`, `{name}_norm_0.dds` and `{name}_metal_0.dds`. With more than one
+    /// material the glTF material name is inserted (`{name}_{material}_diff_0.dds`). A material without base color
+    /// texture gets a 4x4 diffuse of its base color factor. The metallic-roughness image is repacked to the Anno
+    /// metal layout of [`TextureSet`], the other images are written as stored in the glTF.
+    ///
+    /// The result is indexed by rdm material, i.e. after `overide_mesh_idx`. The first slot of a material wins,
+    /// unused material indices get an empty set.
+    pub fn write_textures(
+        &self,
+        dir: &Path,
+        name: &str,
+        encoding: BcEncoding,
+        overide_mesh_idx: Option<&[u32]>,
+    ) -> Result<Vec<TextureSet>, DdsError> {
+        let slots = self.slot_materials();
+        let rdm_material = |slot: usize| match overide_mesh_idx {
+            Some(v) => v[slot] as usize,
+            None => slot,
+        };
+        let count = (0..slots.len())
+            .map(rdm_material)
+            .max()
+            .map_or(0, |m| m + 1);
+        let distinct: HashSet<Option<usize>> = slots.iter().map(|m| m.index()).collect();
+
+        let mut written: HashMap<Option<usize>, TextureSet> = HashMap::new();
+        let mut used_names = HashSet::new();
+        let mut out = vec![TextureSet::default(); count];
+        for (slot, material) in slots.iter().enumerate() {
+            if out[rdm_material(slot)].diff.is_some() {
+                continue;
+            }
+            let textures = match written.entry(material.index()) {
+                Entry::Occupied(e) => e.get().clone(),
+                Entry::Vacant(e) => {
+                    let mut prefix = String::from(name);
+                    if distinct.len() > 1 {
+                        let material_name = match (material.name(), material.index()) {
+                            (Some(n), _) => sanitize_file_name(n),
+                            (None, Some(i)) => format!("mat{}", i),
+                            (None, None) => String::from("default"),
+                        };
+                        prefix = format!("{}_{}", name, material_name);
+                    }
+                    if !used_names.insert(prefix.clone()) {
+                        prefix = format!("{}_{}", prefix, slot);
+                        used_names.insert(prefix.clone());
+                    }
+                    let textures =
+                        self.write_material_textures(material, dir, &prefix, encoding)?;
+                    e.insert(textures).clone()
+                }
+            };
+            out[rdm_material(slot)] = textures;
+        }
+        Ok(out)
+    }
+
+    fn write_material_textures(
+        &self,
+        material: &gltf::Material,
+        dir: &Path,
+        prefix: &str,
+        encoding: BcEncoding,
+    ) -> Result<TextureSet, DdsError> {
+        let pbr = material.pbr_metallic_roughness();
+        // textures without image (allowed by MSFT_texture_dds) are skipped
+        let write = |info: Option<gltf::Texture>,
+                     suffix: &str,
+                     remap: fn(&mut [u8])|
+         -> Result<Option<PathBuf>, DdsError> {
+            let image = match info.and_then(|t| t.source()) {
+                Some(image) => image,
+                None => return Ok(None),
+            };
+            let file = dir.join(format!("{}_{}_0.dds", prefix, suffix));
+            info!("texture: image #{} -> {:?}", image.index(), file);
+            let mut rgba = gltf_image_to_rgba(&self.images[image.index()]);
+            rgba.pixels.chunks_exact_mut(4).for_each(remap);
+            dds_encode::write_dds(&file, &rgba, encoding)?;
+            Ok(Some(file))
+        };
+
+        let base_color = pbr.base_color_texture().map(|i| i.texture());
+        let diff = match write(base_color, "diff", |_| ())? {
+            Some(file) => file,
+            None => {
+                let color = pbr
+                    .base_color_factor()
+                    .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8);
+                let image = RgbaImage {
+                    width: 4,
+                    height: 4,
+                    pixels: color.repeat(16),
+                };
+                let file = dir.join(format!("{}_diff_0.dds", prefix));
+                info!("texture: base color factor {:?} -> {:?}", color, file);
+                dds_encode::write_dds(&file, &image, encoding)?;
+                file
+            }
+        };
+        let norm = write(
+            material.normal_texture().map(|i| i.texture()),
+            "norm",
+            |_| (),
+        )?;
+        // roughness (G) and metallic (B) to metallic (R) and gloss (G)
+        let metal = write(
+            pbr.metallic_roughness_texture().map(|i| i.texture()),
+            "metal",
+            |px| {
+                px[0] = px[2];
+                px[1] = 255 - px[1];
+                px[2] = 0;
+                px[3] = 255;
+            },
+        )?;
+        let alpha_cutoff = match material.alpha_mode() {
+            gltf::material::AlphaMode::Mask => Some(material.alpha_cutoff().unwrap_or(0.5)),
+            _ => None,
+        };
+        Ok(TextureSet {
+            diff: Some(diff),
+            norm,
+            metal,
+            height: None,
+            alpha_cutoff,
+        })
+    }
+
+    /// Reads the glTF animations for `joints`, matched by name. Joints without channels get `frames + 1` idle keys
+    /// (0 gives a single key) with the pose of their glTF node, or their bind pose if the glTF has no such node.
+    /// `baked` is returned by the `gltf_to_rdm` call that produced `joints`.
     pub fn read_animation(
         &self,
         joints: &[RdJoint],
+        baked: &BakedTransform,
         frames: usize,
-        _tmax: f32,
-    ) -> Option<Vec<RdAnim>> {
+        negative_x_and_v0v2v1: bool,
+        animation_name: Option<&str>,
+    ) -> Result<Vec<RdAnim>, GltfImportError> {
         let (gltf, buffers) = (&self.gltf, &self.buffers);
 
+        let selected = match animation_name {
+            Some(name) => Some(find_by_name(
+                "animation",
+                name,
+                gltf.animations().map(|a| (a.index(), a.name())),
+            )?),
+            None => None,
+        };
+
         let mut translation_map: HashMap<String, Vec<Frame>> = HashMap::new();
         let mut rd_animations = Vec::new();
 
         let real_joints: HashSet<_> = joints.iter().map(|e| e.name.as_str()).collect();
+        let rest_scales = self.joint_rest_scales(joints)?;
+        let node_names: HashSet<String> = gltf.nodes().map(|n| self.node_get_name(&n)).collect();
 
-        let interpolate_error_message = "Interpolate required but not supported ! Re-Export model in blender with 'Always sample animations' enabled and try again";
-
-        for (anim_idx, animation) in gltf.animations().enumerate() {
+        for animation in gltf
+            .animations()
+            .filter(|a| selected.is_none() || selected == Some(a.index()))
+        {
             let mut t_max = 0.0;
-            let mut interpolate_channel: HashMap<String, (Vec<Frame>, Vec<Frame>)> = HashMap::new();
+            // joints whose channels can not be copied key by key
+            let mut resample_joints: HashSet<String> = HashSet::new();
+            let mut samplers: HashMap<String, (NodeSamplers, usize)> = HashMap::new();
 
             debug!("animation: {}", animation.name().unwrap_or("default"));
             for channel in animation.channels() {
@@ -235,7 +876,7 @@ impl<'a> ImportedGltf {
                         .unwrap(),
                 );
 
-                let origin = node_get_local_transform(&channel.target().node());
+                let origin = node_get_local_transform(&channel.target().node())?;
                 let origin_translation = [
                     origin.translation.x,
                     origin.translation.y,
@@ -250,19 +891,29 @@ impl<'a> ImportedGltf {
                 ];
 
                 if real_joints.contains(target_node_name_v2.as_str()) {
+                    let (node_samplers, _) = samplers
+                        .entry(target_node_name_v2.clone())
+                        .or_insert_with(|| {
+                            (NodeSamplers::default(), channel.target().node().index())
+                        });
+                    node_samplers.insert(buffers, &channel);
+                    if node_samplers.has_cubic()
+                        || node_samplers.has_step()
+                        || self.anim_fps.is_some()
+                    {
+                        resample_joints.insert(target_node_name_v2.clone());
+                    }
+
                     match output {
                         Rotations(rot) => {
                             let mut rot_iter = rot.into_f32();
                             if let Some(frames) = translation_map.get_mut(&target_node_name_v2) {
+                                if frames.len() != time.len() {
+                                    resample_joints.insert(target_node_name_v2.clone());
+                                }
                                 for (frame, t) in frames.iter_mut().zip(time) {
                                     if !relative_eq!(t, frame.time) {
-                                        interpolate_channel.insert(
-                                            target_node_name_v2,
-                                            (
-                                                frames.clone(),
-                                                read_animation_channel(buffers, channel),
-                                            ),
-                                        );
+                                        resample_joints.insert(target_node_name_v2);
                                         break;
                                     }
                                     let r = rot_iter.next().unwrap();
@@ -277,15 +928,12 @@ impl<'a> ImportedGltf {
                         }
                         Translations(mut trans) => {
                             if let Some(frames) = translation_map.get_mut(&target_node_name_v2) {
+                                if frames.len() != time.len() {
+                                    resample_joints.insert(target_node_name_v2.clone());
+                                }
                                 for (frame, t) in frames.iter_mut().zip(time) {
                                     if !relative_eq!(t, frame.time) {
-                                        interpolate_channel.insert(
-                                            target_node_name_v2,
-                                            (
-                                                read_animation_channel(buffers, channel),
-                                                frames.clone(),
-                                            ),
-                                        );
+                                        resample_joints.insert(target_node_name_v2);
                                         break;
                                     }
                                     frame.translation = trans.next().unwrap();
@@ -297,6 +945,8 @@ impl<'a> ImportedGltf {
                                 );
                             };
                         }
+                        // kept in `samplers`, see below
+                        Scales(_) => {}
                         _ => {
                             warn!(
                                 "output sampler not supported: '{:?}'",
@@ -310,32 +960,99 @@ impl<'a> ImportedGltf {
                 }
             }
 
-            // TODO: finish interpolate
-            if interpolate_channel.drain().next().is_some() {
-                unimplemented!("{}", interpolate_error_message);
+            let scaled =
+                self.take_scale_channels(&animation, joints, &rest_scales, &mut samplers)?;
+            for joint in joints
+                .iter()
+                .filter(|j| has_ancestor_in(joints, j, &scaled))
+            {
+                // joints of an rdm skeleton that are missing in the glTF keep their bind pose
+                let node = match gltf.nodes().find(|n| self.node_get_name(n) == joint.name) {
+                    Some(node) => node,
+                    None => continue,
+                };
+                // the translations change with the parent scale: evaluate them on the timeline
+                resample_joints.insert(joint.name.clone());
+                samplers
+                    .entry(joint.name.clone())
+                    .or_insert_with(|| (NodeSamplers::default(), node.index()));
+            }
+
+            let mut start = 0.0;
+            if let Some((range_start, range_end)) = self.anim_range {
+                let end = range_end.min(t_max as f32);
+                if range_start < 0.0 || range_start >= end {
+                    return Err(GltfImportError::TimeRange {
+                        animation: animation.name().unwrap_or("default").to_string(),
+                        start: range_start,
+                        end: range_end,
+                        t_max: t_max as f32,
+                    });
+                }
+                info!("time range: {}..{}", range_start, end);
+                start = range_start;
+                t_max = (end - range_start) as f64;
+                resample_joints.extend(samplers.keys().cloned());
+            }
+
+            if !resample_joints.is_empty() {
+                let fps = self.anim_fps.unwrap_or(DEFAULT_RESAMPLE_FPS);
+                let timeline: Vec<f32> = resample_timeline(t_max as f32, fps)
+                    .into_iter()
+                    .map(|t| t + start)
+                    .collect();
+                info!(
+                    "resampling {} joints at {} fps ({} keys)",
+                    resample_joints.len(),
+                    fps,
+                    timeline.len()
+                );
+                for name in resample_joints.drain() {
+                    let (node_samplers, node_idx) = &samplers[&name];
+                    let (rest_translation, rest_rotation, _) = gltf
+                        .nodes()
+                        .nth(*node_idx)
+                        .unwrap()
+                        .transform()
+                        .decomposed();
+                    // STEP keys are held until just before the next key
+                    let timeline = node_samplers.step_timeline(&timeline);
+                    let mut frames =
+                        node_samplers.resample(&timeline, rest_rotation, rest_translation);
+                    for frame in frames.iter_mut() {
+                        frame.time -= start;
+                    }
+                    translation_map.insert(name, frames);
+                }
             }
 
             for joint in joints {
                 if !translation_map.contains_key(&joint.name) {
-                    let node_idx = gltf
-                        .nodes()
-                        .find(|n| self.node_get_name(n) == joint.name)
-                        .unwrap();
-                    let origin = node_get_local_transform(&node_idx);
-                    let origin_translation = [
-                        origin.translation.x,
-                        origin.translation.y,
-                        origin.translation.z,
-                    ];
-                    let origin_quaternio_raw = origin.rotation.quaternion().coords;
-                    let origin_rotation = [
-                        origin_quaternio_raw.x,
-                        origin_quaternio_raw.y,
-                        origin_quaternio_raw.z,
-                        origin_quaternio_raw.w,
-                    ];
-                    let intervall = t_max as f32 / frames as f32;
-                    let mut v = Vec::with_capacity(frames);
+                    let (origin_rotation, origin_translation) =
+                        match gltf.nodes().find(|n| self.node_get_name(n) == joint.name) {
+                            Some(node) => {
+                                let origin = node_get_local_transform(&node)?;
+                                let origin_quaternio_raw = origin.rotation.quaternion().coords;
+                                (
+                                    [
+                                        origin_quaternio_raw.x,
+                                        origin_quaternio_raw.y,
+                                        origin_quaternio_raw.z,
+                                        origin_quaternio_raw.w,
+                                    ],
+                                    [
+                                        origin.translation.x,
+                                        origin.translation.y,
+                                        origin.translation.z,
+                                    ],
+                                )
+                            }
+                            // skeleton of another file: rest pose from its bind pose
+                            None => joint_local_bind_frame(joints, joint),
+                        };
+                    // frames == 0: a single key is enough for a joint that does not move
+                    let intervall = t_max as f32 / frames.max(1) as f32;
+                    let mut v = Vec::with_capacity(frames + 1);
                     for i in 0..=frames {
                         v.push(Frame {
                             rotation: origin_rotation,
@@ -348,6 +1065,50 @@ impl<'a> ImportedGltf {
                 }
             }
 
+            // rdm joints have no scale. A child translation is in the frame of its parent: move it by the rotation
+            // and (animated) scale of every ancestor, then take the rotations alone out again.
+            // The bind pose of joints missing in the glTF has no scale to remove.
+            let unscale: Vec<&RdJoint> = joints
+                .iter()
+                .filter(|j| {
+                    (has_ancestor_in(joints, j, &scaled)
+                        || has_ancestor_in(joints, j, &rest_scales))
+                        && node_names.contains(&j.name)
+                })
+                .collect();
+            let rotations: HashMap<String, Sampler<4>> = if unscale.is_empty() {
+                HashMap::new()
+            } else {
+                translation_map
+                    .iter()
+                    .map(|(name, frames)| (name.clone(), Sampler::from_frames(frames)))
+                    .collect()
+            };
+            for joint in unscale {
+                for frame in translation_map.get_mut(&joint.name).unwrap().iter_mut() {
+                    let mut linear = Matrix3::identity();
+                    let mut rotation = UnitQuaternion::identity();
+                    let mut parent = joint.parent;
+                    while let Some(p) = joints.get(parent as usize) {
+                        let scale = match scaled.get(&p.name) {
+                            Some(scale) => Vector3::repeat(scale.sample(frame.time + start)[0]),
+                            None => rest_scales
+                                .get(&p.name)
+                                .copied()
+                                .unwrap_or_else(|| Vector3::repeat(1.0)),
+                        };
+                        let r = rotations[&p.name].sample(frame.time);
+                        linear = r.to_rotation_matrix().matrix()
+                            * Matrix3::from_diagonal(&scale)
+                            * linear;
+                        rotation = r * rotation;
+                        parent = p.parent;
+                    }
+                    let translation = linear * Vector3::from(frame.translation);
+                    frame.translation = (rotation.inverse() * translation).into();
+                }
+            }
+
             let mut frame_collections: Vec<FrameCollection> = Vec::new();
             for (node_str, frames) in translation_map.drain() {
                 frame_collections.push(FrameCollection {
@@ -360,14 +1121,82 @@ impl<'a> ImportedGltf {
 
             frame_collections.sort_by(|a, b| a.name.cmp(&b.name));
 
-            let name = format!("anim_{}", anim_idx);
-            rd_animations.push(RdAnim {
+            let name = animation_name_unique(&animation, &rd_animations);
+            let mut anim = RdAnim {
                 time_max: (t_max * 1000.0) as u32,
                 anim_vec: frame_collections,
                 name,
-            });
+                file_header: RdmFileHeader::default(),
+            };
+            match baked.skin {
+                Some(base) if !base.is_identity(1.0e-6) => anim.transform(base, joints)?,
+                Some(_) => {}
+                // gltf_to_rdm was not called with a skin
+                None if negative_x_and_v0v2v1 => anim.transform(mirror_x(), joints)?,
+                None => {}
+            }
+            if !self.unit_conversion.is_identity() {
+                anim.transform(self.unit_conversion.matrix(), joints)?;
+            }
+            if let Some(offset) = baked.pivot_offset {
+                anim.transform(Translation3::from(offset).to_homogeneous(), joints)?;
+            }
+            rd_animations.push(anim);
+        }
+        Ok(rd_animations)
+    }
+
+    /// Removes the scale samplers from `samplers`. Returns the non-identity ones if `scale_channels` allows baking them.
+    fn take_scale_channels(
+        &self,
+        animation: &gltf::Animation,
+        joints: &[RdJoint],
+        rest_scales: &HashMap<String, Vector3<f32>>,
+        samplers: &mut HashMap<String, (NodeSamplers, usize)>,
+    ) -> Result<HashMap<String, Sampler<3>>, GltfImportError> {
+        let mut scaled = HashMap::new();
+        let mut report = Vec::new();
+        // joint order for a stable report
+        for joint in joints {
+            let scale = match samplers
+                .get_mut(&joint.name)
+                .and_then(|(s, _)| s.scale.take())
+            {
+                Some(scale) => scale,
+                None => continue,
+            };
+            // a channel that only repeats the node scale is handled like the node scale
+            let rest = rest_scales
+                .get(&joint.name)
+                .map(|s| [s.x, s.y, s.z])
+                .unwrap_or([1.0; 3]);
+            let is_rest = |v: &[f32; 3]| (0..3).all(|i| (v[i] - rest[i]).abs() < SCALE_EPSILON);
+            if scale.find_key(|v| !is_rest(v)).is_none() {
+                continue;
+            }
+            let rejected = match self.scale_channels {
+                ScaleChannels::Reject => scale.find_key(|v| !is_rest(v)),
+                ScaleChannels::Bake => scale.find_key(|v| !is_uniform_scale(v)),
+            };
+            if let Some((t, v)) = rejected {
+                report.push(format!("{}: scale {:?} at {}s", joint.name, v, t));
+            } else {
+                warn!(
+                    "scale of joint {:?} is baked into its children. Vertices bound to it keep their size.",
+                    joint.name
+                );
+                scaled.insert(joint.name.clone(), scale);
+            }
+        }
+        if report.is_empty() {
+            Ok(scaled)
+        } else {
+            Err(GltfImportError::ScaleChannels {
+                animation: animation.name().unwrap_or("default").to_string(),
+                mode: self.scale_channels,
+                joints: report,
+            })
         }
-        Some(rd_animations)
     }
 
     pub fn gltf_to_rdm(
@@ -377,11 +1206,18 @@ impl<'a> ImportedGltf {
         negative_x_and_v0v2v1: bool,
         no_transform: bool,
         overide_mesh_idx: Option<Vec<u32>>,
-    ) -> RdModell {
+    ) -> Result<(RdModell, BakedTransform), GltfImportError> {
         if negative_x_and_v0v2v1 {
             warn!("negative_x_and_v0v2v1: {}", negative_x_and_v0v2v1);
             warn!("negative_x_and_v0v2v1 may cause lighting artifacts !");
         }
+        let (skin, skin_bakes) = if load_skin {
+            self.check_node_name_uniqueness();
+            let (joints, bakes) = self.read_skin()?;
+            (Some(joints), bakes)
+        } else {
+            (None, Vec::new())
+        };
         let gltf_imp = self
             .read_mesh(
                 dst_format,
@@ -389,21 +1225,32 @@ impl<'a> ImportedGltf {
                 negative_x_and_v0v2v1,
                 no_transform,
                 overide_mesh_idx,
-            )
+                &skin_bakes,
+            )?
             .unwrap();
         let vertices = gltf_imp.1;
         let triangles = gltf_imp.2;
 
-        let joints_vec = if load_skin {
-            self.check_node_name_uniqueness();
-            Some(self.read_skin())
+        let mut baked = BakedTransform::default();
+        let joints_vec = if let Some(mut joints) = skin {
+            // the skeleton follows the vertices
+            let base = self.node_base(self.mesh_node as usize, negative_x_and_v0v2v1, no_transform);
+            if !base.is_identity(1.0e-6) {
+                info!("skeleton: baking node transform {}", base);
+                let context = TransformContext::new(base)?;
+                for joint in joints.iter_mut() {
+                    context.transform_joint(joint);
+                }
+            }
+            baked.skin = Some(base);
+            Some(joints)
         } else {
             None
         };
 
         // todo!("TODO : FIX ME !!!");
         let mesh_info_vec = gltf_imp.4;
-        RdModell {
+        let mut rdm = RdModell {
             rdmf: None,
             mesh_info: mesh_info_vec,
             joints: joints_vec,
@@ -411,14 +1258,73 @@ impl<'a> ImportedGltf {
             vertex: vertices,
             anim: None,
             mat: None,
+        };
+        if !self.unit_conversion.is_identity() {
+            info!("unit conversion: {:?}", self.unit_conversion);
+            rdm.transform(self.unit_conversion.matrix())
+                .expect("UnitConversion::new checks the scale");
+        }
+        if self.pivot != Pivot::Keep {
+            let positions = rdm
+                .vertex
+                .decode::<3>(UniqueIdentifier::Position, 0)
+                .unwrap();
+            let offset = self.pivot.resolve(&positions);
+            info!(
+                "pivot {:?}: applied offset {:?}",
+                self.pivot,
+                offset.as_slice()
+            );
+            rdm.transform(Translation3::from(offset).to_homogeneous())
+                .expect("translations are invertible");
+            baked.pivot_offset = Some(offset);
         }
+        Ok((rdm, baked))
     }
 
-    fn read_skin(&self) -> Vec<RdJoint> {
-        let mut out_joints_vec = Vec::new();
-        let node_with_skin = self.gltf.nodes().nth(self.mesh_node.try_into().unwrap());
+    fn skin_index(&self) -> Option<usize> {
+        if self.skin_idx.is_some() {
+            return self.skin_idx;
+        }
+        self.gltf
+            .nodes()
+            .nth(self.mesh_node as usize)
+            .and_then(|n| n.skin())
+            .map(|s| s.index())
+    }
 
-        let skin = node_with_skin.unwrap().skin().unwrap();
+    /// Transform applied to the vertices of the mesh instantiated by node `node_idx`.
+    fn node_base(
+        &self,
+        node_idx: usize,
+        negative_x_and_v0v2v1: bool,
+        no_transform: bool,
+    ) -> Matrix4<f32> {
+        let mut base: Matrix4<f32> = if no_transform {
+            Matrix4::identity()
+        } else {
+            build_transform2(&self.gltf, node_idx)
+        };
+        if negative_x_and_v0v2v1 {
+            base *= mirror_x();
+        }
+        debug!("base: {}", &base);
+        base
+    }
+
+    /// Reads the joints of the skin, and for every skin joint the matrix that moves the vertices bound to it from
+    /// the bind pose into the rest pose of the scene. `None` if the joint keeps its bind pose.
+    ///
+    /// rdm joints have no scale. Joints with a scaled node or ancestor get their rest pose without scale as bind
+    /// pose, which places child joints at their scaled positions, and the vertices follow.
+    fn read_skin(&self) -> Result<ReadSkinOutput, TransformError> {
+        let mut out_joints_vec = Vec::new();
+        let mut inverse_bind_matrices = Vec::new();
+        let skin = self
+            .gltf
+            .skins()
+            .nth(self.skin_index().expect("mesh node has no skin"))
+            .unwrap();
         {
             let mut node_names_vec: Vec<String> = Vec::new();
 
@@ -458,19 +1364,78 @@ impl<'a> ImportedGltf {
                 let inverse_bind_matrix: Matrix4<f32> = Matrix4::from_fn(|i, j| mat[j][i]);
                 // inverseBindMatrix^-1 = BindMatrix
                 // BindMatrix: global transform of the respective joint
-                let mat4_init: Matrix4<f32> = inverse_bind_matrix.try_inverse().unwrap();
+                let mat4_init: Matrix4<f32> = inverse_bind_matrix
+                    .try_inverse()
+                    .ok_or(TransformError::Singular(inverse_bind_matrix))?;
                 debug!("{} mat4_init: {}", z, mat4_init);
-                out_joints_vec.push(create_joint(mat4_init, name, parent));
+                out_joints_vec.push(create_joint(mat4_init, name, parent)?);
+                inverse_bind_matrices.push(inverse_bind_matrix);
             }
         }
         let mut check = true;
         while check {
-            check = self.create_joints_from_non_skin_nodes(&mut out_joints_vec);
+            check = self.create_joints_from_non_skin_nodes(&mut out_joints_vec)?;
+        }
+
+        let rest_scales = self.joint_rest_scales(&out_joints_vec)?;
+        let mut bakes = vec![None; inverse_bind_matrices.len()];
+        for (z, node) in skin.joints().enumerate() {
+            let joint = &out_joints_vec[z];
+            if !rest_scales.contains_key(&joint.name)
+                && !has_ancestor_in(&out_joints_vec, joint, &rest_scales)
+            {
+                continue;
+            }
+            let rest = build_transform2(&self.gltf, node.index());
+            let bake = rest * inverse_bind_matrices[z];
+            if !bake.is_identity(1.0e-5) {
+                out_joints_vec[z] = create_joint(rest, joint.name.clone(), joint.parent)?;
+                bakes[z] = Some(bake);
+            }
+        }
+
+        let mut scaled: Vec<String> = rest_scales
+            .iter()
+            .map(|(name, s)| format!("{} {:?}", name, s.as_slice()))
+            .collect();
+        if !scaled.is_empty() {
+            scaled.sort();
+            warn!(
+                "rdm joints have no scale. Node scale is baked into the vertices, the bind pose and the translations of child joints: {}",
+                scaled.join(", ")
+            );
+        }
+        Ok((out_joints_vec, bakes))
+    }
+
+    /// Local scale of the joint nodes, if it is not identity.
+    fn joint_rest_scales(
+        &self,
+        joints: &[RdJoint],
+    ) -> Result<HashMap<String, Vector3<f32>>, TransformError> {
+        let mut scales = HashMap::new();
+        for joint in joints {
+            let node = match self
+                .gltf
+                .nodes()
+                .find(|n| self.node_get_name(n) == joint.name)
+            {
+                Some(node) => node,
+                None => continue,
+            };
+            let (_, _, scale) = decompose_trs(&node_matrix(&node))?;
+            let v: [f32; 3] = scale.into();
+            if !is_identity_scale(&v) {
+                scales.insert(joint.name.clone(), scale);
+            }
         }
-        out_joints_vec
+        Ok(scales)
     }
 
-    fn create_joints_from_non_skin_nodes(&self, rdjoint: &mut Vec<RdJoint>) -> bool {
+    fn create_joints_from_non_skin_nodes(
+        &self,
+        rdjoint: &mut Vec<RdJoint>,
+    ) -> Result<bool, TransformError> {
         // TODO: refactor this ugly mess
         // If a joint has a parent that is not a joint itself convert the parent
         let rdlen = rdjoint.len();
@@ -496,7 +1461,7 @@ impl<'a> ImportedGltf {
                                 mat4_init,
                                 self.node_get_name(&n),
                                 u32::MAX,
-                            ));
+                            )?);
                         }
                     }
                     break;
@@ -504,50 +1469,70 @@ impl<'a> ImportedGltf {
             }
         }
         rdjoint.append(&mut node_converted_to_joints);
-        has_converted
+        Ok(has_converted)
     }
 
     fn read_mesh(
         &self,
         dst_format: TargetVertexFormat,
         load_skin: bool,
-        mut negative_x_and_v0v2v1: bool,
+        negative_x_and_v0v2v1: bool,
         no_transform: bool,
         overide_mesh_idx: Option<Vec<u32>>,
-    ) -> ReadMeshOutput {
+        skin_bakes: &[Option<Matrix4<f32>>],
+    ) -> Result<ReadMeshOutput, GltfImportError> {
         let (gltf, buffers) = (&self.gltf, &self.buffers);
-        // only the nth mesh of file gets read
+        // only the nth mesh of file gets read, unless the whole scene is merged
         if let Some(mesh) = gltf.meshes().nth(self.mesh_idx.try_into().unwrap()) {
-            info!("Mesh #{}", mesh.index());
-
-            let mesh_instantiating_node = self.mesh_node.try_into().unwrap();
-            debug!("mesh_instantiating_node: {}", mesh_instantiating_node);
-
-            let mut base: Matrix4<f32> = if no_transform {
-                Matrix4::identity()
+            let transform =
+                |node_idx: usize| self.node_base(node_idx, negative_x_and_v0v2v1, no_transform);
+
+            // (primitive, node transform, material slot)
+            let mut parts = Vec::new();
+            if self.merge_scene {
+                let mut material_slots: Vec<Option<usize>> = Vec::new();
+                for node in scene_mesh_nodes(gltf) {
+                    let node_mesh = node.mesh().unwrap();
+                    info!("Mesh #{} (node #{})", node_mesh.index(), node.index());
+                    if load_skin && node.skin().map(|s| s.index()) != self.skin_index() {
+                        warn!(
+                            "node #{} does not use the skin of node #{}. Joint indices will not match!",
+                            node.index(),
+                            self.mesh_node
+                        );
+                    }
+                    let base = transform(node.index());
+                    for primitive in node_mesh.primitives() {
+                        let material = primitive.material().index();
+                        let slot = match material_slots.iter().position(|m| *m == material) {
+                            Some(slot) => slot,
+                            None => {
+                                material_slots.push(material);
+                                material_slots.len() - 1
+                            }
+                        };
+                        parts.push((primitive, base, slot as u32));
+                    }
+                }
+                info!("material slots: {:?}", material_slots);
+                if let Some(v) = overide_mesh_idx.as_ref() {
+                    assert_eq!(material_slots.len(), v.len());
+                }
             } else {
-                build_transform2(gltf, mesh_instantiating_node)
-            };
+                info!("Mesh #{}", mesh.index());
 
-            if negative_x_and_v0v2v1 {
-                let m = Matrix3::new(-1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0);
-                base *= m.to_homogeneous();
-                negative_x_and_v0v2v1 = false;
-            }
-
-            debug!("base: {}", &base);
+                let mesh_instantiating_node = self.mesh_node.try_into().unwrap();
+                debug!("mesh_instantiating_node: {}", mesh_instantiating_node);
 
-            let det = base.determinant();
-            if det.is_sign_negative() {
-                warn!("determinant is negative: {}", det);
-                warn!("negative determinant requires special code path!");
-                negative_x_and_v0v2v1 = true;
+                if let Some(v) = overide_mesh_idx.as_ref() {
+                    assert_eq!(mesh.primitives().len(), v.len());
+                }
+                let base = transform(mesh_instantiating_node);
+                for (i, primitive) in mesh.primitives().enumerate() {
+                    parts.push((primitive, base, i as u32));
+                }
             }
 
-            let mat3 = base.fixed_resize::<3, 3>(0.0);
-            let inv_transform_mat3 = mat3.try_inverse().unwrap();
-            let transpose_inv_transform_mat3 = inv_transform_mat3.transpose();
-
             let ident = match dst_format {
                 TargetVertexFormat::P4h_N4b_G4b_B4b_T2h => {
                     crate::vertex::p4h_n4b_g4b_b4b_t2h().to_vec()
@@ -575,11 +1560,15 @@ impl<'a> ImportedGltf {
 
             let mut kown_vbuffers = HashMap::new();
 
-            if let Some(v) = overide_mesh_idx.as_ref() {
-                assert_eq!(mesh.primitives().len(), v.len());
-            }
-            for (i, primitive) in mesh.primitives().enumerate() {
+            for (i, (primitive, base, slot)) in parts.into_iter().enumerate() {
                 info!("- Primitive #{}", primitive.index());
+
+                let det = base.determinant();
+                let negative_x_and_v0v2v1 = det.is_sign_negative();
+                if negative_x_and_v0v2v1 {
+                    warn!("determinant is negative: {}", det);
+                    warn!("negative determinant requires special code path!");
+                }
                 let reader = primitive.reader(|buffer| Some(&buffers[buffer.index()]));
 
                 /* -------- # READ STUFF FROM GLTF # ------------ */
@@ -696,10 +1685,7 @@ impl<'a> ImportedGltf {
                 // ------------- Transform Vertex Data to RDM and write to vertex buffer ----------- //
 
                 //build context
-                let mut context = TransformContext {
-                    base,
-                    transpose_inv_transform_mat3,
-                };
+                let mut context = TransformContext::new(base)?;
 
                 while count > 0 {
                     debug!("count {}", count);
@@ -726,6 +1712,13 @@ impl<'a> ImportedGltf {
                             .collect();
                     }
 
+                    let (position, normal, tangent) = match joint {
+                        Some(joint) if skin_bakes.iter().any(Option::is_some) => {
+                            bake_skin_vertex(skin_bakes, joint, weight, position, normal, tangent)
+                        }
+                        _ => (position, normal, tangent),
+                    };
+
                     let tangent_w: f32 = if negative_x_and_v0v2v1 {
                         tangent[3]
                     } else {
@@ -861,8 +1854,8 @@ impl<'a> ImportedGltf {
                     start_index_location: merged_triangle_vec.len() as u32 * 3,
                     index_count: triangle_vec.len() as u32 * 3,
                     material: match overide_mesh_idx.as_ref() {
-                        Some(j) => j[i],
-                        None => i.try_into().unwrap(),
+                        Some(j) => j[slot as usize],
+                        None => slot,
                     },
                     _padding: Default::default(),
                 });
@@ -872,39 +1865,262 @@ impl<'a> ImportedGltf {
                 info!("{:?}", &mesh_info);
                 //return Some((vertsize, verts, merged_triangle_vec, vertices_count, mesh_info));
             }
+            if self.merge_scene {
+                (mesh_info, merged_triangle_vec) =
+                    group_by_material(&mesh_info, &merged_triangle_vec);
+                info!("{:?}", &mesh_info);
+            }
             let verts = VertexFormat2::new(
                 ident.into_boxed_slice(),
                 vertices_count,
                 vertsize,
-                verts_vec.freeze(),
+                verts_vec,
             );
-            return Some((
+            return Ok(Some((
                 vertsize,
                 verts,
                 merged_triangle_vec,
                 vertices_count,
                 mesh_info,
-            ));
+            )));
         }
-        None
+        Ok(None)
     }
 }
 
+/// Bind pose of `joint` relative to its parent, as rotation (negated w) and translation of a `Frame`.
+fn joint_local_bind_frame(joints: &[RdJoint], joint: &RdJoint) -> ([f32; 4], [f32; 3]) {
+    let global = |j: &RdJoint| {
+        let [x, y, z, w] = j.quaternion;
+        Isometry3::from_parts(
+            Translation3::from(Vector3::from(j.transition)),
+            UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z)),
+        )
+    };
+    let local = match joints.get(joint.parent as usize) {
+        Some(parent) => global(parent).inverse() * global(joint),
+        None => global(joint),
+    };
+    let q = local.rotation.coords;
+    let t = local.translation.vector;
+    ([q.x, q.y, q.z, -q.w], [t.x, t.y, t.z])
+}
+
+fn unique_node_names(gltf: &gltf::Document) -> Vec<String> {
+    let raw: Vec<String> = gltf
+        .nodes()
+        .map(|n| match n.name() {
+            Some(name) => name.to_owned(),
+            None => format!("node{}", n.index()),
+        })
+        .collect();
+    let mut parent = vec![None; raw.len()];
+    for node in gltf.nodes() {
+        for child in node.children() {
+            parent[child.index()] = Some(node.index());
+        }
+    }
+    let mut count: HashMap<&str, usize> = HashMap::new();
+    for name in raw.iter() {
+        *count.entry(name).or_default() += 1;
+    }
+
+    let mut used = HashSet::new();
+    let mut names = Vec::with_capacity(raw.len());
+    for (i, name) in raw.iter().enumerate() {
+        let mut name = match parent[i] {
+            Some(p) if count[name.as_str()] > 1 => format!("{}/{}", raw[p], name),
+            _ => name.clone(),
+        };
+        if used.contains(&name) {
+            let base = name;
+            name = (1..)
+                .map(|k| format!("{}~{}", base, k))
+                .find(|n| !used.contains(n))
+                .unwrap();
+        }
+        used.insert(name.clone());
+        names.push(name);
+    }
+    names
+}
+
+/// Moves a vertex by the `bakes` of its joints (see `ImportedGltf::read_skin`), blended by `weight`.
+/// Without weights the vertex follows its first joint.
+fn bake_skin_vertex(
+    bakes: &[Option<Matrix4<f32>>],
+    joint: [u16; 4],
+    weight: Option<[f32; 4]>,
+    position: [f32; 3],
+    normal: [f32; 3],
+    tangent: [f32; 4],
+) -> ([f32; 3], [f32; 3], [f32; 4]) {
+    let weight = weight.unwrap_or([1.0, 0.0, 0.0, 0.0]);
+    let mut mat = Matrix4::zeros();
+    for (j, w) in joint.iter().zip(weight) {
+        let bake = bakes.get(*j as usize).copied().flatten();
+        mat += bake.unwrap_or_else(Matrix4::identity) * w;
+    }
+    let mat3 = mat.fixed_resize::<3, 3>(0.0);
+    let normal_mat = match mat3.try_inverse() {
+        Some(inv) if weight.iter().sum::<f32>() > 0.0 => inv.transpose(),
+        _ => return (position, normal, tangent),
+    };
+    let p = mat.transform_point(&Point3::from(position));
+    let n = (normal_mat * Vector3::from(normal)).normalize();
+    let t = (mat3 * Vector3::new(tangent[0], tangent[1], tangent[2])).normalize();
+    (p.into(), n.into(), [t.x, t.y, t.z, tangent[3]])
+}
+
+fn has_ancestor_in<T>(joints: &[RdJoint], joint: &RdJoint, set: &HashMap<String, T>) -> bool {
+    let mut parent = joint.parent;
+    while let Some(p) = joints.get(parent as usize) {
+        if set.contains_key(&p.name) {
+            return true;
+        }
+        parent = p.parent;
+    }
+    false
+}
+
+/// Sanitized glTF animation name, `anim_<index>` if there is none. A name already in `previous` gets the index appended.
+fn animation_name_unique(animation: &gltf::Animation, previous: &[RdAnim]) -> String {
+    // no dots: the writer sets the extension
+    let name = match animation.name() {
+        Some(name) if !name.is_empty() => sanitize_file_name(name).replace('.', "_"),
+        _ => return format!("anim_{}", animation.index()),
+    };
+    if previous.iter().any(|a| a.name == name) {
+        format!("{}_{}", name, animation.index())
+    } else {
+        name
+    }
+}
+
+fn sanitize_file_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || "-_.".contains(c) {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    match name.trim_matches('.') {
+        "" => String::from("_"),
+        trimmed => trimmed.to_owned(),
+    }
+}
+
+/// Converts a decoded glTF image to 8 bit RGBA. Grey images are expanded, 16 bit and float channels rounded.
+fn gltf_image_to_rgba(data: &gltf::image::Data) -> RgbaImage {
+    use gltf::image::Format::*;
+    let (channels, size) = match data.format {
+        R8 => (1, 1),
+        R8G8 => (2, 1),
+        R8G8B8 => (3, 1),
+        R8G8B8A8 => (4, 1),
+        R16 => (1, 2),
+        R16G16 => (2, 2),
+        R16G16B16 => (3, 2),
+        R16G16B16A16 => (4, 2),
+        R32G32B32FLOAT => (3, 4),
+        R32G32B32A32FLOAT => (4, 4),
+    };
+    let mut pixels = Vec::with_capacity((data.width * data.height) as usize * 4);
+    for px in data.pixels.chunks_exact(channels * size) {
+        let c = |i: usize| -> u8 {
+            let b = &px[i * size..(i + 1) * size];
+            match size {
+                1 => b[0],
+                2 => (u16::from_ne_bytes([b[0], b[1]]) >> 8) as u8,
+                _ => {
+                    let v = f32::from_ne_bytes([b[0], b[1], b[2], b[3]]);
+                    (v.clamp(0.0, 1.0) * 255.0).round() as u8
+                }
+            }
+        };
+        pixels.extend_from_slice(&match channels {
+            1 => [c(0), c(0), c(0), 255],
+            2 => [c(0), c(0), c(0), c(1)],
+            3 => [c(0), c(1), c(2), 255],
+            _ => [c(0), c(1), c(2), c(3)],
+        });
+    }
+    RgbaImage {
+        width: data.width,
+        height: data.height,
+        pixels,
+    }
+}
+
+/// All nodes of the default scene (or the first scene) that instantiate a mesh, depth first.
+fn scene_mesh_nodes(gltf: &gltf::Document) -> Vec<Node<'_>> {
+    let mut out = Vec::new();
+    let mut stack: Vec<Node> = match gltf.default_scene().or_else(|| gltf.scenes().next()) {
+        Some(scene) => scene.nodes().collect(),
+        None => gltf.nodes().collect(),
+    };
+    stack.reverse();
+    while let Some(node) = stack.pop() {
+        if node.mesh().is_some() {
+            out.push(node.clone());
+        }
+        let mut children: Vec<Node> = node.children().collect();
+        children.reverse();
+        stack.extend(children);
+    }
+    out
+}
+
+/// Reorders the triangles so that all primitives of one material slot form a single MeshInfo.
+fn group_by_material(
+    mesh_info: &[MeshInfo],
+    triangles: &[Triangle],
+) -> (Vec<MeshInfo>, Vec<Triangle>) {
+    let mut materials: Vec<u32> = mesh_info.iter().map(|m| m.material).collect();
+    materials.sort_unstable();
+    materials.dedup();
+
+    let mut grouped_info = Vec::with_capacity(materials.len());
+    let mut grouped_triangles = Vec::with_capacity(triangles.len());
+    for material in materials {
+        let start = grouped_triangles.len();
+        for info in mesh_info.iter().filter(|m| m.material == material) {
+            let first = info.start_index_location as usize / 3;
+            let count = info.index_count as usize / 3;
+            grouped_triangles.extend_from_slice(&triangles[first..first + count]);
+        }
+        grouped_info.push(MeshInfo {
+            start_index_location: start as u32 * 3,
+            index_count: (grouped_triangles.len() - start) as u32 * 3,
+            material,
+            _padding: Default::default(),
+        });
+    }
+    (grouped_info, grouped_triangles)
+}
+
+/// Reflection on the x axis used by `negative_x_and_v0v2v1`.
+fn mirror_x() -> Matrix4<f32> {
+    Matrix4::new_nonuniform_scaling(&Vector3::new(-1.0, 1.0, 1.0))
+}
+
 #[inline]
-fn create_joint(mut mat4_init: Matrix4<f32>, name: String, parent: u32) -> RdJoint {
-    // may perform expensive checks ...
+fn create_joint(
+    mat4_init: Matrix4<f32>,
+    name: String,
+    parent: u32,
+) -> Result<RdJoint, TransformError> {
     debug!("node_to_joint mat4_init: {}", mat4_init);
-    mat4_init.m44 = 1.0;
-    let similarity: Similarity3<f32> = nalgebra::try_convert(mat4_init).unwrap();
-    debug!("similarity.scaling: {}", similarity.scaling());
-
-    let isometry: Isometry3<f32> = similarity.isometry;
-    let unit_quaternion = isometry.rotation;
+    // the bind pose keeps its position, scale is dropped
+    let (translation, unit_quaternion, scale) = decompose_trs(&mat4_init)?;
+    debug!("scale: {}", scale);
     let quaternion_raw = unit_quaternion.quaternion().coords;
 
-    let translation: Translation3<f32> = isometry.translation;
-
-    RdJoint {
+    Ok(RdJoint {
         name,
         parent,
         quaternion: [
@@ -914,9 +2130,11 @@ fn create_joint(mut mat4_init: Matrix4<f32>, name: String, parent: u32) -> RdJoi
             quaternion_raw.w,
         ],
         transition: [translation.x, translation.y, translation.z],
-    }
+    })
 }
 
+type ReadSkinOutput = (Vec<RdJoint>, Vec<Option<Matrix4<f32>>>);
+
 type ReadMeshOutput = Option<(u32, VertexFormat2, Vec<Triangle>, u32, Vec<MeshInfo>)>;
 
 fn find_first_mesh_instantiating_node(gltf: &gltf::Document, mesh_idx: usize) -> Option<usize> {
@@ -0,0 +1,395 @@
+use gltf::animation::util::ReadOutputs;
+use gltf::animation::{Channel, Interpolation};
+use nalgebra::{Quaternion, UnitQuaternion};
+use std::str::FromStr;
+
+use crate::rdm_data_anim::Frame;
+
+/// Frame rate used when channels must be resampled but no frame rate was requested.
+pub const DEFAULT_RESAMPLE_FPS: f32 = 30.0;
+
+/// A STEP key is preceded by a copy of the previous value this long before it (seconds), so that the linear
+/// playback of rdm keys jumps instead of blending.
+pub const STEP_HOLD: f32 = 1.0e-3;
+
+/// Scale keys closer than this to 1 (or to each other) count as identity (or uniform). Exporters leave noise around 1e-4.
+pub const SCALE_EPSILON: f32 = 1.0e-3;
+
+/// What to do with animated scale, which an rdm `Frame` can not store.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ScaleChannels {
+    /// Fail with a list of all joints with a non-identity scale channel.
+    Reject,
+    /// Fold uniform scale into the translations of all child joints. Fails for non-uniform scale.
+    /// Vertices bound to the scaled joint itself keep their size.
+    Bake,
+}
+
+impl FromStr for ScaleChannels {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "reject" => Ok(ScaleChannels::Reject),
+            "bake" => Ok(ScaleChannels::Bake),
+            _ => Err(format!("Invalid value for ScaleChannels: {}", input)),
+        }
+    }
+}
+
+pub fn is_identity_scale(v: &[f32; 3]) -> bool {
+    v.iter().all(|x| (x - 1.0).abs() < SCALE_EPSILON)
+}
+
+pub fn is_uniform_scale(v: &[f32; 3]) -> bool {
+    (v[0] - v[1]).abs() < SCALE_EPSILON && (v[0] - v[2]).abs() < SCALE_EPSILON
+}
+
+/// Keys of one glTF animation sampler.
+pub struct Sampler<const N: usize> {
+    times: Vec<f32>,
+    // CUBICSPLINE: in-tangent, value, out-tangent per key
+    values: Vec<[f32; N]>,
+    interpolation: Interpolation,
+}
+
+impl<const N: usize> Sampler<N> {
+    pub fn new(times: Vec<f32>, values: Vec<[f32; N]>, interpolation: Interpolation) -> Self {
+        let per_key = match interpolation {
+            Interpolation::CubicSpline => 3,
+            _ => 1,
+        };
+        assert_eq!(times.len() * per_key, values.len());
+        assert!(!times.is_empty());
+        Sampler {
+            times,
+            values,
+            interpolation,
+        }
+    }
+
+    pub fn times(&self) -> &[f32] {
+        &self.times
+    }
+
+    pub fn is_cubic(&self) -> bool {
+        self.interpolation == Interpolation::CubicSpline
+    }
+
+    pub fn is_step(&self) -> bool {
+        self.interpolation == Interpolation::Step
+    }
+
+    /// For STEP: the time of every key after the first, each preceded by the time at which the previous value
+    /// still holds. Empty for other interpolations.
+    pub fn step_times(&self) -> Vec<f32> {
+        if !self.is_step() {
+            return Vec::new();
+        }
+        self.times
+            .windows(2)
+            .flat_map(|w| [(w[1] - STEP_HOLD).max((w[0] + w[1]) / 2.0), w[1]])
+            .collect()
+    }
+
+    /// Time and value of the first key matching `predicate`.
+    pub fn find_key(&self, predicate: impl Fn(&[f32; N]) -> bool) -> Option<(f32, [f32; N])> {
+        (0..self.times.len())
+            .map(|k| (self.times[k], self.value(k)))
+            .find(|(_, v)| predicate(v))
+    }
+
+    fn value(&self, k: usize) -> [f32; N] {
+        match self.interpolation {
+            Interpolation::CubicSpline => self.values[3 * k + 1],
+            _ => self.values[k],
+        }
+    }
+
+    /// Hermite spline between key `k` and `k + 1`, `s` in 0..=1.
+    fn cubic(&self, k: usize, s: f32) -> [f32; N] {
+        let dt = self.times[k + 1] - self.times[k];
+        let v0 = self.values[3 * k + 1];
+        let b0 = self.values[3 * k + 2];
+        let a1 = self.values[3 * (k + 1)];
+        let v1 = self.values[3 * (k + 1) + 1];
+
+        let s2 = s * s;
+        let s3 = s2 * s;
+        let h00 = 2.0 * s3 - 3.0 * s2 + 1.0;
+        let h10 = s3 - 2.0 * s2 + s;
+        let h01 = -2.0 * s3 + 3.0 * s2;
+        let h11 = s3 - s2;
+
+        let mut out = [0.0; N];
+        for i in 0..N {
+            out[i] = h00 * v0[i] + h10 * dt * b0[i] + h01 * v1[i] + h11 * dt * a1[i];
+        }
+        out
+    }
+
+    /// Key index and position between key `k` and `k + 1`. `Err(k)` with the nearest key if `t` is outside the keys.
+    fn locate(&self, t: f32) -> Result<(usize, f32), usize> {
+        let last = self.times.len() - 1;
+        if t <= self.times[0] {
+            return Err(0);
+        }
+        if t >= self.times[last] {
+            return Err(last);
+        }
+        let k = self.times.partition_point(|&x| x <= t) - 1;
+        let s = (t - self.times[k]) / (self.times[k + 1] - self.times[k]);
+        Ok((k, s))
+    }
+}
+
+impl Sampler<3> {
+    pub fn sample(&self, t: f32) -> [f32; 3] {
+        match self.locate(t) {
+            Err(k) => self.value(k),
+            Ok((k, s)) => match self.interpolation {
+                Interpolation::Step => self.value(k),
+                Interpolation::Linear => {
+                    let (a, b) = (self.value(k), self.value(k + 1));
+                    [
+                        a[0] + (b[0] - a[0]) * s,
+                        a[1] + (b[1] - a[1]) * s,
+                        a[2] + (b[2] - a[2]) * s,
+                    ]
+                }
+                Interpolation::CubicSpline => self.cubic(k, s),
+            },
+        }
+    }
+}
+
+impl Sampler<4> {
+    /// Linear sampler of the rotations of `frames`, which store the inverse rotation.
+    pub fn from_frames(frames: &[Frame]) -> Self {
+        Sampler::new(
+            frames.iter().map(|f| f.time).collect(),
+            frames
+                .iter()
+                .map(|f| {
+                    let [x, y, z, w] = f.rotation;
+                    [x, y, z, -w]
+                })
+                .collect(),
+            Interpolation::Linear,
+        )
+    }
+
+    pub fn sample(&self, t: f32) -> UnitQuaternion<f32> {
+        let quat =
+            |v: [f32; 4]| UnitQuaternion::from_quaternion(Quaternion::new(v[3], v[0], v[1], v[2]));
+        match self.locate(t) {
+            Err(k) => quat(self.value(k)),
+            Ok((k, s)) => match self.interpolation {
+                Interpolation::Step => quat(self.value(k)),
+                Interpolation::Linear => {
+                    let a = quat(self.value(k));
+                    let mut b = quat(self.value(k + 1));
+                    // shortest path
+                    if a.coords.dot(&b.coords) < 0.0 {
+                        b = UnitQuaternion::new_unchecked(-b.into_inner());
+                    }
+                    a.try_slerp(&b, s, 1.0e-6)
+                        .unwrap_or_else(|| UnitQuaternion::new_normalize(a.lerp(&b, s)))
+                }
+                Interpolation::CubicSpline => quat(self.cubic(k, s)),
+            },
+        }
+    }
+}
+
+/// Rotation, translation and scale samplers of one node.
+#[derive(Default)]
+pub struct NodeSamplers {
+    pub rotation: Option<Sampler<4>>,
+    pub translation: Option<Sampler<3>>,
+    pub scale: Option<Sampler<3>>,
+}
+
+impl NodeSamplers {
+    /// Stores the sampler of `channel`. Returns false for morph target weights.
+    pub fn insert(&mut self, buffers: &[gltf::buffer::Data], channel: &Channel) -> bool {
+        let reader = channel.reader(|buffer| Some(&buffers[buffer.index()]));
+        let times: Vec<f32> = reader.read_inputs().unwrap().collect();
+        let interpolation = channel.sampler().interpolation();
+        match reader.read_outputs().unwrap() {
+            ReadOutputs::Rotations(rot) => {
+                let values = rot.into_f32().collect();
+                self.rotation = Some(Sampler::new(times, values, interpolation));
+                true
+            }
+            ReadOutputs::Translations(trans) => {
+                self.translation = Some(Sampler::new(times, trans.collect(), interpolation));
+                true
+            }
+            ReadOutputs::Scales(scale) => {
+                self.scale = Some(Sampler::new(times, scale.collect(), interpolation));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    pub fn has_cubic(&self) -> bool {
+        self.rotation
+            .as_ref()
+            .map(|s| s.is_cubic())
+            .unwrap_or(false)
+            || self
+                .translation
+                .as_ref()
+                .map(|s| s.is_cubic())
+                .unwrap_or(false)
+    }
+
+    pub fn has_step(&self) -> bool {
+        self.rotation.as_ref().map(|s| s.is_step()).unwrap_or(false)
+            || self
+                .translation
+                .as_ref()
+                .map(|s| s.is_step())
+                .unwrap_or(false)
+    }
+
+    /// `timeline` with the `step_times` of both channels that lie inside it.
+    pub fn step_timeline(&self, timeline: &[f32]) -> Vec<f32> {
+        let (first, last) = (timeline[0], timeline[timeline.len() - 1]);
+        let mut times = timeline.to_vec();
+        let steps = self.rotation.as_ref().map(|s| s.step_times());
+        let steps = steps
+            .into_iter()
+            .chain(self.translation.as_ref().map(|s| s.step_times()))
+            .flatten();
+        times.extend(steps.filter(|&t| t > first && t < last));
+        times.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        times.dedup_by(|a, b| (*a - *b).abs() < 1.0e-6);
+        times
+    }
+
+    /// Evaluates both channels on `timeline`. Missing channels use the node's rest pose.
+    pub fn resample(
+        &self,
+        timeline: &[f32],
+        rest_rotation: [f32; 4],
+        rest_translation: [f32; 3],
+    ) -> Vec<Frame> {
+        timeline
+            .iter()
+            .map(|&t| {
+                let r = match self.rotation.as_ref() {
+                    Some(s) => s.sample(t).into_inner().coords.into(),
+                    None => rest_rotation,
+                };
+                Frame {
+                    rotation: [r[0], r[1], r[2], -r[3]],
+                    translation: match self.translation.as_ref() {
+                        Some(s) => s.sample(t),
+                        None => rest_translation,
+                    },
+                    time: t,
+                }
+            })
+            .collect()
+    }
+}
+
+/// Times 0, 1/fps, 2/fps, … and `t_max` as last key.
+pub fn resample_timeline(t_max: f32, fps: f32) -> Vec<f32> {
+    // the tolerance avoids a duplicate last key when t_max * fps is a whole number
+    let steps = (t_max * fps - 1.0e-3).ceil().max(0.0) as usize;
+    let mut timeline: Vec<f32> = (0..steps).map(|i| i as f32 / fps).collect();
+    timeline.push(t_max);
+    timeline
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sample_step_linear_cubic() {
+        let step = Sampler::new(
+            vec![0.0, 1.0],
+            vec![[0.0; 3], [2.0; 3]],
+            Interpolation::Step,
+        );
+        assert_eq!(step.sample(0.5), [0.0; 3]);
+        assert_eq!(step.sample(1.5), [2.0; 3]);
+
+        let linear = Sampler::new(
+            vec![0.0, 1.0],
+            vec![[0.0; 3], [2.0; 3]],
+            Interpolation::Linear,
+        );
+        assert_eq!(linear.sample(0.25), [0.5; 3]);
+        assert_eq!(linear.sample(-1.0), [0.0; 3]);
+
+        // zero tangents: smoothstep
+        let cubic = Sampler::new(
+            vec![0.0, 1.0],
+            vec![[0.0; 3], [0.0; 3], [0.0; 3], [0.0; 3], [2.0; 3], [0.0; 3]],
+            Interpolation::CubicSpline,
+        );
+        assert_eq!(cubic.sample(0.5), [1.0; 3]);
+        assert!(cubic.sample(0.25)[0] < 0.5);
+    }
+
+    #[test]
+    fn slerp_shortest_path() {
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        // identity and 90° around y, the second one stored with negated sign
+        let rot = Sampler::new(
+            vec![0.0, 1.0],
+            vec![[0.0, 0.0, 0.0, 1.0], [0.0, -half, 0.0, -half]],
+            Interpolation::Linear,
+        );
+        let angle = rot.sample(0.5).angle();
+        assert!((angle - std::f32::consts::FRAC_PI_4).abs() < 1e-5);
+    }
+
+    #[test]
+    fn scale_keys() {
+        let scale = Sampler::new(
+            vec![0.0, 1.0, 2.0],
+            vec![[1.0; 3], [2.0; 3], [1.0, 3.0, 1.0]],
+            Interpolation::Linear,
+        );
+        assert_eq!(
+            scale.find_key(|v| !is_identity_scale(v)),
+            Some((1.0, [2.0; 3]))
+        );
+        assert_eq!(
+            scale.find_key(|v| !is_uniform_scale(v)),
+            Some((2.0, [1.0, 3.0, 1.0]))
+        );
+        assert_eq!("bake".parse(), Ok(ScaleChannels::Bake));
+    }
+
+    #[test]
+    fn step_hold() {
+        let step = NodeSamplers {
+            translation: Some(Sampler::new(
+                vec![0.0, 0.5, 2.0],
+                vec![[0.0; 3], [1.0; 3], [2.0; 3]],
+                Interpolation::Step,
+            )),
+            ..Default::default()
+        };
+        let timeline = step.step_timeline(&[0.0, 1.0]);
+        assert_eq!(timeline, vec![0.0, 0.5 - STEP_HOLD, 0.5, 1.0]);
+        let frames = step.resample(&timeline, [0.0, 0.0, 0.0, 1.0], [0.0; 3]);
+        assert_eq!(frames[1].translation, [0.0; 3]);
+        assert_eq!(frames[2].translation, [1.0; 3]);
+    }
+
+    #[test]
+    fn timeline() {
+        assert_eq!(resample_timeline(0.1, 30.0).len(), 4);
+        assert_eq!(*resample_timeline(0.1, 30.0).last().unwrap(), 0.1);
+        assert_eq!(resample_timeline(0.0, 30.0), vec![0.0]);
+    }
+}
@@ -1,10 +1,7 @@
-use binrw::BinReaderExt;
 use rdm_data_main::*;
 use rdm_data_main::{MeshInfo, RdmFile};
 use std::path::Path;
 
-use std::fs::File;
-
 use std::str;
 
 use half::f16;
@@ -17,9 +14,14 @@ extern crate log;
 #[macro_use]
 extern crate approx;
 
+pub mod cfg;
+pub mod dds;
+pub mod dds_encode;
 pub mod gltf_export;
 pub mod gltf_reader;
+pub mod gltf_reader_anim;
 pub mod gltf_reader_vertex;
+pub mod rda;
 pub mod rdm_anim;
 pub mod rdm_material;
 pub mod vertex;
@@ -52,15 +54,89 @@ pub struct RdJoint {
     parent: u32,
 }
 
+impl RdJoint {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Bind pose position in model space.
+    pub fn transition(&self) -> [f32; 3] {
+        self.transition
+    }
+
+    /// Bind pose rotation in model space, `[x, y, z, w]`.
+    pub fn quaternion(&self) -> [f32; 4] {
+        self.quaternion
+    }
+}
+
 impl RdModell {
     pub fn has_skin(&self) -> bool {
         self.joints.is_some()
     }
 
+    /// File header of the rdm the model was read from, `None` for imported models.
+    pub fn file_header(&self) -> Option<RdmFileHeader> {
+        self.rdmf.as_ref().map(|rdm| rdm.file_header)
+    }
+
+    /// Texture path of every material as stored in the rdm, see [`RdMaterial::discover`].
+    pub fn material_textures(&self) -> Vec<Option<String>> {
+        match self.rdmf.as_ref() {
+            Some(rdm) => rdm.header1.material_textures(),
+            None => Vec::new(),
+        }
+    }
+
     pub fn add_anim(&mut self, anim: RdAnim) {
         self.anim = Some(anim);
     }
 
+    /// Applies `transform` to the vertices, the skeleton and an attached animation.
+    ///
+    /// A transform with a negative determinant also reverses the triangle winding. Fails without changing the
+    /// model if `transform` is not invertible.
+    pub fn transform(
+        &mut self,
+        transform: Matrix4<f32>,
+    ) -> Result<(), vertex_transform::TransformError> {
+        let mut context = vertex_transform::TransformContext::new(transform)?;
+
+        let mut streams = self.vertex.decode_streams();
+        for position in streams.positions.iter_mut() {
+            *position = context.transform_position(*position).into();
+        }
+        for normal in streams.normals.iter_mut().flatten() {
+            *normal = context.transform_normal(*normal).into();
+        }
+        for vectors in [streams.tangents.as_mut(), streams.bitangents.as_mut()]
+            .into_iter()
+            .flatten()
+        {
+            for v in vectors.iter_mut() {
+                *v = context.transform_direction(*v).into();
+            }
+        }
+        self.vertex.encode_streams(&streams);
+
+        if context.is_mirroring() {
+            for t in self.triangle_indices.iter_mut() {
+                t.indices.swap(1, 2);
+            }
+        }
+
+        if let Some(joints) = self.joints.as_mut() {
+            for joint in joints.iter_mut() {
+                context.transform_joint(joint);
+            }
+        }
+
+        if let (Some(anim), Some(joints)) = (self.anim.as_mut(), self.joints.as_ref()) {
+            anim.transform(transform, joints)?;
+        }
+        Ok(())
+    }
+
     pub fn add_skin(&mut self) {
         let rdm = self.rdmf.as_ref().unwrap();
 
@@ -105,9 +181,7 @@ impl RdModell {
     }
 
     fn new(buf: Vec<u8>) -> Self {
-        let mut reader = std::io::Cursor::new(&buf);
-
-        let rdm: RdmFile<RdmKindMesh> = reader.read_le().unwrap();
+        let rdm = RdmFile::<RdmKindMesh>::from_bytes(&buf).unwrap_or_else(|e| panic!("{}", e));
 
         let vvert = VertexFormat2::read_format_via_data(&rdm);
         info!(
@@ -150,11 +224,7 @@ pub struct Triangle {
 
 impl<P: AsRef<Path>> From<P> for RdModell {
     fn from(f_path: P) -> Self {
-        let mut f = File::open(&f_path).unwrap();
-        let metadata = f.metadata().unwrap();
-        let len = metadata.len() as usize;
-        let mut buffer = vec![0; len];
-        std::io::Read::read_exact(&mut f, &mut buffer).expect("I/O ERROR");
+        let buffer = rda::read(f_path.as_ref()).expect("I/O ERROR");
 
         let buffer_len = buffer.len();
 
@@ -180,4 +250,139 @@ mod tests_intern {
         assert_eq!(rdm.vertex.get_size(), 8);
         assert_eq!(rdm.triangle_indices.len() * 3, 78);
     }
+
+    #[test]
+    fn transform_singular() {
+        let bytes = include_bytes!("../rdm/basalt_crusher_others_lod2.rdm");
+        let mut rdm = RdModell::new(bytes.to_vec());
+        let positions = rdm
+            .vertex
+            .decode::<3>(vertex::UniqueIdentifier::Position, 0);
+
+        let flat = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 0.0, 1.0));
+        assert!(matches!(
+            rdm.transform(flat),
+            Err(vertex_transform::TransformError::Singular(_))
+        ));
+        let nan = Matrix4::new_scaling(f32::NAN);
+        assert!(matches!(
+            rdm.transform(nan),
+            Err(vertex_transform::TransformError::NotFinite(_))
+        ));
+        assert_eq!(
+            rdm.vertex
+                .decode::<3>(vertex::UniqueIdentifier::Position, 0),
+            positions
+        );
+    }
+
+    #[test]
+    fn transform_mirror_and_scale() {
+        let bytes = include_bytes!("../rdm/basalt_crusher_others_lod2.rdm");
+        let mut rdm = RdModell::new(bytes.to_vec());
+        rdm.add_skin();
+
+        let positions = rdm
+            .vertex
+            .decode::<3>(vertex::UniqueIdentifier::Position, 0);
+        let triangle = rdm.triangle_indices[0];
+        let joint = rdm.joints.as_ref().unwrap()[1].clone();
+
+        let mirror = Matrix4::new_nonuniform_scaling(&Vector3::new(-2.0, 2.0, 2.0));
+        rdm.transform(mirror).unwrap();
+
+        let transformed = rdm
+            .vertex
+            .decode::<3>(vertex::UniqueIdentifier::Position, 0);
+        for (p, q) in positions
+            .unwrap()
+            .iter()
+            .zip(transformed.unwrap().iter())
+            .take(32)
+        {
+            assert_relative_eq!(-2.0 * p[0], q[0], epsilon = 1e-2);
+            assert_relative_eq!(2.0 * p[1], q[1], epsilon = 1e-2);
+        }
+        assert_eq!(rdm.triangle_indices[0].indices[1], triangle.indices[2]);
+        assert_eq!(rdm.triangle_indices[0].indices[2], triangle.indices[1]);
+
+        let mirrored = &rdm.joints.as_ref().unwrap()[1];
+        assert_relative_eq!(
+            mirrored.transition[0],
+            -2.0 * joint.transition[0],
+            epsilon = 1e-4
+        );
+        assert_relative_eq!(
+            mirrored.transition[2],
+            2.0 * joint.transition[2],
+            epsilon = 1e-4
+        );
+        assert_relative_eq!(mirrored.quaternion[0], joint.quaternion[0], epsilon = 1e-4);
+        assert_relative_eq!(mirrored.quaternion[1], -joint.quaternion[1], epsilon = 1e-4);
+        assert_relative_eq!(mirrored.quaternion[3], joint.quaternion[3], epsilon = 1e-4);
+
+        // non-uniform scale: tangents stay in the surface, perpendicular to the normals
+        let mut rdm = RdModell::new(bytes.to_vec());
+        let before = rdm.vertex.decode_streams();
+        let stretch = Matrix4::new_nonuniform_scaling(&Vector3::new(1.0, 2.5, 0.5))
+            * Matrix4::from_euler_angles(0.3, 0.0, 0.7);
+        rdm.transform(stretch).unwrap();
+        let after = rdm.vertex.decode_streams();
+        let dots = |streams: &vertex::VertexStreams| {
+            let normals = streams.normals.as_ref().unwrap().iter();
+            let tangents = streams.tangents.as_ref().unwrap().iter();
+            normals
+                .zip(tangents)
+                .map(|(n, t)| Vector3::from(*n).dot(&Vector3::from(*t)))
+                .collect::<Vec<f32>>()
+        };
+        let mut checked = 0;
+        for (before, after) in dots(&before).into_iter().zip(dots(&after)) {
+            // smoothed tangents of the source are not always perpendicular
+            if before.abs() < 0.01 {
+                assert!(after.abs() < 0.05, "{}", after);
+                checked += 1;
+            }
+        }
+        assert!(checked > 1000);
+    }
+
+    #[test]
+    fn unit_conversion_roundtrip() {
+        use vertex_transform::{Axis, UnitConversion};
+
+        // Blender: Z up, -Y forward
+        let conv = UnitConversion::new(2.0, Axis::Z, Axis::NegY).unwrap();
+        let up = conv.matrix().transform_vector(&Vector3::z());
+        assert_eq!([up.x, up.y, up.z], [0.0, 2.0, 0.0]);
+        assert!(UnitConversion::new(1.0, Axis::Z, Axis::NegZ).is_err());
+
+        let bytes = include_bytes!("../rdm/basalt_crusher_others_lod2.rdm");
+        let mut rdm = RdModell::new(bytes.to_vec());
+        rdm.add_skin();
+        let positions = rdm
+            .vertex
+            .decode::<3>(vertex::UniqueIdentifier::Position, 0);
+        let joint = rdm.joints.as_ref().unwrap()[2].clone();
+
+        rdm.transform(conv.matrix()).unwrap();
+        rdm.transform(conv.matrix().try_inverse().unwrap()).unwrap();
+
+        let restored = rdm
+            .vertex
+            .decode::<3>(vertex::UniqueIdentifier::Position, 0);
+        for (p, q) in positions.unwrap().iter().zip(restored.unwrap().iter()) {
+            for i in 0..3 {
+                assert_relative_eq!(p[i], q[i], epsilon = 1e-2, max_relative = 2e-3);
+            }
+        }
+        let restored_joint = &rdm.joints.as_ref().unwrap()[2];
+        for i in 0..3 {
+            assert_relative_eq!(
+                joint.transition[i],
+                restored_joint.transition[i],
+                epsilon = 1e-4
+            );
+        }
+    }
 }
@@ -0,0 +1,456 @@
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    fs::File,
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock},
+    time::SystemTime,
+};
+
+use binrw::{BinRead, BinReaderExt};
+use flate2::read::ZlibDecoder;
+
+/// Separates the archive from the path of a file inside it: `data0.rda!/graphics/foo_lod0.rdm`.
+pub const ARCHIVE_SEPARATOR: &str = "!";
+
+const MAGIC: &[u8; 18] = b"Resource File V2.2";
+
+const FLAG_COMPRESSED: u32 = 1;
+const FLAG_ENCRYPTED: u32 = 2;
+const FLAG_RESIDENT: u32 = 4;
+const FLAG_DELETED: u32 = 8;
+
+/// Upper bound for the buffer reserved before inflating, the sizes in the archive are not trusted.
+const MAX_PREALLOC: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug)]
+pub enum RdaError {
+    Io(io::Error),
+    /// Not an RDA file or an RDA version other than 2.2.
+    NotRda,
+    /// The archive uses encrypted blocks.
+    Encrypted,
+    /// A block or directory entry points outside of the archive.
+    Corrupt(String),
+    NotFound(String),
+}
+
+impl fmt::Display for RdaError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RdaError::Io(e) => write!(f, "{}", e),
+            RdaError::NotRda => write!(f, "not an RDA archive (Resource File V2.2)"),
+            RdaError::Encrypted => write!(f, "encrypted RDA blocks are not supported"),
+            RdaError::Corrupt(msg) => write!(f, "corrupt RDA archive: {}", msg),
+            RdaError::NotFound(name) => write!(f, "{:?} is not in the archive", name),
+        }
+    }
+}
+
+impl std::error::Error for RdaError {}
+
+impl From<io::Error> for RdaError {
+    fn from(e: io::Error) -> Self {
+        RdaError::Io(e)
+    }
+}
+
+impl From<binrw::Error> for RdaError {
+    fn from(e: binrw::Error) -> Self {
+        match e {
+            binrw::Error::Io(e) => RdaError::Io(e),
+            e => RdaError::Corrupt(e.to_string()),
+        }
+    }
+}
+
+impl From<RdaError> for io::Error {
+    fn from(e: RdaError) -> Self {
+        match e {
+            RdaError::Io(e) => e,
+            RdaError::NotFound(_) => io::Error::new(io::ErrorKind::NotFound, e),
+            e => io::Error::new(io::ErrorKind::InvalidData, e),
+        }
+    }
+}
+
+#[derive(Debug, BinRead)]
+#[br(little)]
+struct RdaHeader {
+    magic: [u8; 18],
+    _unknown: [u8; 766],
+    first_block: u64,
+}
+
+#[derive(Debug, BinRead)]
+#[br(little)]
+struct BlockHeader {
+    flags: u32,
+    file_count: u32,
+    directory_size: u64,
+    directory_size_decompressed: u64,
+    next_block: u64,
+}
+
+#[derive(Debug, BinRead)]
+#[br(little)]
+struct DirEntry {
+    name: [u16; 260],
+    offset: u64,
+    compressed_size: u64,
+    size: u64,
+    timestamp: u64,
+    _unknown: u64,
+}
+
+/// Data of a memory resident block: all its files in one (compressed) buffer.
+#[derive(Debug, Clone, Copy)]
+struct ResidentBuffer {
+    offset: u64,
+    compressed_size: u64,
+    size: u64,
+    compressed: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct RdaEntry {
+    /// Path inside the archive, e.g. `data/graphics/buildings/mill/rdm/mill_lod0.rdm`.
+    pub name: String,
+    pub size: u64,
+    /// Unix time of the last modification.
+    pub timestamp: u64,
+    offset: u64,
+    compressed_size: u64,
+    compressed: bool,
+    resident: Option<ResidentBuffer>,
+}
+
+/// Resource archive (`.rda`, version 2.2) of the Anno games. Unencrypted blocks only.
+#[derive(Debug)]
+pub struct RdaArchive {
+    path: PathBuf,
+    entries: Vec<RdaEntry>,
+}
+
+impl RdaArchive {
+    pub fn open(path: &Path) -> Result<Self, RdaError> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let header: RdaHeader = file.read_le()?;
+        if &header.magic != MAGIC {
+            return Err(RdaError::NotRda);
+        }
+
+        let mut entries = Vec::new();
+        let mut block_offset = header.first_block;
+        while block_offset < len {
+            file.seek(SeekFrom::Start(block_offset))?;
+            let block: BlockHeader = file.read_le()?;
+            if block.next_block <= block_offset && block.next_block < len {
+                return Err(RdaError::Corrupt(format!(
+                    "block at {} links back to {}",
+                    block_offset, block.next_block
+                )));
+            }
+            if block.flags & FLAG_DELETED == 0 {
+                read_block(&mut file, block_offset, &block, &mut entries)?;
+            }
+            block_offset = block.next_block;
+        }
+        debug!("{:?}: {} entries", path, entries.len());
+
+        Ok(RdaArchive {
+            path: path.to_path_buf(),
+            entries,
+        })
+    }
+
+    pub fn entries(&self) -> &[RdaEntry] {
+        &self.entries
+    }
+
+    /// The entry `name`, compared case insensitive with `/` and `\` treated alike. A name that is not the
+    /// full path matches the entry ending in `/<name>`, e.g. `graphics/foo.rdm` finds `data/graphics/foo.rdm`.
+    /// Later blocks replace files of earlier ones.
+    pub fn find(&self, name: &str) -> Option<&RdaEntry> {
+        let name = normalize(name);
+        let suffix = format!("/{}", name);
+        let entries = self.entries.iter().rev();
+        let mut by_suffix = None;
+        for entry in entries {
+            let entry_name = normalize(&entry.name);
+            if entry_name == name {
+                return Some(entry);
+            }
+            if by_suffix.is_none() && entry_name.ends_with(&suffix) {
+                by_suffix = Some(entry);
+            }
+        }
+        by_suffix
+    }
+
+    pub fn read(&self, entry: &RdaEntry) -> Result<Vec<u8>, RdaError> {
+        let mut file = File::open(&self.path)?;
+        let (data, offset) = match entry.resident {
+            Some(buffer) => {
+                file.seek(SeekFrom::Start(buffer.offset))?;
+                let raw = read_exact(&mut file, buffer.compressed_size)?;
+                let data = if buffer.compressed {
+                    inflate(&raw, buffer.size)?
+                } else {
+                    raw
+                };
+                (data, entry.offset)
+            }
+            None => {
+                file.seek(SeekFrom::Start(entry.offset))?;
+                (read_exact(&mut file, entry.compressed_size)?, 0)
+            }
+        };
+        let outside = || RdaError::Corrupt(format!("{} is outside of its block", entry.name));
+        let end = offset
+            .checked_add(entry.compressed_size)
+            .ok_or_else(outside)?;
+        let range = usize::try_from(offset).map_err(|_| outside())?
+            ..usize::try_from(end).map_err(|_| outside())?;
+        let raw = data.get(range).ok_or_else(outside)?;
+        if entry.compressed {
+            inflate(raw, entry.size)
+        } else {
+            Ok(raw.to_vec())
+        }
+    }
+
+    /// Reads the file `name`, see [`find`](Self::find).
+    pub fn read_file(&self, name: &str) -> Result<Vec<u8>, RdaError> {
+        let entry = self
+            .find(name)
+            .ok_or_else(|| RdaError::NotFound(name.to_owned()))?;
+        self.read(entry)
+    }
+
+    /// Writes every entry whose name starts with `prefix` below `dir`, keeping the archive directories.
+    /// Returns the written files.
+    pub fn extract(&self, prefix: &str, dir: &Path) -> Result<Vec<PathBuf>, RdaError> {
+        let prefix = normalize(prefix);
+        let mut written = Vec::new();
+        for entry in &self.entries {
+            let name = normalize(&entry.name);
+            if !name.starts_with(&prefix) {
+                continue;
+            }
+            if name.split('/').any(|c| c == "..") {
+                return Err(RdaError::Corrupt(format!(
+                    "entry {} leaves the directory",
+                    entry.name
+                )));
+            }
+            let file = dir.join(entry.name.replace('\\', "/").trim_start_matches('/'));
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&file, self.read(entry)?)?;
+            written.push(file);
+        }
+        Ok(written)
+    }
+}
+
+/// Splits `data0.rda!/graphics/foo.rdm` into archive and path inside it.
+pub fn split_archive_path(path: &Path) -> Option<(PathBuf, String)> {
+    let text = path.to_str()?;
+    let lower = text.to_ascii_lowercase();
+    let pos = lower.find(&format!(".rda{}", ARCHIVE_SEPARATOR))? + ".rda".len();
+    let inner = text[pos + ARCHIVE_SEPARATOR.len()..].trim_start_matches(['/', '\\']);
+    Some((PathBuf::from(&text[..pos]), inner.to_owned()))
+}
+
+/// Parsed archive of [`cached`] with the modification time and length of the file it was read from.
+struct CachedArchive {
+    modified: Option<SystemTime>,
+    len: u64,
+    archive: Arc<RdaArchive>,
+}
+
+/// The archive at `path`, only parsed again when the file changed since the last call.
+fn cached(path: &Path) -> Result<Arc<RdaArchive>, RdaError> {
+    static ARCHIVES: OnceLock<Mutex<HashMap<PathBuf, CachedArchive>>> = OnceLock::new();
+    let metadata = fs::metadata(path)?;
+    let (modified, len) = (metadata.modified().ok(), metadata.len());
+    let mut archives = ARCHIVES
+        .get_or_init(Default::default)
+        .lock()
+        .unwrap_or_else(|e| e.into_inner());
+    if let Some(cached) = archives.get(path) {
+        if cached.modified.is_some() && cached.modified == modified && cached.len == len {
+            return Ok(Arc::clone(&cached.archive));
+        }
+    }
+    let archive = Arc::new(RdaArchive::open(path)?);
+    archives.insert(
+        path.to_path_buf(),
+        CachedArchive {
+            modified,
+            len,
+            archive: Arc::clone(&archive),
+        },
+    );
+    Ok(archive)
+}
+
+/// Reads `path` from the file system, or from an archive for paths like `data0.rda!/graphics/foo.rdm`.
+/// The directory of an archive is read once and reused by later calls.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+    match split_archive_path(path) {
+        Some((archive, name)) => Ok(cached(&archive)?.read_file(&name)?),
+        None => fs::read(path),
+    }
+}
+
+/// Like [`Path::is_file`], also for files in archives, see [`read`].
+pub fn is_file(path: &Path) -> bool {
+    match split_archive_path(path) {
+        Some((archive, name)) => cached(&archive)
+            .map(|a| a.find(&name).is_some())
+            .unwrap_or(false),
+        None => path.is_file(),
+    }
+}
+
+fn read_block(
+    file: &mut File,
+    block_offset: u64,
+    block: &BlockHeader,
+    entries: &mut Vec<RdaEntry>,
+) -> Result<(), RdaError> {
+    if block.flags & FLAG_ENCRYPTED != 0 {
+        return Err(RdaError::Encrypted);
+    }
+    let compressed = block.flags & FLAG_COMPRESSED != 0;
+    let directory_offset = block_offset
+        .checked_sub(block.directory_size)
+        .ok_or_else(|| RdaError::Corrupt(format!("directory of block at {}", block_offset)))?;
+    file.seek(SeekFrom::Start(directory_offset))?;
+    let raw = read_exact(file, block.directory_size)?;
+    let directory = if compressed {
+        inflate(&raw, block.directory_size_decompressed)?
+    } else {
+        raw
+    };
+
+    let resident = if block.flags & FLAG_RESIDENT != 0 {
+        let sizes_offset = directory_offset
+            .checked_sub(16)
+            .ok_or_else(|| RdaError::Corrupt(format!("resident block at {}", block_offset)))?;
+        file.seek(SeekFrom::Start(sizes_offset))?;
+        let compressed_size: u64 = file.read_le()?;
+        let size: u64 = file.read_le()?;
+        let offset = sizes_offset
+            .checked_sub(compressed_size)
+            .ok_or_else(|| RdaError::Corrupt(format!("resident block at {}", block_offset)))?;
+        Some(ResidentBuffer {
+            offset,
+            compressed_size,
+            size,
+            compressed,
+        })
+    } else {
+        None
+    };
+
+    let mut reader = io::Cursor::new(directory);
+    for _ in 0..block.file_count {
+        let e: DirEntry = reader.read_le()?;
+        let end = e.name.iter().position(|c| *c == 0).unwrap_or(e.name.len());
+        entries.push(RdaEntry {
+            name: String::from_utf16_lossy(&e.name[..end]),
+            size: e.size,
+            timestamp: e.timestamp,
+            offset: e.offset,
+            compressed_size: e.compressed_size,
+            // files of a resident block are only compressed as part of the buffer
+            compressed: compressed && resident.is_none(),
+            resident,
+        });
+    }
+    Ok(())
+}
+
+fn read_exact(file: &mut File, len: u64) -> Result<Vec<u8>, RdaError> {
+    let mut buffer = Vec::new();
+    file.take(len).read_to_end(&mut buffer)?;
+    if buffer.len() as u64 != len {
+        return Err(RdaError::Corrupt(String::from("unexpected end of file")));
+    }
+    Ok(buffer)
+}
+
+fn inflate(data: &[u8], size: u64) -> Result<Vec<u8>, RdaError> {
+    let mut out = Vec::with_capacity(size.min(MAX_PREALLOC) as usize);
+    ZlibDecoder::new(data).read_to_end(&mut out)?;
+    if out.len() as u64 != size {
+        return Err(RdaError::Corrupt(format!(
+            "inflated {} bytes, expected {}",
+            out.len(),
+            size
+        )));
+    }
+    Ok(out)
+}
+
+fn normalize(name: &str) -> String {
+    name.replace('\\', "/")
+        .trim_start_matches('/')
+        .to_ascii_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn archive_path() {
+        assert_eq!(
+            split_archive_path(Path::new("maindata/data0.rda!/graphics/foo_lod0.rdm")),
+            Some((
+                PathBuf::from("maindata/data0.rda"),
+                String::from("graphics/foo_lod0.rdm")
+            ))
+        );
+        assert_eq!(
+            split_archive_path(Path::new("Data0.RDA!data\\foo.dds")),
+            Some((PathBuf::from("Data0.RDA"), String::from("data\\foo.dds")))
+        );
+        assert_eq!(split_archive_path(Path::new("rdm/foo!.rdm")), None);
+    }
+
+    #[test]
+    fn find_by_suffix() {
+        let entry = |name: &str| RdaEntry {
+            name: name.to_owned(),
+            size: 0,
+            timestamp: 0,
+            offset: 0,
+            compressed_size: 0,
+            compressed: false,
+            resident: None,
+        };
+        let archive = RdaArchive {
+            path: PathBuf::new(),
+            entries: vec![
+                entry("data/graphics/foo.rdm"),
+                entry("data/graphics/other/foo.rdm"),
+                entry("data/graphics/bar.rdm"),
+            ],
+        };
+        let found = |name| archive.find(name).map(|e| e.name.as_str());
+        assert_eq!(found("/Graphics/foo.rdm"), Some("data/graphics/foo.rdm"));
+        assert_eq!(
+            found("data\\graphics\\bar.rdm"),
+            Some("data/graphics/bar.rdm")
+        );
+        assert_eq!(found("other/foo.rdm"), Some("data/graphics/other/foo.rdm"));
+        assert_eq!(found("aphics/bar.rdm"), None);
+    }
+}
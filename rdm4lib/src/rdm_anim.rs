@@ -1,11 +1,12 @@
-use binrw::BinReaderExt;
 use std::path::Path;
 
 use crate::{
     rdm_data_anim::Frame,
-    rdm_data_main::{RdmFile, RdmKindAnim},
+    rdm_data_main::{RdmFile, RdmFileHeader, RdmKindAnim},
+    vertex_transform::{TransformContext, TransformError},
+    RdJoint,
 };
-use std::fs::File;
+use nalgebra::Matrix4;
 
 #[derive(Debug, Clone)]
 pub struct FrameCollection {
@@ -18,12 +19,13 @@ pub struct RdAnim {
     pub time_max: u32,
     pub name: String,
     pub anim_vec: Vec<FrameCollection>,
+    /// Version of the rdm the animation was read from, kept by `RdAnimWriter2`.
+    pub file_header: RdmFileHeader,
 }
 
 impl RdAnim {
     pub fn new(buffer: Vec<u8>, name_anim: String) -> Self {
-        let mut reader = std::io::Cursor::new(&buffer);
-        let rdmm: RdmFile<RdmKindAnim> = reader.read_le().unwrap();
+        let rdmm = RdmFile::<RdmKindAnim>::from_bytes(&buffer).unwrap_or_else(|e| panic!("{}", e));
         let v = &rdmm.header1.meta_anim.anims;
 
         let time_max = rdmm.header1.meta_anim.time_max;
@@ -44,15 +46,40 @@ impl RdAnim {
             anim_vec,
             name: name_anim,
             time_max,
+            file_header: rdmm.file_header,
         }
     }
+
+    /// Applies `transform` to all keys. `joints` is the skeleton the animation targets,
+    /// it is needed to tell root joints (global keys) apart from child joints (local keys).
+    pub fn transform(
+        &mut self,
+        transform: Matrix4<f32>,
+        joints: &[RdJoint],
+    ) -> Result<(), TransformError> {
+        let context = TransformContext::new(transform)?;
+        for collection in self.anim_vec.iter_mut() {
+            let is_root = match joints.iter().find(|j| j.name == collection.name) {
+                Some(joint) => joint.parent == u32::MAX,
+                None => {
+                    warn!(
+                        "Animation target {} not in skeleton. Treating it as child joint.",
+                        collection.name
+                    );
+                    false
+                }
+            };
+            for frame in collection.frames.iter_mut() {
+                context.transform_frame(frame, is_root);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl<P: AsRef<Path>> From<P> for RdAnim {
     fn from(f_path: P) -> Self {
-        let mut f = File::open(&f_path).unwrap();
-        let mut buffer = Vec::new();
-        std::io::Read::read_to_end(&mut f, &mut buffer).ok();
+        let buffer = crate::rda::read(f_path.as_ref()).unwrap();
 
         let buffer_len = buffer.len();
         info!("loaded {:?} into buffer", f_path.as_ref().to_str().unwrap());
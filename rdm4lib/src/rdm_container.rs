@@ -157,7 +157,8 @@ where
     T: RdmRead,
 {
     #[br(assert(!T_IS_PARTSIZED
-        || TypeId::of::<RdmHeader1>() == TypeId::of::<T>() && (info.part_size == 48 || info.part_size == 52)
+        // RdmHeader1 is 48 or 52 bytes, larger ones are read up to the known 48
+        || TypeId::of::<RdmHeader1>() == TypeId::of::<T>() && info.part_size as usize >= T::get_struct_byte_size()
         || info.part_size as usize == T::get_struct_byte_size(),
      "Struct ({}) size mismatch expected {} but got {}",std::any::type_name::<T>(),T::get_struct_byte_size(),info.part_size))
     ]
@@ -48,6 +48,7 @@ pub struct Frame {
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     use super::*;
     use binrw::{BinReaderExt, BinWriterExt};
@@ -90,7 +91,16 @@ pub struct RdAnimWriter2 {
     export: RdmFile<RdmKindAnim>,
 }
 
+/// Default for [`anim_file_name`]: `<model>_<anim>`, as in `basalt_crusher_others_work01`.
+pub const ANIM_NAME_TEMPLATE: &str = "{model}_{anim}";
+
+/// Replaces `{model}` and `{anim}` in `template`. No extension is added.
+pub fn anim_file_name(template: &str, model: &str, anim: &str) -> String {
+    template.replace("{model}", model).replace("{anim}", anim)
+}
+
 impl RdAnimWriter2 {
+    /// Writes into `dir` as `<anim>.rdm`. If `dir` is a file, `<file stem>_<anim>.rdm` is written next to it.
     pub fn write_anim_rdm(self, dir: Option<PathBuf>, create_new: bool) {
         let mut file = dir.unwrap_or_else(|| {
             let f = PathBuf::from("rdm_out");
@@ -98,12 +108,17 @@ impl RdAnimWriter2 {
             f
         });
         if file.is_dir() {
-            file.push(self.name);
+            file.push(&self.name);
         } else {
             let n = file.file_stem().unwrap();
-            let anim_name = format!("{}_{}", n.to_string_lossy(), self.name);
+            let anim_name = anim_file_name(ANIM_NAME_TEMPLATE, &n.to_string_lossy(), &self.name);
             file.set_file_name(anim_name);
         }
+        self.write_anim_rdm_as(file, create_new);
+    }
+
+    /// Writes to `file` with the extension set to `rdm`.
+    pub fn write_anim_rdm_as(self, mut file: PathBuf, create_new: bool) {
         file.set_extension("rdm");
 
         let mut writer = OpenOptions::new()
@@ -123,8 +138,10 @@ impl RdAnimWriter2 {
         use super::*;
 
         let data = include_bytes!("../rdm/basalt_crusher_others_idle01.rdm");
-        let mut reader = std::io::Cursor::new(&data);
-        let mut anim: RdmFile<RdmKindAnim> = reader.read_le().unwrap();
+        let mut anim = RdmFile::<RdmKindAnim>::from_bytes(data).unwrap();
+        anim.file_header = anim
+            .file_header
+            .with_unknown_fields_of(anim_input.file_header);
 
         let export_name = br"G:\graphic\danny\Anno5\preproduction\buildings\others\basalt_crusher_others\scenes\basalt_crusher_others_idle01_01.max";
 
@@ -1,11 +1,12 @@
 use std::{
     any::TypeId,
+    fmt,
     fs::{self, OpenOptions},
     io::SeekFrom,
     path::PathBuf,
 };
 
-use binrw::{binrw, BinWriterExt};
+use binrw::{binrw, BinRead, BinReaderExt, BinWriterExt};
 use std::marker::PhantomData;
 
 use crate::{rdm_container::*, rdm_data_anim::AnimMeta, RdModell};
@@ -191,7 +192,23 @@ pub struct RdmHeader1 {
 
     #[bw(args_raw = end)]
     pub meta_anim: NullableAnnoPtr<RdmTypedT<AnimMeta>>,
-    _data: [u8; 48 - 5 * 4],
+    _data: [u8; 48 - RdmHeader1::POINTERS_SIZE as usize],
+}
+
+impl RdmHeader1 {
+    /// Size of the pointers every version starts with, the rest of the struct is not interpreted.
+    pub const POINTERS_SIZE: u32 = 5 * 4;
+
+    /// `RdmMat.png` of every material: the texture path in the source scene.
+    pub fn material_textures(&self) -> Vec<Option<String>> {
+        match self.rdm_blob_to_mat.value.as_ref() {
+            Some(blobs) => blobs
+                .iter()
+                .map(|blob| blob.mat.png.value.as_ref().map(|s| s.as_ascii().to_owned()))
+                .collect(),
+            None => Vec::new(),
+        }
+    }
 }
 
 #[binrw]
@@ -211,12 +228,79 @@ pub trait RdmFileType {}
 impl RdmFileType for RdmKindMesh {}
 impl RdmFileType for RdmKindAnim {}
 
+/// File header after the `RDM` magic. Only the two offsets are interpreted, the other fields are kept as
+/// read and written back unchanged. All known files (Anno 1800) have `01 | 0x14 | 0 | 4 | 0x1C`.
+#[binrw]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RdmFileHeader {
+    /// Unknown, 1 in all known files.
+    pub unknown0: u8,
+    /// Offset of the root container, i.e. the size of the file header.
+    pub header_size: u32,
+    /// Unknown, 0 and 4 in all known files.
+    pub unknown1: [u32; 2],
+    /// Offset of `RdmHeader1`.
+    pub root_offset: u32,
+}
+
+impl RdmFileHeader {
+    pub const ANNO_1800: RdmFileHeader = RdmFileHeader {
+        unknown0: 1,
+        header_size: 0x14,
+        unknown1: [0, 4],
+        root_offset: 0x1C,
+    };
+
+    /// `RdmHeader1` directly follows the prefix of the root container, the only layout rdm4 reads.
+    /// The unknown fields are not checked.
+    pub fn is_supported(&self) -> bool {
+        self.header_size >= 0x14 && self.root_offset == self.header_size + 8
+    }
+
+    /// Whether the unknown fields have the values of all known files.
+    pub fn has_anno_1800_unknown_fields(&self) -> bool {
+        self.unknown0 == Self::ANNO_1800.unknown0 && self.unknown1 == Self::ANNO_1800.unknown1
+    }
+
+    /// `self` with the unknown fields of `other`, keeping the offsets rdm4 writes.
+    pub fn with_unknown_fields_of(self, other: RdmFileHeader) -> RdmFileHeader {
+        RdmFileHeader {
+            unknown0: other.unknown0,
+            unknown1: other.unknown1,
+            ..self
+        }
+    }
+}
+
+impl Default for RdmFileHeader {
+    fn default() -> Self {
+        RdmFileHeader::ANNO_1800
+    }
+}
+
+impl fmt::Display for RdmFileHeader {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "root container at {:#x}, RdmHeader1 at {:#x}",
+            self.header_size, self.root_offset
+        )?;
+        if !self.has_anno_1800_unknown_fields() {
+            write!(f, ", unknown fields differ from Anno 1800")?;
+        }
+        Ok(())
+    }
+}
+
 #[binrw]
-#[brw(magic = b"RDM\x01\x14\x00\x00\x00\x00\x00\x00\x00\x04\x00\x00\x00\x1c\x00\x00\x00")]
+#[brw(magic = b"RDM")]
 pub struct RdmFile<T: RdmFileType + 'static> {
-    #[brw(seek_before = SeekFrom::Start(0x00000014))]
-    #[br(assert(header1.header2.ptr == 0x1C + header1.info.part_size))]
-    // RdmHeader1 size is usually 48 but sometimes 52
+    #[br(assert(file_header.is_supported(), "unsupported rdm file header ({}), RdmHeader1 must follow the root container at +8", file_header))]
+    pub file_header: RdmFileHeader,
+
+    #[brw(seek_before = SeekFrom::Start(file_header.header_size as u64))]
+    #[br(assert(header1.header2.ptr == file_header.root_offset + header1.info.part_size, "rdm file header ({}): RdmHeader1 of {} bytes is not followed by ExportInfo", file_header, header1.info.part_size))]
+    // RdmHeader1 size depends on the version, usually 48 but sometimes 52
     // TODO: fix some rdm anim's have a NULL meta_anim.ptr
     #[br(assert(TypeId::of::<RdmKindMesh>() == TypeId::of::<T>() || header1.storage.item[0].meta_anim.ptr != 0 && header1.storage.item[0].meta.ptr == 0, "the input file is not a valid rdm anim!"))]
     #[br(assert(TypeId::of::<RdmKindAnim>() == TypeId::of::<T>() || header1.storage.item[0].meta.ptr != 0 && header1.storage.item[0].meta_anim.ptr == 0, "the input file is not a rdm mesh!"))]
@@ -226,6 +310,45 @@ pub struct RdmFile<T: RdmFileType + 'static> {
     kind: PhantomData<T>,
 }
 
+#[derive(Debug)]
+pub struct RdmReadError {
+    /// Fields of the file header, `None` if the file does not start with it.
+    pub file_header: Option<RdmFileHeader>,
+    pub error: binrw::Error,
+}
+
+impl fmt::Display for RdmReadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.file_header {
+            Some(header) => write!(f, "rdm file header ({}): {}", header, self.error),
+            None => write!(f, "not an rdm file: {}", self.error),
+        }
+    }
+}
+
+impl std::error::Error for RdmReadError {}
+
+impl<T: RdmFileType + 'static> RdmFile<T>
+where
+    Self: for<'a> BinRead<Args<'a> = ()>,
+{
+    /// Reads an rdm, errors describe the file header found.
+    pub fn from_bytes(data: &[u8]) -> Result<Self, RdmReadError> {
+        std::io::Cursor::new(data)
+            .read_le()
+            .map_err(|error| RdmReadError {
+                file_header: read_file_header(data),
+                error,
+            })
+    }
+}
+
+/// File header of the rdm `data`, if it starts with the `RDM` magic.
+pub fn read_file_header(data: &[u8]) -> Option<RdmFileHeader> {
+    let header = data.strip_prefix(b"RDM")?;
+    std::io::Cursor::new(header).read_le().ok()
+}
+
 pub trait DataAndPointedToSize {
     fn get_direct_and_pointed_data_size(&self) -> u64;
 }
@@ -291,6 +414,7 @@ impl DataAndPointedToSize for AnnoU16 {
 }
 
 #[cfg(test)]
+#[allow(clippy::items_after_test_module)]
 mod tests {
     use super::*;
     use binrw::{BinReaderExt, BinWriterExt};
@@ -339,6 +463,49 @@ mod tests {
         dbg!(data.len());
         assert_eq!(data, fs::read("/tmp/rdm_out.rdm").unwrap())
     }
+
+    #[test]
+    fn rdm_file_headers() {
+        let data = include_bytes!("../rdm/fishery_others_cutout_lod0.rdm").to_vec();
+        assert_eq!(read_file_header(&data), Some(RdmFileHeader::ANNO_1800));
+
+        // unknown header fields are read and kept on write
+        let mut other = data.clone();
+        other[8..12].copy_from_slice(&3u32.to_le_bytes());
+        let model = crate::RdModell::new(other);
+        assert_eq!(model.file_header().unwrap().unknown1, [3, 4]);
+        assert!(model
+            .file_header()
+            .unwrap()
+            .to_string()
+            .ends_with("unknown fields differ from Anno 1800"));
+        let written = RdWriter2::new(model);
+        assert_eq!(
+            written.inner.file_header,
+            RdmFileHeader {
+                unknown1: [3, 4],
+                ..RdmFileHeader::ANNO_1800
+            }
+        );
+
+        // RdmHeader1 not right after the root container prefix
+        let mut moved = data.clone();
+        moved[16..20].copy_from_slice(&0x20u32.to_le_bytes());
+        let err = RdmFile::<RdmKindMesh>::from_bytes(&moved).err().unwrap();
+        assert_eq!(err.file_header.unwrap().root_offset, 0x20);
+        assert!(err
+            .to_string()
+            .contains("root container at 0x14, RdmHeader1 at 0x20"));
+        assert!(!err.to_string().contains("unknown fields"));
+
+        // RdmHeader1 smaller than its known fields
+        let mut small = data.clone();
+        small[0x18..0x1C].copy_from_slice(&20u32.to_le_bytes());
+        assert!(RdmFile::<RdmKindMesh>::from_bytes(&small).is_err());
+
+        let err = RdmFile::<RdmKindAnim>::from_bytes(b"DDS ").err().unwrap();
+        assert!(err.file_header.is_none());
+    }
 }
 
 pub struct RdWriter2 {
@@ -346,7 +513,8 @@ pub struct RdWriter2 {
 }
 
 impl RdWriter2 {
-    pub fn write_rdm(self, dir: Option<PathBuf>, create_new: bool) -> PathBuf {
+    /// File written by [`write_rdm`](Self::write_rdm) for `dir`. Creates `rdm_out` if `dir` is `None`.
+    pub fn output_path(dir: Option<PathBuf>) -> PathBuf {
         let mut file = dir.unwrap_or_else(|| {
             let f = PathBuf::from("rdm_out");
             let _ = fs::create_dir(&f);
@@ -355,6 +523,11 @@ impl RdWriter2 {
         if file.is_dir() {
             file.push("out.rdm");
         }
+        file
+    }
+
+    pub fn write_rdm(self, dir: Option<PathBuf>, create_new: bool) -> PathBuf {
+        let file = Self::output_path(dir);
 
         let mut writer = OpenOptions::new()
             .write(true)
@@ -376,8 +549,10 @@ impl RdWriter2 {
         // let data = fs::read("rdm/basalt_crusher_others_lod0.rdm").unwrap();
         let data = include_bytes!("../rdm/basalt_crusher_others_lod0.rdm");
 
-        let mut reader = std::io::Cursor::new(&data);
-        let mut rdm: RdmFile<RdmKindMesh> = reader.read_le().unwrap();
+        let mut rdm = RdmFile::<RdmKindMesh>::from_bytes(data).unwrap();
+        if let Some(header) = rdm_in.file_header() {
+            rdm.file_header = rdm.file_header.with_unknown_fields_of(header);
+        }
 
         let export_name = br"\\060.alpha\data\Art\graphic_backup\christian\#ANNO5\buildings\others\basalt_crusher_others\Lowpoly\basalt_crusher_others_low_05.max";
 
@@ -492,8 +667,26 @@ impl RdWriter2 {
         let material = br"Default Standard12432142134";
         let dummy_png_path = br"d:/projekte/anno5/game/testdata/graphics/dummy_objects/dummy_christian/rdm/basalt_crusher_others/diffuse.png";
 
+        // diffuse texture of each material, e.g. written by ImportedGltf::write_textures.
+        // RdmMat has a single texture path: normal and metal textures are referenced by the cfg only.
+        let textures: Vec<Option<Vec<u8>>> = match rdm_in.mat.as_ref() {
+            Some(mat) => mat
+                .texture_sets()
+                .iter()
+                .map(|t| {
+                    let diff = t.diff.as_ref()?;
+                    Some(diff.to_string_lossy().replace('\\', "/").into_bytes())
+                })
+                .collect(),
+            None => Vec::new(),
+        };
+
         let mut mats = vec![];
-        for _ in 0..MeshInfo::get_max_material(&rdm_in.mesh_info) + 1 {
+        for i in 0..MeshInfo::get_max_material(&rdm_in.mesh_info) + 1 {
+            let png_path = match textures.get(i as usize) {
+                Some(Some(path)) => path.as_slice(),
+                _ => dummy_png_path.as_slice(),
+            };
             let dummy_mat = RdmBlobToMat {
                 mat: AnnoPtr2(RdmFilePtr {
                     ptr: 0,
@@ -520,11 +713,11 @@ impl RdWriter2 {
                                     ptr: 0,
                                     value: Some(RdmContainer {
                                         info: RdmContainerPrefix {
-                                            count: dummy_png_path.len() as u32,
+                                            count: png_path.len() as u32,
                                             part_size: 1,
                                         },
                                         storage: rdm_container::VectorN {
-                                            items: dummy_png_path.map(AnnoChar).into(),
+                                            items: png_path.iter().copied().map(AnnoChar).collect(),
                                         },
                                     }),
                                 }),
@@ -1,64 +1,259 @@
-use std::process::Command;
+use crate::dds::{self, DdsError, RgbaImage};
+use crate::rda;
 use std::{
     fs,
     path::{Path, PathBuf},
+    str::FromStr,
 };
 
+/// How base color textures are stored in an exported glTF. Normal, metallic-roughness and occlusion textures
+/// are remapped from the Anno channels and always PNG.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextureExport {
+    /// Decode to PNG.
+    Png,
+    /// Keep the original DDS via `MSFT_texture_dds`. Textures that can be decoded also get a PNG fallback.
+    Dds,
+    /// Only the original DDS. `MSFT_texture_dds` becomes a required extension.
+    DdsOnly,
+}
+
+impl FromStr for TextureExport {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "png" => Ok(TextureExport::Png),
+            "dds" => Ok(TextureExport::Dds),
+            "dds-only" | "ddsonly" => Ok(TextureExport::DdsOnly),
+            _ => Err(format!(
+                "Invalid value for TextureExport: {}, Only png/dds/dds-only are allowed value",
+                input
+            )),
+        }
+    }
+}
+
+/// Cutout models are named `*_cutout_lod<n>.rdm`.
+pub fn is_cutout_model(model: &Path) -> bool {
+    texture_set_name(model).ends_with("_cutout")
+}
+
+/// Name shared by the textures of a model: the file stem without LOD suffix (`foo_lod0.rdm` -> `foo`).
+pub fn texture_set_name(model: &Path) -> String {
+    let stem = model
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    match stem.rfind("_lod") {
+        Some(i)
+            if i > 0 && stem.len() > i + 4 && stem[i + 4..].bytes().all(|b| b.is_ascii_digit()) =>
+        {
+            stem[..i].to_owned()
+        }
+        _ => stem,
+    }
+}
+
+/// Textures of one material, named `<name>_diff_0.dds`, `<name>_norm_0.dds`, `<name>_metal_0.dds` and
+/// `<name>_height_0.dds` by Anno.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TextureSet {
+    /// Base color, alpha in A.
+    pub diff: Option<PathBuf>,
+    /// Tangent space normal: X in R, Y in G. Z is not stored.
+    pub norm: Option<PathBuf>,
+    /// Metallic in R, gloss in G.
+    pub metal: Option<PathBuf>,
+    /// Height in R, exported as occlusion.
+    pub height: Option<PathBuf>,
+    /// Cutout material: pixels with a base color alpha below the cutoff are discarded.
+    pub alpha_cutoff: Option<f32>,
+}
+
+impl TextureSet {
+    /// `diff` and the `_norm`/`_metal`/`_height` textures next to it, if `diff` follows the `_diff` naming.
+    fn with_siblings(diff: PathBuf) -> Self {
+        let sibling = |kind: &str| {
+            let name = diff.file_name()?.to_str()?;
+            let i = name.rfind("_diff")?;
+            let path = diff.with_file_name(format!("{}_{}{}", &name[..i], kind, &name[i + 5..]));
+            rda::is_file(&path).then_some(path)
+        };
+        TextureSet {
+            norm: sibling("norm"),
+            metal: sibling("metal"),
+            height: sibling("height"),
+            diff: Some(diff),
+            alpha_cutoff: None,
+        }
+    }
+
+    /// The texture set `<dir>/<name>_diff_0.dds` (and `_norm_0`, ...), if the diffuse texture exists.
+    fn find(dir: &Path, name: &str) -> Option<Self> {
+        let diff = dir.join(format!("{}_diff_0.dds", name));
+        rda::is_file(&diff).then(|| Self::with_siblings(diff))
+    }
+}
+
 #[derive(Clone, Debug)]
 pub struct RdMaterial {
-    c_model_diff_tex: Vec<PathBuf>,
+    textures: Vec<TextureSet>,
+    /// Mipmap level written to PNG, 0 is the full resolution. Clamped to the smallest level of each texture.
+    pub mip_level: u32,
+    pub texture_export: TextureExport,
 }
 
 impl RdMaterial {
+    /// One diffuse texture per material, by position.
     pub fn new<P: AsRef<Path> + Into<PathBuf>>(paths: Vec<P>) -> Self {
-        let mut v = Vec::with_capacity(paths.len());
-        for p in paths {
-            v.push(p.into());
-        }
+        let textures = paths
+            .into_iter()
+            .map(|p| TextureSet {
+                diff: Some(p.into()),
+                ..Default::default()
+            })
+            .collect();
+        Self::from_texture_sets(textures)
+    }
+
+    pub fn from_texture_sets(textures: Vec<TextureSet>) -> Self {
         RdMaterial {
-            c_model_diff_tex: v,
+            textures,
+            mip_level: 0,
+            texture_export: TextureExport::Png,
+        }
+    }
+
+    /// Texture set of every material, by material index.
+    pub fn texture_sets(&self) -> &[TextureSet] {
+        &self.textures
+    }
+
+    /// Makes every material a cutout material, see [`TextureSet::alpha_cutoff`].
+    pub fn set_alpha_cutoff(&mut self, cutoff: f32) {
+        for set in self.textures.iter_mut() {
+            set.alpha_cutoff = Some(cutoff);
         }
     }
 
-    pub fn run_dds_converter(&self, dst: &Path) {
-        if cfg!(windows) {
-            self.run_texconv(dst);
-        } else {
-            unimplemented!("DDS convert needs Windows texconv!");
+    /// Finds the textures of `model` for each of its materials. `rdm_textures` are the `RdmMat.png` paths of
+    /// the model. A path is tried below `data_root`, by file name next to the model and as is, each as
+    /// `<stem>_0.dds` and `<stem>.dds`. A `_diff` texture brings its `_norm`, `_metal` and `_height` siblings.
+    ///
+    /// A model with a single material falls back to the Anno texture set next to it: `foo_lod0.rdm` uses
+    /// `foo_diff_0.dds`, `foo_norm_0.dds` and `foo_metal_0.dds`.
+    pub fn discover(
+        model: &Path,
+        rdm_textures: &[Option<String>],
+        data_root: Option<&Path>,
+    ) -> Self {
+        let dir = model.parent().unwrap_or_else(|| Path::new(""));
+        let mut textures: Vec<TextureSet> = rdm_textures
+            .iter()
+            .map(|path| {
+                let found = path
+                    .as_deref()
+                    .and_then(|p| resolve_texture(p, dir, data_root));
+                debug!("texture {:?}: {:?}", path, found);
+                found.map(TextureSet::with_siblings).unwrap_or_default()
+            })
+            .collect();
+
+        if textures.len() <= 1 && textures.iter().all(|t| t.diff.is_none()) {
+            if let Some(set) = TextureSet::find(dir, &texture_set_name(model)) {
+                textures = vec![set];
+            }
         }
+        for (i, set) in textures.iter().enumerate() {
+            match set.diff.as_ref() {
+                Some(diff) => info!("material {}: {:?}", i, diff),
+                None => warn!("material {}: no texture found", i),
+            }
+        }
+        Self::from_texture_sets(textures)
     }
 
-    fn run_texconv(&self, dst: &Path) {
-        warn!("running texconv ...");
-        for p in self.c_model_diff_tex.iter() {
-            let ab_path = p.canonicalize().unwrap();
-            let ab_dst = dst.canonicalize().unwrap();
+    /// Decodes the DDS texture `path` at `mip_level` and encodes it as PNG.
+    pub fn to_png(&self, path: &Path) -> Result<Vec<u8>, DdsError> {
+        self.decode(path)?.to_png()
+    }
 
-            let mut file_dst = ab_dst.join(ab_path.file_stem().unwrap());
-            file_dst.set_extension("PNG");
-            if file_dst.is_file() {
-                match fs::remove_file(&file_dst) {
-                    Ok(_) => {
-                        debug!("removed: {:?}", &file_dst)
-                    }
-                    Err(e) => {
-                        debug!("failed to remove: {:?}, {:?}", &file_dst, e)
-                    }
-                }
+    /// Decodes the DDS texture `path` at `mip_level`.
+    pub fn decode(&self, path: &Path) -> Result<RgbaImage, DdsError> {
+        let dds = dds::Dds::open(path)?;
+        let level = self.mip_level.min(dds.mip_count() - 1);
+        if level != self.mip_level {
+            warn!(
+                "{:?} has {} mipmap levels, using level {}",
+                path,
+                dds.mip_count(),
+                level
+            );
+        }
+        dds.decode(level)
+    }
+
+    /// glTF normal map of the Anno normal map `norm`: Z is rebuilt from X and Y.
+    pub fn normal_map(&self, norm: &Path) -> Result<RgbaImage, DdsError> {
+        let mut image = self.decode(norm)?;
+        for px in image.pixels.chunks_exact_mut(4) {
+            let x = f32::from(px[0]) / 127.5 - 1.0;
+            let y = f32::from(px[1]) / 127.5 - 1.0;
+            let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+            px[2] = ((z + 1.0) * 127.5).round() as u8;
+            px[3] = 255;
+        }
+        Ok(image)
+    }
+
+    /// glTF occlusion (R), roughness (G) and metallic (B) of `set`: occlusion is the height, roughness the inverted
+    /// gloss. The result has the size of the metal texture, the height is sampled to match. `None` without
+    /// metal and height texture.
+    pub fn occlusion_roughness_metallic(
+        &self,
+        set: &TextureSet,
+    ) -> Result<Option<RgbaImage>, DdsError> {
+        let metal = set.metal.as_deref().map(|p| self.decode(p)).transpose()?;
+        let height = set.height.as_deref().map(|p| self.decode(p)).transpose()?;
+        let (width, height_px) = match (&metal, &height) {
+            (Some(m), _) | (None, Some(m)) => (m.width, m.height),
+            (None, None) => return Ok(None),
+        };
+
+        // nearest pixel of `image` for (x, y) of the result
+        let sample = |image: &RgbaImage, x: u32, y: u32, c: usize| {
+            let sx = (x as u64 * image.width as u64 / width as u64) as usize;
+            let sy = (y as u64 * image.height as u64 / height_px as u64) as usize;
+            image.pixels[(sy * image.width as usize + sx) * 4 + c]
+        };
+        let mut pixels = Vec::with_capacity((width * height_px) as usize * 4);
+        for y in 0..height_px {
+            for x in 0..width {
+                let occlusion = height.as_ref().map_or(255, |h| sample(h, x, y, 0));
+                let (roughness, metallic) = match &metal {
+                    Some(m) => (255 - sample(m, x, y, 1), sample(m, x, y, 0)),
+                    None => (255, 0),
+                };
+                pixels.extend_from_slice(&[occlusion, roughness, metallic, 255]);
             }
+        }
+        Ok(Some(RgbaImage {
+            width,
+            height: height_px,
+            pixels,
+        }))
+    }
 
-            let output = Command::new("texconv.exe")
-                .arg(ab_path.as_os_str())
-                .arg(r"-o")
-                .arg(ab_dst.as_os_str())
-                .arg(r"-ft")
-                .arg(r"png")
-                .output()
-                .expect("failed to execute texconv.exe");
-            debug!("{:?}", &ab_path.to_str().unwrap()[4..]);
-            debug!("{:?}", &ab_dst.to_str().unwrap()[4..]);
-            debug!("{:?}", output);
+    /// Writes every texture as `<file stem>.PNG` into `dst`.
+    pub fn run_dds_converter(&self, dst: &Path) -> Result<(), DdsError> {
+        for p in self {
+            let mut file_dst = dst.join(p.file_stem().unwrap());
+            file_dst.set_extension("PNG");
+            debug!("convert: {:?} to {:?}", p, &file_dst);
+            fs::write(&file_dst, self.to_png(p)?)?;
         }
+        Ok(())
     }
 }
 
@@ -71,11 +266,49 @@ where
     }
 }
 
+/// Every texture path: diffuse, normal, metal and height of each material.
 impl<'a> IntoIterator for &'a RdMaterial {
     type Item = &'a PathBuf;
-    type IntoIter = std::slice::Iter<'a, PathBuf>;
+    type IntoIter = Box<dyn Iterator<Item = &'a PathBuf> + 'a>;
 
     fn into_iter(self) -> Self::IntoIter {
-        self.c_model_diff_tex.iter()
+        Box::new(self.textures.iter().flat_map(|t| {
+            [&t.diff, &t.norm, &t.metal, &t.height]
+                .into_iter()
+                .flatten()
+        }))
+    }
+}
+
+/// Resolves the texture path `reference` of an rdm to an existing DDS file.
+pub(crate) fn resolve_texture(
+    reference: &str,
+    model_dir: &Path,
+    data_root: Option<&Path>,
+) -> Option<PathBuf> {
+    let reference = reference.replace('\\', "/");
+    // drop the drive letter of the source scene, `d:/projekte/...`
+    let relative = match reference.split_once(":/") {
+        Some((drive, rest)) if drive.len() == 1 => rest,
+        _ => reference.trim_start_matches('/'),
+    };
+    let relative = Path::new(relative);
+    let stem = relative.file_stem()?.to_str()?;
+    let parent = relative.parent().unwrap_or_else(|| Path::new(""));
+
+    let mut dirs = Vec::new();
+    if let Some(root) = data_root {
+        dirs.push(root.join(parent));
     }
+    dirs.push(model_dir.to_path_buf());
+    dirs.push(parent.to_path_buf());
+
+    dirs.iter()
+        .flat_map(|dir| {
+            [
+                dir.join(format!("{}_0.dds", stem)),
+                dir.join(format!("{}.dds", stem)),
+            ]
+        })
+        .find(|p| rda::is_file(p))
 }
@@ -1,4 +1,4 @@
-use bytes::{Buf, Bytes};
+use bytes::{Buf, BytesMut};
 use rdm_derive::RdmStructSize;
 use std::{fmt, str::FromStr};
 
@@ -152,23 +152,23 @@ impl<const I: u32, const M: usize> From<AnnoData<u8, I, 4>> for AnnoData<f32, I,
 }
 
 pub trait GetVertex {
-    fn get_unit(b: &mut Bytes) -> Self;
+    fn get_unit<B: Buf>(b: &mut B) -> Self;
 }
 
 impl<const I: u32, const N: usize> GetVertex for AnnoData<u8, I, N> {
-    fn get_unit(b: &mut Bytes) -> Self {
+    fn get_unit<B: Buf>(b: &mut B) -> Self {
         Self::from_fn_generic(|_| b.get_u8())
     }
 }
 
 impl<const I: u32, const N: usize> GetVertex for AnnoData<f16, I, N> {
-    fn get_unit(b: &mut Bytes) -> Self {
+    fn get_unit<B: Buf>(b: &mut B) -> Self {
         Self::from_fn_generic(|_| f16::from_bits(b.get_u16_le()))
     }
 }
 
 impl<const I: u32, const N: usize> GetVertex for AnnoData<f32, I, N> {
-    fn get_unit(b: &mut Bytes) -> Self {
+    fn get_unit<B: Buf>(b: &mut B) -> Self {
         Self::from_fn_generic(|_| b.get_f32_le())
     }
 }
@@ -185,6 +185,22 @@ impl<const I: u32, const N: usize> Normalise for AnnoData<f32, I, N> {
     }
 }
 
+/// Structure-of-arrays copy of a vertex buffer with every stream decoded to `f32`.
+///
+/// Packed 4 byte streams decode according to their interpretation: normals, tangents and
+/// bitangents to -1.0..=1.0, weights and colors to 0.0..=1.0 and joint indices to their raw value.
+#[derive(Debug, Clone, Default)]
+pub struct VertexStreams {
+    pub positions: Vec<[f32; 3]>,
+    pub normals: Option<Vec<[f32; 3]>>,
+    pub tangents: Option<Vec<[f32; 3]>>,
+    pub bitangents: Option<Vec<[f32; 3]>>,
+    pub texcoords: Vec<Vec<[f32; 2]>>,
+    pub colors: Vec<Vec<[f32; 4]>>,
+    pub joints: Vec<Vec<[f32; 4]>>,
+    pub weights: Vec<Vec<[f32; 4]>>,
+}
+
 #[derive(Debug)]
 pub struct VertexFormat2 {
     pub identifiers: Box<[VertexIdentifier]>,
@@ -192,7 +208,7 @@ pub struct VertexFormat2 {
     text: String,
     pub vertex_count: u32,
     size: u32,
-    vertex_buffer: Bytes,
+    vertex_buffer: BytesMut,
     pub weight_sum: Option<Vec<u32>>,
 }
 
@@ -244,7 +260,7 @@ impl VertexFormat2 {
         identifiers: Box<[VertexIdentifier]>,
         vertex_count: u32,
         vertex_size: u32,
-        vertex_buffer: Bytes,
+        vertex_buffer: BytesMut,
     ) -> Self {
         let mut offsets = Vec::with_capacity(identifiers.len());
         let mut off = 0;
@@ -284,7 +300,8 @@ impl VertexFormat2 {
 
         let vertex_count = meta.vertex.info.count;
         let vertex_size = meta.vertex.info.part_size;
-        let vertex_buffer = Bytes::from(meta.vertex.iter().map(|x| x.0).collect::<Vec<u8>>());
+        let vertex_buffer =
+            BytesMut::from(&meta.vertex.iter().map(|x| x.0).collect::<Vec<u8>>()[..]);
 
         Self::new(
             vec.into_boxed_slice(),
@@ -319,6 +336,162 @@ impl VertexFormat2 {
         &self.vertex_buffer
     }
 
+    /// Index into `identifiers` of the `set`-th stream of kind `search_ident`.
+    pub fn stream_index(&self, search_ident: UniqueIdentifier, set: usize) -> Option<usize> {
+        self.find_component_offsets(search_ident).nth(set)
+    }
+
+    /// Decodes one stream into `f32` values, one `[f32; N]` per vertex.
+    ///
+    /// Components the stream does not have are filled with 0.0, surplus components are dropped.
+    pub fn decode<const N: usize>(
+        &self,
+        search_ident: UniqueIdentifier,
+        set: usize,
+    ) -> Option<Vec<[f32; N]>> {
+        let idx = self.stream_index(search_ident, set)?;
+        let ident = &self.identifiers[idx];
+        let range = self.offsets[idx]..self.offsets[idx] + ident.get_size() as usize;
+
+        let decoded = self
+            .vertex_buffer
+            .chunks_exact(self.size as usize)
+            .map(|vertex| {
+                let mut unit = [0.0; N];
+                ident.decode_unit(&vertex[range.clone()], &mut unit);
+                unit
+            })
+            .collect();
+        Some(decoded)
+    }
+
+    /// Writes `data` back into one stream using the stream's original encoding. Returns `None` if the stream
+    /// does not exist or `data` does not have one value per vertex.
+    ///
+    /// Components of the stream that `data` does not provide are left untouched.
+    pub fn encode<const N: usize>(
+        &mut self,
+        search_ident: UniqueIdentifier,
+        set: usize,
+        data: &[[f32; N]],
+    ) -> Option<()> {
+        if data.len() != self.len() as usize {
+            return None;
+        }
+        let idx = self.stream_index(search_ident.clone(), set)?;
+        let ident = &self.identifiers[idx];
+        let range = self.offsets[idx]..self.offsets[idx] + ident.get_size() as usize;
+
+        for (vertex, unit) in self
+            .vertex_buffer
+            .chunks_exact_mut(self.size as usize)
+            .zip(data.iter())
+        {
+            ident.encode_unit(unit, &mut vertex[range.clone()]);
+        }
+        if search_ident == UniqueIdentifier::Weight {
+            self.weight_sum = None;
+        }
+        Some(())
+    }
+
+    /// Overwrites a single vertex of one stream. Returns `None` if the stream or the vertex does not exist.
+    pub fn set<const N: usize>(
+        &mut self,
+        search_ident: UniqueIdentifier,
+        set: usize,
+        vertex: usize,
+        value: [f32; N],
+    ) -> Option<()> {
+        if vertex >= self.len() as usize {
+            return None;
+        }
+        let idx = self.stream_index(search_ident.clone(), set)?;
+        let ident = &self.identifiers[idx];
+        let start = vertex * self.size as usize + self.offsets[idx];
+        let end = start + ident.get_size() as usize;
+
+        ident.encode_unit(&value, &mut self.vertex_buffer[start..end]);
+        if search_ident == UniqueIdentifier::Weight {
+            self.weight_sum = None;
+        }
+        Some(())
+    }
+
+    pub fn set_position(&mut self, vertex: usize, position: [f32; 3]) -> Option<()> {
+        self.set(UniqueIdentifier::Position, 0, vertex, position)
+    }
+
+    pub fn set_normal(&mut self, vertex: usize, normal: [f32; 3]) -> Option<()> {
+        self.set(UniqueIdentifier::Normal, 0, vertex, normal)
+    }
+
+    pub fn set_tangent(&mut self, vertex: usize, tangent: [f32; 3]) -> Option<()> {
+        self.set(UniqueIdentifier::GTangent, 0, vertex, tangent)
+    }
+
+    pub fn set_bitangent(&mut self, vertex: usize, bitangent: [f32; 3]) -> Option<()> {
+        self.set(UniqueIdentifier::Bitangent, 0, vertex, bitangent)
+    }
+
+    pub fn set_texcoord(&mut self, vertex: usize, set: usize, uv: [f32; 2]) -> Option<()> {
+        self.set(UniqueIdentifier::Texcoord, set, vertex, uv)
+    }
+
+    pub fn set_color(&mut self, vertex: usize, set: usize, color: [f32; 4]) -> Option<()> {
+        self.set(UniqueIdentifier::Color, set, vertex, color)
+    }
+
+    /// Decodes every stream of the vertex buffer, see [`VertexStreams`].
+    pub fn decode_streams(&self) -> VertexStreams {
+        let sets = |ident: UniqueIdentifier| self.find_component_offsets(ident).count();
+
+        VertexStreams {
+            positions: self.decode(UniqueIdentifier::Position, 0).unwrap(),
+            normals: self.decode(UniqueIdentifier::Normal, 0),
+            tangents: self.decode(UniqueIdentifier::GTangent, 0),
+            bitangents: self.decode(UniqueIdentifier::Bitangent, 0),
+            texcoords: (0..sets(UniqueIdentifier::Texcoord))
+                .filter_map(|i| self.decode(UniqueIdentifier::Texcoord, i))
+                .collect(),
+            colors: (0..sets(UniqueIdentifier::Color))
+                .filter_map(|i| self.decode(UniqueIdentifier::Color, i))
+                .collect(),
+            joints: (0..sets(UniqueIdentifier::IJoint))
+                .filter_map(|i| self.decode(UniqueIdentifier::IJoint, i))
+                .collect(),
+            weights: (0..sets(UniqueIdentifier::Weight))
+                .filter_map(|i| self.decode(UniqueIdentifier::Weight, i))
+                .collect(),
+        }
+    }
+
+    /// Writes all streams of `streams` back. Streams missing in either side or of another length are skipped.
+    pub fn encode_streams(&mut self, streams: &VertexStreams) {
+        self.encode(UniqueIdentifier::Position, 0, &streams.positions);
+        if let Some(normals) = streams.normals.as_ref() {
+            self.encode(UniqueIdentifier::Normal, 0, normals);
+        }
+        if let Some(tangents) = streams.tangents.as_ref() {
+            self.encode(UniqueIdentifier::GTangent, 0, tangents);
+        }
+        if let Some(bitangents) = streams.bitangents.as_ref() {
+            self.encode(UniqueIdentifier::Bitangent, 0, bitangents);
+        }
+        for (i, texcoords) in streams.texcoords.iter().enumerate() {
+            self.encode(UniqueIdentifier::Texcoord, i, texcoords);
+        }
+        for (i, colors) in streams.colors.iter().enumerate() {
+            self.encode(UniqueIdentifier::Color, i, colors);
+        }
+        for (i, joints) in streams.joints.iter().enumerate() {
+            self.encode(UniqueIdentifier::IJoint, i, joints);
+        }
+        for (i, weights) in streams.weights.iter().enumerate() {
+            self.encode(UniqueIdentifier::Weight, i, weights);
+        }
+    }
+
     pub fn iter<
         'a,
         Z: GetUniqueIdentifier + GetVertex,
@@ -348,7 +521,7 @@ impl VertexFormat2 {
 
         let mut count = 0;
 
-        let mut vbuffer = self.vertex_buffer.clone();
+        let mut vbuffer = &self.vertex_buffer[..];
         assert_eq!(vbuffer.len() as u32 % self.size, 0);
         let n = vbuffer.len() as u32 / self.size;
         assert_eq!(self.vertex_count, n);
@@ -412,6 +585,60 @@ impl VertexIdentifier {
         }
     }
 
+    /// Number of values one unit of this identifier decodes to.
+    pub fn component_count(&self) -> usize {
+        match self.unit_size {
+            IdentifierSize::U32 if self.count == 0x1 => 4,
+            _ => self.count as usize,
+        }
+    }
+
+    // A single U32 holds four packed bytes. U32 with a count > 1 is only written by
+    // P3f_N3f_G3f_B3f_T2f_C4b (B3f) and stores plain f32 values.
+    fn decode_unit(&self, src: &[u8], dst: &mut [f32]) {
+        let n = dst.len().min(self.component_count());
+        for (i, v) in dst.iter_mut().enumerate().take(n) {
+            *v = match self.unit_size {
+                IdentifierSize::U32 if self.count == 0x1 => {
+                    let b = src[i] as f32;
+                    match self.interpretation {
+                        0x0 => b,
+                        0x6 => ((2.0 * b) / 255.0) - 1.0,
+                        _ => b / 255.0,
+                    }
+                }
+                IdentifierSize::U16 => {
+                    f16::from_bits(u16::from_le_bytes([src[2 * i], src[2 * i + 1]])).to_f32()
+                }
+                IdentifierSize::U32 | IdentifierSize::F32 => {
+                    f32::from_le_bytes(src[4 * i..4 * i + 4].try_into().unwrap())
+                }
+            };
+        }
+    }
+
+    fn encode_unit(&self, src: &[f32], dst: &mut [u8]) {
+        let n = src.len().min(self.component_count());
+        for (i, &v) in src.iter().enumerate().take(n) {
+            match self.unit_size {
+                IdentifierSize::U32 if self.count == 0x1 => {
+                    let b = match self.interpretation {
+                        0x0 => v,
+                        0x6 => ((v + 1.0) / 2.0) * 255.0,
+                        _ => v * 255.0,
+                    };
+                    dst[i] = b.round().clamp(0.0, 255.0) as u8;
+                }
+                IdentifierSize::U16 => {
+                    dst[2 * i..2 * i + 2].copy_from_slice(&f16::from_f32(v).to_bits().to_le_bytes())
+                }
+                IdentifierSize::U32 | IdentifierSize::F32 => {
+                    dst[4 * i..4 * i + 4].copy_from_slice(&v.to_le_bytes())
+                }
+            }
+        }
+    }
+
     pub const fn p4h() -> Self {
         VertexIdentifier {
             uniq: UniqueIdentifier::Position,
@@ -772,4 +999,54 @@ mod tests_vertex {
             assert_eq!(&bytes2[i], &bytes[i]);
         }
     }
+
+    #[test]
+    fn decode_encode_streams_roundtrip() {
+        let bytes = include_bytes!("../rdm/basalt_crusher_others_lod2.rdm");
+        let mut rdm = RdModell::new(bytes.to_vec());
+        let original = rdm.vertex.as_bytes().to_vec();
+
+        let streams = rdm.vertex.decode_streams();
+        assert_eq!(streams.positions.len(), 2615);
+        assert!(streams.normals.is_some());
+        assert_eq!(streams.texcoords.len(), 1);
+        assert_eq!(streams.joints.len(), 1);
+        assert!(streams.colors.is_empty());
+
+        rdm.vertex.encode_streams(&streams);
+        assert_eq!(original, rdm.vertex.as_bytes());
+    }
+
+    #[test]
+    fn set_position_and_texcoord() {
+        let bytes = include_bytes!("../rdm/basalt_crusher_others_lod2.rdm");
+        let mut rdm = RdModell::new(bytes.to_vec());
+
+        let mut uvs = rdm
+            .vertex
+            .decode::<2>(UniqueIdentifier::Texcoord, 0)
+            .unwrap();
+        for uv in uvs.iter_mut() {
+            uv[0] += 0.5;
+        }
+        rdm.vertex
+            .encode(UniqueIdentifier::Texcoord, 0, &uvs)
+            .unwrap();
+        rdm.vertex.set_position(3, [1.0, -2.0, 0.5]).unwrap();
+        assert!(rdm.vertex.set_color(3, 0, [1.0; 4]).is_none());
+        let len = rdm.vertex.len() as usize;
+        assert!(rdm.vertex.set_position(len, [0.0; 3]).is_none());
+        assert!(rdm
+            .vertex
+            .encode(UniqueIdentifier::Texcoord, 0, &uvs[1..])
+            .is_none());
+
+        let positions = rdm
+            .vertex
+            .decode::<3>(UniqueIdentifier::Position, 0)
+            .unwrap();
+        assert_eq!(positions[3], [1.0, -2.0, 0.5]);
+        let t2f: Vec<T2f> = rdm.vertex.iter::<T2h, T2f>(0).unwrap().collect();
+        assert_relative_eq!(t2f[7].data[0], uvs[7][0], epsilon = 1e-3);
+    }
 }
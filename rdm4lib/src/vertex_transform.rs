@@ -1,16 +1,228 @@
+use crate::rdm_data_anim::Frame;
 use crate::vertex::W4b;
 use crate::vertex::*;
+use crate::RdJoint;
 use half::f16;
 use nalgebra::Vector3;
 use nalgebra::*;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Axis {
+    X,
+    Y,
+    Z,
+    NegX,
+    NegY,
+    NegZ,
+}
+
+impl Axis {
+    fn vector(self) -> Vector3<f32> {
+        match self {
+            Axis::X => Vector3::x(),
+            Axis::Y => Vector3::y(),
+            Axis::Z => Vector3::z(),
+            Axis::NegX => -Vector3::x(),
+            Axis::NegY => -Vector3::y(),
+            Axis::NegZ => -Vector3::z(),
+        }
+    }
+}
+
+impl FromStr for Axis {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        match input.to_ascii_lowercase().as_str() {
+            "x" | "+x" => Ok(Axis::X),
+            "y" | "+y" => Ok(Axis::Y),
+            "z" | "+z" => Ok(Axis::Z),
+            "-x" => Ok(Axis::NegX),
+            "-y" => Ok(Axis::NegY),
+            "-z" => Ok(Axis::NegZ),
+            _ => Err(format!("Invalid value for Axis: {}", input)),
+        }
+    }
+}
+
+/// Unit scale and axis convention of the glTF side.
+///
+/// rdm is Y up and Z forward. On import `matrix()` converts from the glTF convention to rdm,
+/// on export the inverse is applied.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct UnitConversion {
+    /// rdm units per glTF unit.
+    pub scale: f32,
+    pub up_axis: Axis,
+    pub forward_axis: Axis,
+}
+
+impl Default for UnitConversion {
+    fn default() -> Self {
+        UnitConversion {
+            scale: 1.0,
+            up_axis: Axis::Y,
+            forward_axis: Axis::Z,
+        }
+    }
+}
+
+impl UnitConversion {
+    pub fn new(scale: f32, up_axis: Axis, forward_axis: Axis) -> Result<Self, String> {
+        if !scale.is_normal() || scale.is_sign_negative() {
+            return Err(format!("scale must be positive: {}", scale));
+        }
+        if up_axis.vector().dot(&forward_axis.vector()) != 0.0 {
+            return Err(format!(
+                "up axis {:?} and forward axis {:?} must be perpendicular",
+                up_axis, forward_axis
+            ));
+        }
+        Ok(UnitConversion {
+            scale,
+            up_axis,
+            forward_axis,
+        })
+    }
+
+    pub fn is_identity(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Maps glTF space into rdm space.
+    pub fn matrix(&self) -> Matrix4<f32> {
+        let up = self.up_axis.vector();
+        let forward = self.forward_axis.vector();
+        let right = up.cross(&forward);
+        let rows = Matrix3::from_rows(&[right.transpose(), up.transpose(), forward.transpose()]);
+        (rows * self.scale).to_homogeneous()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TransformError {
+    /// No inverse of the 3x3 part: zero scale or a collapsed axis.
+    Singular(Matrix4<f32>),
+    /// NaN or infinite values.
+    NotFinite(Matrix4<f32>),
+}
+
+impl fmt::Display for TransformError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TransformError::Singular(m) => write!(f, "transform is not invertible: {}", m),
+            TransformError::NotFinite(m) => write!(f, "transform has non-finite values: {}", m),
+        }
+    }
+}
+
+impl std::error::Error for TransformError {}
 
 pub struct TransformContext {
     pub(crate) base: Matrix<f32, Const<4>, Const<4>, ArrayStorage<f32, 4, 4>>,
     pub(crate) transpose_inv_transform_mat3:
         Matrix<f32, Const<3>, Const<3>, ArrayStorage<f32, 3, 3>>,
+    // base = T * scale * rotation * mirror, mirror = diag(-1, 1, 1) for a negative determinant
+    rotation: UnitQuaternion<f32>,
+    scale: f32,
+    mirror: bool,
 }
 
 impl TransformContext {
+    pub fn new(base: Matrix4<f32>) -> Result<Self, TransformError> {
+        if base.iter().any(|v| !v.is_finite()) {
+            return Err(TransformError::NotFinite(base));
+        }
+        let mat3 = base.fixed_resize::<3, 3>(0.0);
+        let inv_transform_mat3 = mat3
+            .try_inverse()
+            .filter(|inv| inv.iter().all(|v| v.is_finite()))
+            .ok_or(TransformError::Singular(base))?;
+        let transpose_inv_transform_mat3 = inv_transform_mat3.transpose();
+
+        let det = mat3.determinant();
+        let mirror = det.is_sign_negative();
+        let scale = det.abs().cbrt();
+
+        let mut rot3 = mat3 / scale;
+        if mirror {
+            rot3.set_column(0, &-rot3.column(0));
+        }
+        let column_norms: Vec<f32> = rot3.column_iter().map(|c| c.norm()).collect();
+        if column_norms.iter().any(|n| (n - 1.0).abs() > 1e-3) {
+            warn!(
+                "transform has non-uniform scale or shear ({:?}). Joints only keep rotation and uniform scale!",
+                column_norms
+            );
+        }
+        let rotation = UnitQuaternion::from_rotation_matrix(&Rotation3::from_matrix(&rot3));
+
+        Ok(TransformContext {
+            base,
+            transpose_inv_transform_mat3,
+            rotation,
+            scale,
+            mirror,
+        })
+    }
+
+    /// Whether the transform flips handedness, which requires reversing the triangle winding.
+    pub fn is_mirroring(&self) -> bool {
+        self.mirror
+    }
+
+    fn mirror_rotation(&self, q: UnitQuaternion<f32>) -> UnitQuaternion<f32> {
+        if self.mirror {
+            let c = q.coords;
+            UnitQuaternion::new_unchecked(Quaternion::new(c.w, c.x, -c.y, -c.z))
+        } else {
+            q
+        }
+    }
+
+    fn mirror_vector(&self, v: Vector3<f32>) -> Vector3<f32> {
+        if self.mirror {
+            Vector3::new(-v.x, v.y, v.z)
+        } else {
+            v
+        }
+    }
+
+    /// Transforms a joint's global bind pose.
+    pub fn transform_joint(&self, joint: &mut RdJoint) {
+        let [x, y, z, w] = joint.quaternion;
+        let q = UnitQuaternion::from_quaternion(Quaternion::new(w, x, y, z));
+        let q = self.rotation * self.mirror_rotation(q);
+
+        let t = Point3::from(joint.transition);
+        let t = self.base.transform_point(&t);
+
+        let c = q.coords;
+        joint.quaternion = [c.x, c.y, c.z, c.w];
+        joint.transition = [t.x, t.y, t.z];
+    }
+
+    /// Transforms an animation key. Keys of root joints are global, all others are relative to the parent joint.
+    pub fn transform_frame(&self, frame: &mut Frame, is_root: bool) {
+        // rdm stores the rotation with a negated w
+        let [x, y, z, w] = frame.rotation;
+        let q = UnitQuaternion::from_quaternion(Quaternion::new(-w, x, y, z));
+        let t = Vector3::from(frame.translation);
+
+        let (q, t) = if is_root {
+            let t = self.base.transform_point(&Point3::from(t)).coords;
+            (self.rotation * self.mirror_rotation(q), t)
+        } else {
+            (self.mirror_rotation(q), self.mirror_vector(t) * self.scale)
+        };
+
+        let c = q.coords;
+        frame.rotation = [c.x, c.y, c.z, -c.w];
+        frame.translation = [t.x, t.y, t.z];
+    }
+
     pub fn transform_position(&mut self, position: [f32; 3]) -> Point<f32, 3> {
         let vertex = Point3::new(position[0], position[1], position[2]);
         self.base.transform_point(&vertex)
@@ -31,6 +243,13 @@ impl TransformContext {
         Vector3::new(tx, ty, tz)
     }
 
+    /// Tangents and bitangents lie in the surface: they follow the upper 3x3 of the transform, not the inverse
+    /// transpose used for normals. Normalized.
+    pub fn transform_direction(&self, direction: [f32; 3]) -> Vector3<f32> {
+        let v = self.base.fixed_slice::<3, 3>(0, 0) * Vector3::from(direction);
+        v.try_normalize(0.0).unwrap_or(v)
+    }
+
     pub fn transform_normal(&mut self, normal: [f32; 3]) -> Vector3<f32> {
         let normv: Vector3<f32> = Vector3::new(normal[0], normal[1], normal[2]);
         let transformed_normal: Vector3<f32> = self.transpose_inv_transform_mat3 * normv;
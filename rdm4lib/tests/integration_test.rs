@@ -13,16 +13,25 @@ use std::str;
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rdm4lib::rdm_data_anim::RdAnimWriter2;
+    use rdm4lib::gltf_reader::{GltfImportError, MeshSelector, ResolveNodeName, SplitMode};
+    use rdm4lib::gltf_reader_anim::ScaleChannels;
+    use rdm4lib::rdm_data_anim::{self, RdAnimWriter2};
     use rdm4lib::rdm_data_main::RdWriter2;
+    use rdm4lib::vertex::UniqueIdentifier;
+    use rdm4lib::vertex_transform::UnitConversion;
     use rdm4lib::{gltf_export::GltfExportFormat, vertex::TargetVertexFormat};
     use sha2::{Digest, Sha256};
     use std::convert::TryFrom;
     use std::fs;
     use std::path::PathBuf;
 
-    #[cfg(target_os = "windows")]
-    use rdm4lib::rdm_material::RdMaterial;
+    use rdm4lib::cfg::{Cfg, CfgError};
+    use rdm4lib::dds::{Dds, DdsFormat, RgbaImage};
+    use rdm4lib::dds_encode::{self, BcEncoding};
+    use rdm4lib::rda::{RdaArchive, RdaError};
+    use rdm4lib::rdm_material::{
+        is_cutout_model, texture_set_name, RdMaterial, TextureExport, TextureSet,
+    };
 
     pub fn check_hash(path: &Path, expected: &str) {
         let hash = Sha256::new()
@@ -57,20 +66,26 @@ mod tests {
         let anim = RdAnim::from("rdm/basalt_crusher_others_work01.rdm");
         rdm.add_anim(anim);
 
-        gltf_export::build(rdm, None, false, GltfExportFormat::GltfSeparate);
+        gltf_export::build(
+            rdm,
+            None,
+            false,
+            GltfExportFormat::GltfSeparate,
+            UnitConversion::default(),
+        );
 
         let args = ["-ar", "gltf_out/out.gltf"];
         let output = if cfg!(target_os = "windows") {
             Command::new("gltf_validator.exe")
-                .args(&args)
+                .args(args)
                 .output()
-                .or_else(|_| Command::new("..\\gltf_validator.exe").args(&args).output())
+                .or_else(|_| Command::new("..\\gltf_validator.exe").args(args).output())
                 .expect("failed to execute process")
         } else {
             Command::new("gltf_validator")
-                .args(&args)
+                .args(args)
                 .output()
-                .or_else(|_| Command::new("../gltf_validator").args(&args).output())
+                .or_else(|_| Command::new("../gltf_validator").args(args).output())
                 .expect("failed to execute process")
         };
 
@@ -125,7 +140,6 @@ mod tests {
 
     #[test]
     #[cfg_attr(miri, ignore)]
-    #[cfg(target_os = "windows")]
     fn excavator_tycoons_lod1() {
         let mut rdm = RdModell::from("rdm/excavator_tycoons_lod1.rdm");
         rdm.mat = Some(RdMaterial::from(r"rdm/excavator_tycoons_diff_0.dds"));
@@ -146,12 +160,13 @@ mod tests {
             Some(Path::new("gltf_out1").into()),
             false,
             GltfExportFormat::GltfSeparate,
+            UnitConversion::default(),
         );
+        assert!(Path::new("gltf_out1/excavator_tycoons_diff_0.PNG").is_file());
     }
 
     #[test]
     #[cfg_attr(miri, ignore)]
-    #[cfg(target_os = "windows")]
     fn residence_tier02_estate02() {
         let mut rdm = RdModell::from("rdm/residence_tier_02_estate_02_lod2.rdm");
         rdm.mat = Some(RdMaterial::new(vec![
@@ -171,7 +186,516 @@ mod tests {
             Some(Path::new("gltf_out2").into()),
             false,
             GltfExportFormat::GltfSeparate,
+            UnitConversion::default(),
+        );
+        for png in [
+            "residence_tier02_04_diff_0.PNG",
+            "residence_02_05_diff_0.PNG",
+            "brick_wall_white_estate_01_diff_0.PNG",
+        ] {
+            assert!(Path::new("gltf_out2").join(png).is_file());
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn export_msft_texture_dds() {
+        let export = |texture_export, dir: &str| {
+            let mut rdm = RdModell::from("rdm/fishery_others_lod2.rdm");
+            let mut mat = RdMaterial::new(vec![
+                "rdm/excavator_tycoons_diff_0.dds",
+                "rdm/brick_wall_white_estate_01_diff_0.dds",
+            ]);
+            mat.texture_export = texture_export;
+            rdm.mat = Some(mat);
+            fs::create_dir_all(dir).unwrap();
+            gltf_export::build(
+                rdm,
+                Some(Path::new(dir).into()),
+                false,
+                GltfExportFormat::GltfSeparate,
+                UnitConversion::default(),
+            );
+            let gltf = Path::new(dir).join("out.gltf");
+            let json: serde_json::Value =
+                serde_json::from_str(&fs::read_to_string(gltf).unwrap()).unwrap();
+            json
+        };
+
+        let json = export(TextureExport::Dds, "gltf_out4");
+        assert_eq!(json["extensionsUsed"][0], "MSFT_texture_dds");
+        assert!(json.get("extensionsRequired").is_none());
+        let texture = &json["textures"][1];
+        let png = texture["source"].as_u64().unwrap() as usize;
+        let dds = texture["extensions"]["MSFT_texture_dds"]["source"]
+            .as_u64()
+            .unwrap() as usize;
+        assert_eq!(
+            json["images"][png]["uri"],
+            "brick_wall_white_estate_01_diff_0.PNG"
+        );
+        assert_eq!(
+            json["images"][dds]["uri"],
+            "brick_wall_white_estate_01_diff_0.dds"
+        );
+        assert_eq!(
+            fs::read("gltf_out4/brick_wall_white_estate_01_diff_0.dds").unwrap(),
+            fs::read("rdm/brick_wall_white_estate_01_diff_0.dds").unwrap()
+        );
+        assert!(Path::new("gltf_out4/brick_wall_white_estate_01_diff_0.PNG").is_file());
+
+        let json = export(TextureExport::DdsOnly, "gltf_out5");
+        assert_eq!(json["extensionsRequired"][0], "MSFT_texture_dds");
+        assert_eq!(json["images"].as_array().unwrap().len(), 2);
+        for texture in json["textures"].as_array().unwrap() {
+            assert!(texture.get("source").is_none());
+        }
+        assert!(!Path::new("gltf_out5/excavator_tycoons_diff_0.PNG").exists());
+        assert!(Path::new("gltf_out5/excavator_tycoons_diff_0.dds").is_file());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn discover_texture_sets() {
+        let dir = Path::new("gltf_out6");
+        let root = dir.join("data_root");
+        let batch = root.join("anno 1800 milestone 09/lod_batch_01/residence_tier02_estate_02");
+        fs::create_dir_all(&batch).unwrap();
+        // RdmMat.png: 04_diff.tga below the data root, 05_diff.tga next to the model, none for wall_elements
+        fs::copy(
+            "rdm/residence_tier02_04_diff_0.dds",
+            batch.join("04_diff_0.dds"),
+        )
+        .unwrap();
+        fs::copy(
+            "rdm/brick_wall_white_estate_01_diff_0.dds",
+            batch.join("04_norm_0.dds"),
+        )
+        .unwrap();
+        fs::copy("rdm/residence_02_05_diff_0.dds", dir.join("05_diff_0.dds")).unwrap();
+        let model = dir.join("residence_lod2.rdm");
+        fs::copy("rdm/residence_tier_02_estate_02_lod2.rdm", &model).unwrap();
+
+        let rdm = RdModell::from(&model);
+        let paths = rdm.material_textures();
+        assert_eq!(
+            paths[2].as_deref(),
+            Some("d:/anno 1800 milestone 09/lod_batch_01/residence_tier02_estate_02/wall_elements_diff.tga")
+        );
+        let mat = RdMaterial::discover(&model, &paths, Some(&root));
+        let sets = mat.texture_sets();
+        assert_eq!(sets.len(), 3);
+        assert_eq!(sets[0].diff, Some(batch.join("04_diff_0.dds")));
+        assert_eq!(sets[0].norm, Some(batch.join("04_norm_0.dds")));
+        assert_eq!(sets[0].metal, None);
+        assert_eq!(sets[1].diff, Some(dir.join("05_diff_0.dds")));
+        assert_eq!(sets[2], TextureSet::default());
+
+        // a single material falls back to the texture set named after the model
+        let model = dir.join("excavator_lod1.rdm");
+        fs::copy("rdm/excavator_tycoons_lod1.rdm", &model).unwrap();
+        for kind in ["diff", "norm", "metal"] {
+            let texture = dir.join(format!("excavator_{}_0.dds", kind));
+            fs::copy("rdm/excavator_tycoons_diff_0.dds", texture).unwrap();
+        }
+        let mut rdm = RdModell::from(&model);
+        let mat = RdMaterial::discover(&model, &rdm.material_textures(), None);
+        assert_eq!(
+            mat.texture_sets()[0].metal,
+            Some(dir.join("excavator_metal_0.dds"))
+        );
+        rdm.mat = Some(mat);
+
+        let out = dir.join("out");
+        fs::create_dir_all(&out).unwrap();
+        gltf_export::build(
+            rdm,
+            Some(out.clone()),
+            false,
+            GltfExportFormat::GltfSeparate,
+            UnitConversion::default(),
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("out.gltf")).unwrap()).unwrap();
+        let material = &json["materials"][0];
+        assert_eq!(
+            material["pbrMetallicRoughness"]["baseColorTexture"]["index"],
+            0
+        );
+        assert_eq!(material["normalTexture"]["index"], 1);
+        assert_eq!(
+            material["pbrMetallicRoughness"]["metallicRoughnessTexture"]["index"],
+            2
+        );
+        // no height texture, no occlusion
+        assert!(material.get("occlusionTexture").is_none());
+        assert!(out.join("excavator_norm_0.PNG").is_file());
+        assert!(out.join("excavator_metal_0_orm.PNG").is_file());
+
+        // a diffuse or normal map that can not be read leaves the slot empty
+        fs::write(dir.join("excavator_diff_0.dds"), b"not a dds").unwrap();
+        fs::write(dir.join("excavator_norm_0.dds"), b"not a dds").unwrap();
+        let mut rdm = RdModell::from(&model);
+        rdm.mat = Some(RdMaterial::discover(&model, &rdm.material_textures(), None));
+        let out = dir.join("out_broken");
+        fs::create_dir_all(&out).unwrap();
+        gltf_export::build(
+            rdm,
+            Some(out.clone()),
+            false,
+            GltfExportFormat::GltfSeparate,
+            UnitConversion::default(),
         );
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(out.join("out.gltf")).unwrap()).unwrap();
+        let material = &json["materials"][0];
+        assert!(material.get("normalTexture").is_none());
+        assert!(material["pbrMetallicRoughness"]
+            .get("baseColorTexture")
+            .is_none());
+        assert_eq!(
+            material["pbrMetallicRoughness"]["metallicRoughnessTexture"]["index"],
+            0
+        );
+        assert_eq!(json["images"].as_array().unwrap().len(), 1);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn pbr_channel_remap() {
+        let dir = Path::new("gltf_out7");
+        fs::create_dir_all(dir).unwrap();
+        let solid = |name: &str, rgba: [u8; 4]| {
+            let image = RgbaImage {
+                width: 8,
+                height: 8,
+                pixels: rgba.repeat(64),
+            };
+            let path = dir.join(name);
+            dds_encode::write_dds(&path, &image, BcEncoding::Bc7).unwrap();
+            path
+        };
+        let set = TextureSet {
+            diff: Some(PathBuf::from("rdm/excavator_tycoons_diff_0.dds")),
+            norm: Some(solid("cutout_norm_0.dds", [191, 128, 0, 255])),
+            metal: Some(solid("cutout_metal_0.dds", [255, 204, 0, 255])),
+            height: Some(solid("cutout_height_0.dds", [100, 100, 100, 255])),
+            alpha_cutoff: Some(0.5),
+        };
+        let mat = RdMaterial::from_texture_sets(vec![set.clone()]);
+        let close = |a: &[u8], b: &[u8]| a.iter().zip(b).all(|(a, b)| a.abs_diff(*b) <= 3);
+
+        // X 0.5, Y 0: Z 0.87
+        let normal = mat.normal_map(set.norm.as_ref().unwrap()).unwrap();
+        assert!(close(&normal.pixels[..4], &[191, 128, 238, 255]));
+        // occlusion from height, roughness = 1 - gloss, metallic
+        let orm = mat.occlusion_roughness_metallic(&set).unwrap().unwrap();
+        assert!(close(&orm.pixels[..4], &[100, 51, 255, 255]));
+
+        let mut rdm = RdModell::from("rdm/fishery_others_cutout_lod0.rdm");
+        assert!(is_cutout_model(Path::new(
+            "rdm/fishery_others_cutout_lod0.rdm"
+        )));
+        rdm.mat = Some(mat);
+        gltf_export::build(
+            rdm,
+            Some(dir.into()),
+            false,
+            GltfExportFormat::GltfSeparate,
+            UnitConversion::default(),
+        );
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(dir.join("out.gltf")).unwrap()).unwrap();
+        let material = &json["materials"][0];
+        assert_eq!(material["alphaMode"], "MASK");
+        assert_eq!(material["alphaCutoff"], 0.5);
+        let orm_index = &material["pbrMetallicRoughness"]["metallicRoughnessTexture"]["index"];
+        assert_eq!(&material["occlusionTexture"]["index"], orm_index);
+        assert!(material["normalTexture"]["index"].is_u64());
+        assert!(dir.join("cutout_metal_0_orm.PNG").is_file());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn cfg_scene() {
+        let dir = Path::new("gltf_out8");
+        fs::create_dir_all(dir.join("maps")).unwrap();
+        fs::create_dir_all(dir.join("anim")).unwrap();
+        fs::copy(
+            "rdm/basalt_crusher_others_lod2.rdm",
+            dir.join("basalt_crusher_others_lod2.rdm"),
+        )
+        .unwrap();
+        fs::copy(
+            "rdm/basalt_crusher_others_work01.rdm",
+            dir.join("anim/basalt_crusher_others_work01.rdm"),
+        )
+        .unwrap();
+        fs::copy(
+            "rdm/excavator_tycoons_diff_0.dds",
+            dir.join("maps/basalt_crusher_diff_0.dds"),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("basalt_crusher_others.cfg"),
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<Config>
+  <ConfigType>MAIN</ConfigType>
+  <Models>
+    <Config>
+      <ConfigType>MODEL</ConfigType>
+      <FileName>data/graphics/buildings/basalt_crusher/rdm/basalt_crusher_others_lod0.rdm</FileName>
+      <Materials>
+        <Config>
+          <ConfigType>MATERIAL</ConfigType>
+          <Name>plain</Name>
+          <cModelDiffTex>data/graphics/buildings/basalt_crusher/maps/basalt_crusher_diff.psd</cModelDiffTex>
+        </Config>
+        <Config>
+          <ConfigType>MATERIAL</ConfigType>
+          <Name>Brick Wall</Name>
+          <ALPHA_TEST_ENABLED>1</ALPHA_TEST_ENABLED>
+        </Config>
+      </Materials>
+      <Animations>
+        <Config>
+          <ConfigType>ANIMATION</ConfigType>
+          <FileName>data/graphics/buildings/basalt_crusher/anim/basalt_crusher_others_work01.rdm</FileName>
+        </Config>
+      </Animations>
+    </Config>
+  </Models>
+</Config>
+"#,
+        )
+        .unwrap();
+
+        let cfg = Cfg::open(&dir.join("basalt_crusher_others.cfg")).unwrap();
+        let model = cfg
+            .model_for(&dir.join("basalt_crusher_others_lod2.rdm"))
+            .unwrap();
+        assert_eq!(
+            cfg.resolve(&model.animations[0].file_name, None),
+            Some(dir.join("anim/basalt_crusher_others_work01.rdm"))
+        );
+        let mat = cfg.material(model, None);
+        let sets = mat.texture_sets();
+        assert_eq!(
+            sets[0].diff,
+            Some(dir.join("maps/basalt_crusher_diff_0.dds"))
+        );
+        assert_eq!(sets[1].diff, None);
+        assert_eq!(sets[1].alpha_cutoff, Some(0.5));
+
+        // glTF materials "brick wall", "plain" in the cfg order "plain", "Brick Wall"
+        let i_gltf =
+            gltf_reader::ImportedGltf::try_from(Path::new("rdm/gltf/textured.gltf")).unwrap();
+        assert_eq!(
+            Cfg::material_order(model, &i_gltf.material_names()).unwrap(),
+            vec![1, 0]
+        );
+        assert!(matches!(
+            Cfg::material_order(model, &[Some(String::from("glass"))]),
+            Err(CfgError::UnknownMaterial { .. })
+        ));
+    }
+
+    type RdaBlock<'a> = (u32, &'a [(&'a str, &'a [u8])]);
+
+    /// RDA 2.2 archive of `blocks`, each block its flags (1 = zlib, 4 = resident) and a list of (name, data).
+    fn write_rda(path: &Path, blocks: &[RdaBlock]) {
+        use flate2::{write::ZlibEncoder, Compression};
+        use std::io::Write;
+        let zlib = |data: &[u8]| {
+            let mut e = ZlibEncoder::new(Vec::new(), Compression::default());
+            e.write_all(data).unwrap();
+            e.finish().unwrap()
+        };
+
+        let mut out = b"Resource File V2.2".to_vec();
+        out.resize(18 + 766 + 8, 0);
+        let mut link = 18 + 766;
+        for (flags, files) in blocks {
+            let resident = flags & 4 != 0;
+            let mut dir = Vec::new();
+            // resident blocks keep all files in one buffer, compressed as a whole
+            let mut buffer = Vec::new();
+            for (name, data) in files.iter() {
+                let stored = if flags & 1 != 0 && !resident {
+                    zlib(data)
+                } else {
+                    data.to_vec()
+                };
+                let mut utf16: Vec<u8> = name.encode_utf16().flat_map(u16::to_le_bytes).collect();
+                utf16.resize(520, 0);
+                dir.extend(utf16);
+                let offset = if resident { buffer.len() } else { out.len() };
+                for value in [offset, stored.len(), data.len(), 0, 0] {
+                    dir.extend((value as u64).to_le_bytes());
+                }
+                if resident {
+                    buffer.extend(stored);
+                } else {
+                    out.extend(stored);
+                }
+            }
+            if resident {
+                let stored = if flags & 1 != 0 {
+                    zlib(&buffer)
+                } else {
+                    buffer.clone()
+                };
+                out.extend(&stored);
+                out.extend((stored.len() as u64).to_le_bytes());
+                out.extend((buffer.len() as u64).to_le_bytes());
+            }
+            let dir_size = dir.len();
+            let dir = if flags & 1 != 0 { zlib(&dir) } else { dir };
+            out.extend(&dir);
+            let block = out.len() as u64;
+            out[link..link + 8].copy_from_slice(&block.to_le_bytes());
+            out.extend(flags.to_le_bytes());
+            out.extend((files.len() as u32).to_le_bytes());
+            out.extend((dir.len() as u64).to_le_bytes());
+            out.extend((dir_size as u64).to_le_bytes());
+            link = out.len();
+            out.extend(0u64.to_le_bytes());
+        }
+        let end = out.len() as u64;
+        out[link..link + 8].copy_from_slice(&end.to_le_bytes());
+        fs::write(path, out).unwrap();
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn rda_archive() {
+        let dir = Path::new("gltf_out9");
+        fs::create_dir_all(dir).unwrap();
+        let model = fs::read("rdm/basalt_crusher_others_lod2.rdm").unwrap();
+        let anim = fs::read("rdm/basalt_crusher_others_work01.rdm").unwrap();
+        let texture = fs::read("rdm/excavator_tycoons_diff_0.dds").unwrap();
+        let archive = dir.join("data0.rda");
+        write_rda(
+            &archive,
+            &[
+                (
+                    0,
+                    &[
+                        ("data/graphics/crusher/rdm/crusher_lod2.rdm", &model),
+                        ("data/graphics/crusher/maps/crusher_diff_0.dds", b"old"),
+                    ],
+                ),
+                (
+                    1,
+                    &[
+                        ("data/graphics/crusher/anim/crusher_work01.rdm", &anim),
+                        ("data/graphics/crusher/maps/crusher_diff_0.dds", &texture),
+                    ],
+                ),
+                (
+                    1 | 4,
+                    &[
+                        ("data/config/crusher.txt", b"resident"),
+                        ("data/config/other.txt", b"second file"),
+                    ],
+                ),
+            ],
+        );
+
+        let rda = RdaArchive::open(&archive).unwrap();
+        assert_eq!(rda.entries().len(), 6);
+        assert_eq!(rda.entries()[2].size, anim.len() as u64);
+        assert_eq!(rda.read_file("config/crusher.txt").unwrap(), b"resident");
+        assert_eq!(rda.read_file("config/other.txt").unwrap(), b"second file");
+        assert_eq!(
+            rda.read_file("graphics/crusher/anim/crusher_work01.rdm")
+                .unwrap(),
+            anim
+        );
+        // the later block wins
+        assert_eq!(
+            rda.read_file("data/graphics/crusher/maps/crusher_diff_0.dds")
+                .unwrap(),
+            texture
+        );
+        assert!(matches!(
+            rda.read_file("crusher_lod0.rdm"),
+            Err(RdaError::NotFound(_))
+        ));
+
+        let out = dir.join("extracted");
+        let written = rda.extract("data/graphics/crusher/rdm", &out).unwrap();
+        assert_eq!(
+            written,
+            vec![out.join("data/graphics/crusher/rdm/crusher_lod2.rdm")]
+        );
+        assert_eq!(fs::read(&written[0]).unwrap(), model);
+
+        // models, animations and textures straight from the archive
+        let in_archive = |name: &str| PathBuf::from(format!("{}!/{}", archive.display(), name));
+        let mut rdm = RdModell::from(in_archive("graphics/crusher/rdm/crusher_lod2.rdm"));
+        assert_eq!(rdm.vertex.len(), 2615);
+        let anim = RdAnim::from(in_archive("graphics/crusher/anim/crusher_work01.rdm"));
+        assert_eq!(anim.name, "crusher_work01");
+        rdm.add_skin();
+        rdm.add_anim(anim);
+        let model = in_archive("graphics/crusher/rdm/crusher_lod2.rdm");
+        // the archive as data root of the texture paths stored in the rdm
+        let reference = Some(String::from(
+            "d:/data/graphics/crusher/maps/crusher_diff.psd",
+        ));
+        let root = PathBuf::from(format!("{}!", archive.display()));
+        let mat = RdMaterial::discover(&model, &[reference], Some(&root));
+        assert_eq!(
+            mat.texture_sets()[0].diff,
+            Some(in_archive("data/graphics/crusher/maps/crusher_diff_0.dds"))
+        );
+        assert!(Dds::open(mat.texture_sets()[0].diff.as_ref().unwrap()).is_ok());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn dds_decode_mipmaps() {
+        let bc7 = Dds::open(Path::new("rdm/brick_wall_white_estate_01_diff_0.dds")).unwrap();
+        assert_eq!(bc7.format(), DdsFormat::Bc7);
+        assert_eq!(bc7.mip_count(), 10);
+        let bc1 = Dds::open(Path::new("rdm/excavator_tycoons_diff_0.dds")).unwrap();
+        assert_eq!(bc1.format(), DdsFormat::Bc1);
+
+        for dds in [bc7, bc1] {
+            let mip0 = dds.decode(0).unwrap();
+            let mip1 = dds.decode(1).unwrap();
+            assert_eq!((mip0.width, mip0.height), (dds.width(), dds.height()));
+            assert_eq!((mip1.width, mip1.height), dds.mip_size(1));
+
+            // the stored mipmap 1 matches a 2x2 box filter of the decoded level 0
+            let w = mip0.width as usize;
+            let mut error = 0;
+            for (i, px) in mip1.pixels.chunks_exact(4).enumerate() {
+                let (x, y) = (i % mip1.width as usize * 2, i / mip1.width as usize * 2);
+                for (c, v) in px.iter().enumerate() {
+                    let sum: u32 = [(0, 0), (1, 0), (0, 1), (1, 1)]
+                        .iter()
+                        .map(|(dx, dy)| u32::from(mip0.pixels[((y + dy) * w + x + dx) * 4 + c]))
+                        .sum();
+                    error += (sum as i32 / 4 - i32::from(*v)).unsigned_abs();
+                }
+            }
+            assert!(error / (mip1.pixels.len() as u32) < 4, "{}", error);
+
+            let last = dds.decode(dds.mip_count() - 1).unwrap();
+            let (w, h) = dds.mip_size(dds.mip_count() - 1);
+            assert_eq!(last.pixels.len(), (w * h * 4) as usize);
+            assert!(dds.decode(dds.mip_count()).is_err());
+        }
+
+        let png = Dds::open(Path::new("rdm/brick_wall_white_estate_01_diff_0.dds"))
+            .unwrap()
+            .decode(2)
+            .unwrap()
+            .to_png()
+            .unwrap();
+        assert_eq!(&png[1..4], b"PNG");
     }
 
     #[test]
@@ -218,19 +742,21 @@ mod tests {
     fn read_gltf_skin_round_trip() {
         let f_path = Path::new("rdm/gltf/stormtrooper_with_tangent.gltf");
         let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
-        let mut rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
+        let (mut rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
             &i_gltf,
             TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b,
             true,
             false,
             true,
             None,
-        );
+        )
+        .unwrap();
         assert_eq!(rdm.vertex.len(), 5184);
 
         let jj = rdm.joints.clone().unwrap();
         let mut anims =
-            gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, 6, 0.33333).unwrap();
+            gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+                .unwrap();
 
         assert_eq!(anims.len(), 1);
         let anim = anims.pop().unwrap();
@@ -245,13 +771,886 @@ mod tests {
             Some(Path::new("gltf_out3").into()),
             false,
             GltfExportFormat::GltfSeparate,
+            UnitConversion::default(),
         );
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_skin_follows_node_transform() {
+        // "body" is at (5, 0, 0), turned by 90° around y. "bone" is 1 above "root", which moves by (2, 0, 0).
+        let i_gltf =
+            gltf_reader::ImportedGltf::try_from(Path::new("rdm/gltf/skinned_moved.gltf")).unwrap();
+        let format = TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b;
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            format.clone(),
+            true,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let assert_near = |a: &[f32], b: &[f32]| {
+            assert!(
+                a.iter().zip(b).all(|(a, b)| (a - b).abs() < 1e-4),
+                "{:?} != {:?}",
+                a,
+                b
+            );
+        };
+
+        // the skeleton moved with the mesh
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        let joint = |name: &str| jj.iter().find(|j| j.name() == name).unwrap();
+        assert_near(&joint("root").transition(), &[5.0, 0.0, 0.0]);
+        assert_near(&joint("root").quaternion(), &[0.0, half, 0.0, half]);
+        assert_near(&joint("bone").transition(), &[5.0, 1.0, 0.0]);
+        assert_near(&joint("bone").quaternion(), &[0.0, half, 0.0, half]);
+
+        let frames = |name: &str| {
+            anim.anim_vec
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+                .frames
+                .clone()
+        };
+        // keys of the root joint are global: (2, 0, 0) ends up at (5, 0, -2), rdm stores the inverse rotation
+        let root = frames("root");
+        assert_near(&root[0].translation, &[5.0, 0.0, 0.0]);
+        assert_near(&root[1].translation, &[5.0, 0.0, -2.0]);
+        assert_near(&root[0].rotation, &[0.0, -half, 0.0, half]);
+        // keys of child joints are relative to the parent and keep their values
+        let (unbaked, unbaked_transform) =
+            gltf_reader::ImportedGltf::gltf_to_rdm(&i_gltf, format, true, false, true, None)
+                .unwrap();
+        let unbaked_anim = gltf_reader::ImportedGltf::read_animation(
+            &i_gltf,
+            unbaked.joints.as_ref().unwrap(),
+            &unbaked_transform,
+            6,
+            false,
+            None,
+        )
+        .unwrap()
+        .pop()
+        .unwrap();
+        let unbaked_bone = &unbaked_anim
+            .anim_vec
+            .iter()
+            .find(|c| c.name == "bone")
+            .unwrap()
+            .frames;
+        let bone = frames("bone");
+        assert_eq!(bone.len(), unbaked_bone.len());
+        for (b, u) in bone.iter().zip(unbaked_bone) {
+            assert_near(&b.translation, &u.translation);
+            assert_near(&b.rotation, &u.rotation);
+        }
+        assert_near(&bone[0].translation, &[0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_skin_node_scale() {
+        // "root" has scale (2, 3, 1), the inverse bind matrices have no scale
+        let i_gltf =
+            gltf_reader::ImportedGltf::try_from(Path::new("rdm/gltf/skinned_node_scale.gltf"))
+                .unwrap();
+        let (rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+
+        // the vertices are moved into the scaled rest pose
+        let positions = rdm
+            .vertex
+            .decode::<3>(UniqueIdentifier::Position, 0)
+            .unwrap();
+        assert_eq!(positions[1], [2.0, 0.0, 0.0]);
+        assert_eq!(positions[2], [0.0, 6.0, 0.0]);
+
+        // and so is the bind pose of the child
+        let joints = rdm.joints.unwrap();
+        let joint = |name: &str| joints.iter().find(|j| j.name() == name).unwrap();
+        assert_eq!(joint("root").transition(), [0.0, 0.0, 0.0]);
+        assert_eq!(joint("bone").transition(), [0.0, 3.0, 0.0]);
+        assert_eq!(joint("bone").quaternion(), [0.0, 0.0, 0.0, 1.0]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_negative_x() {
+        let f_path = Path::new("rdm/gltf/stormtrooper_with_tangent.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let read = |negative_x_and_v0v2v1| {
+            let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+                &i_gltf,
+                TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b,
+                true,
+                negative_x_and_v0v2v1,
+                true,
+                None,
+            )
+            .unwrap();
+            let jj = rdm.joints.unwrap();
+            gltf_reader::ImportedGltf::read_animation(
+                &i_gltf,
+                &jj,
+                &baked,
+                6,
+                negative_x_and_v0v2v1,
+                None,
+            )
+            .unwrap()
+            .pop()
+            .unwrap()
+        };
+        let anim = read(false);
+        let mirrored = read(true);
+
+        assert_eq!(anim.anim_vec.len(), mirrored.anim_vec.len());
+        for (a, m) in anim.anim_vec.iter().zip(mirrored.anim_vec.iter()) {
+            assert_eq!(a.name, m.name);
+            for (fa, fm) in a.frames.iter().zip(m.frames.iter()) {
+                assert_eq!(fa.time, fm.time);
+                assert!((fa.translation[0] + fm.translation[0]).abs() < 1e-4);
+                assert!((fa.translation[1] - fm.translation[1]).abs() < 1e-4);
+                // a quaternion and its negation are the same rotation
+                let sign = if (fa.rotation[3] - fm.rotation[3]).abs() < 1e-4 {
+                    1.0
+                } else {
+                    -1.0
+                };
+                assert!((fa.rotation[0] - sign * fm.rotation[0]).abs() < 1e-4);
+                assert!((fa.rotation[1] + sign * fm.rotation[1]).abs() < 1e-4);
+                assert!((fa.rotation[2] + sign * fm.rotation[2]).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_pivot_bbox_bottom_centre() {
+        let f_path = Path::new("rdm/gltf/stormtrooper_with_tangent.gltf");
+        let mut i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        i_gltf.pivot = "bbox-bottom-centre".parse().unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+
+        let positions = rdm
+            .vertex
+            .decode::<3>(UniqueIdentifier::Position, 0)
+            .unwrap();
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for p in positions.iter() {
+            for i in 0..3 {
+                min[i] = min[i].min(p[i]);
+                max[i] = max[i].max(p[i]);
+            }
+        }
+        assert!(min[1].abs() < 1e-3);
+        assert!((min[0] + max[0]).abs() < 1e-2);
+        assert!((min[2] + max[2]).abs() < 1e-2);
+
+        let jj = rdm.joints.unwrap();
+        let moved = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let kept = gltf_reader::BakedTransform {
+            pivot_offset: None,
+            ..baked
+        };
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &kept, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let changed = anim
+            .anim_vec
+            .iter()
+            .zip(moved.anim_vec.iter())
+            .filter(|(a, m)| a.frames[0].translation != m.frames[0].translation)
+            .count();
+        assert!(changed > 0 && changed < anim.anim_vec.len());
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_merge_scene() {
+        let f_path = Path::new("rdm/gltf/scene_props.gltf");
+        let mut i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        i_gltf.merge_scene = true;
+        let (rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h,
+            false,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+
+        // prop_a, its child prop_a_top and prop_b: one triangle each
+        assert_eq!(rdm.vertex.len(), 9);
+        assert_eq!(rdm.triangle_indices.len(), 3);
+        let slots: Vec<_> = rdm
+            .mesh_info
+            .iter()
+            .map(|m| (m.material, m.start_index_location, m.index_count))
+            .collect();
+        assert_eq!(slots, vec![(0, 0, 6), (1, 6, 3)]);
+
+        let positions = rdm
+            .vertex
+            .decode::<3>(UniqueIdentifier::Position, 0)
+            .unwrap();
+        assert!(positions.contains(&[1.0, 3.0, 0.0]));
+        assert!(positions.contains(&[1.0, 0.0, 0.0]));
+        assert!(positions.contains(&[-1.0, 2.0, 0.0]));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_encode_textures() {
+        let f_path = Path::new("rdm/gltf/textured.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        assert_eq!(i_gltf.material_slots(), vec![Some(0), Some(1)]);
+
+        let dir = Path::new("rdm_out/textured");
+        fs::create_dir_all(dir).unwrap();
+        let model = dir.join("house_lod0.rdm");
+        assert_eq!(texture_set_name(&model), "house");
+        let textures = i_gltf
+            .write_textures(dir, &texture_set_name(&model), BcEncoding::Bc7, None)
+            .unwrap();
+        assert_eq!(textures.len(), 2);
+
+        let brick = &textures[0];
+        assert_eq!(brick.diff, Some(dir.join("house_brick_wall_diff_0.dds")));
+        let diff = Dds::open(brick.diff.as_ref().unwrap()).unwrap();
+        assert_eq!(diff.format(), DdsFormat::Bc7);
+        assert_eq!((diff.width(), diff.height(), diff.mip_count()), (8, 8, 4));
+        let norm = Dds::open(brick.norm.as_ref().unwrap()).unwrap();
+        assert_eq!(norm.mip_count(), 4);
+        let metal = Dds::open(brick.metal.as_ref().unwrap()).unwrap();
+        assert_eq!((metal.width(), metal.mip_count()), (4, 3));
+
+        // no texture: solid diffuse of the base color factor
+        let plain = &textures[1];
+        assert!(plain.norm.is_none() && plain.metal.is_none());
+        let red = Dds::open(plain.diff.as_ref().unwrap())
+            .unwrap()
+            .decode(0)
+            .unwrap();
+        assert_eq!(&red.pixels[..4], &[255, 0, 0, 255]);
+
+        let (mut rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h,
+            false,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+        rdm.mat = Some(RdMaterial::from_texture_sets(textures));
+        let written = RdWriter2::new(rdm).write_rdm(Some(model), false);
+        let bytes = fs::read(written).unwrap();
+        let recorded = b"rdm_out/textured/house_plain_diff_0.dds";
+        assert!(bytes.windows(recorded.len()).any(|w| w == recorded));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_split_nodes() {
+        let f_path = Path::new("rdm/gltf/scene_props.gltf");
+        let mut i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        assert_eq!(i_gltf.split_targets(SplitMode::Mesh), vec![0, 1]);
+
+        let targets = i_gltf.split_targets(SplitMode::Node);
+        assert_eq!(targets, vec![0, 2, 1]);
+
+        let mut names = Vec::new();
+        let mut mesh_names = Vec::new();
+        for (index, node) in targets.into_iter().enumerate() {
+            i_gltf.change_mesh_node(node);
+            names.push(i_gltf.output_name("{mesh}/{node} {index}", index, &names));
+            mesh_names.push(i_gltf.output_name("{mesh}", index, &mesh_names));
+
+            let (rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
+                &i_gltf,
+                TargetVertexFormat::P4h_N4b_G4b_B4b_T2h,
+                false,
+                false,
+                false,
+                None,
+            )
+            .unwrap();
+            assert_eq!(rdm.vertex.len(), 3);
+            if node == 2 {
+                // prop_a_top: child of prop_a
+                let positions = rdm
+                    .vertex
+                    .decode::<3>(UniqueIdentifier::Position, 0)
+                    .unwrap();
+                assert_eq!(positions[0], [1.0, 2.0, 0.0]);
+            }
+        }
+        assert_eq!(
+            names,
+            vec!["tri_a_prop_a_0", "tri_a_prop_a_top_1", "tri_b_prop_b_2"]
+        );
+        // prop_a and prop_a_top instance the same mesh
+        assert_eq!(mesh_names, vec!["tri_a", "tri_a_1", "tri_b"]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn select_by_name() {
+        let f_path = Path::new("rdm/gltf/scene_props.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_import(
+            f_path,
+            MeshSelector::NodeName("prop_a_top".into()),
+            ResolveNodeName::UniqueName,
+        )
+        .unwrap();
+        let (rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h,
+            false,
+            false,
+            false,
+            None,
+        )
+        .unwrap();
+        let positions = rdm
+            .vertex
+            .decode::<3>(UniqueIdentifier::Position, 0)
+            .unwrap();
+        assert_eq!(positions[0], [1.0, 2.0, 0.0]);
+
+        match gltf_reader::ImportedGltf::try_import(
+            f_path,
+            MeshSelector::MeshName("tri_c".into()),
+            ResolveNodeName::UniqueName,
+        ) {
+            Err(GltfImportError::NotFound { available, .. }) => {
+                assert_eq!(available, vec!["tri_a", "tri_b"])
+            }
+            _ => panic!("expected NotFound"),
+        }
+
+        let f_path = Path::new("rdm/gltf/stormtrooper_with_tangent.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let read = |name| {
+            gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, Some(name))
+        };
+        assert_eq!(read("mixamo.com").unwrap().len(), 1);
+        let err = read("walk").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "no animation named \"walk\". Available: [\"mixamo.com\"]"
+        );
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_resample() {
+        let f_path = Path::new("rdm/gltf/skinned_resample.gltf");
+        let mut i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let frames = |anim: &rdm4lib::rdm_anim::RdAnim, name: &str| {
+            anim.anim_vec
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+                .frames
+                .clone()
+        };
+
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(anim.name, "wave");
+        assert_eq!(
+            rdm_data_anim::anim_file_name(
+                rdm_data_anim::ANIM_NAME_TEMPLATE,
+                "body_lod0",
+                &anim.name
+            ),
+            "body_lod0_wave"
+        );
+        // LINEAR with matching keys: copied as is
+        assert_eq!(frames(&anim, "root").len(), 2);
+        // CUBICSPLINE rotation and STEP translation on other keys: resampled at 30 fps,
+        // with the STEP key at 0.25 and the held value just before it
+        let bone = frames(&anim, "bone");
+        assert_eq!(bone.len(), 33);
+        assert_eq!(bone[32].time, 1.0);
+        let mid = &bone[17];
+        assert!((mid.rotation[2] - (std::f32::consts::PI / 8.0).sin()).abs() < 1e-4);
+        assert!((mid.rotation[3] + (std::f32::consts::PI / 8.0).cos()).abs() < 1e-4);
+        assert_eq!(bone[8].translation, [0.0, 1.0, 0.0]);
+        assert_eq!(bone[9].time, 0.25);
+        assert_eq!(bone[9].translation, [0.0, 1.5, 0.0]);
+
+        i_gltf.anim_fps = Some(10.0);
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let root = frames(&anim, "root");
+        assert_eq!(root.len(), 11);
+        assert!((root[5].translation[0] - 1.0).abs() < 1e-5);
+        assert_eq!(frames(&anim, "bone").len(), 13);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_step() {
+        let f_path = Path::new("rdm/gltf/skinned_step.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let root = &anim
+            .anim_vec
+            .iter()
+            .find(|c| c.name == "root")
+            .unwrap()
+            .frames;
+        // STEP translation: resampled at 30 fps, the first value held until just before the last key
+        assert_eq!(root.len(), 32);
+        let held = &root[30];
+        assert!(held.time > 0.99 && held.time < 1.0);
+        assert_eq!(held.translation, root[0].translation);
+        assert_eq!(root[31].time, 1.0);
+        assert!((root[31].translation[0] - 2.0).abs() < 1e-5);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_scale() {
+        let f_path = Path::new("rdm/gltf/skinned_scale.gltf");
+        let mut i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let read = |i_gltf: &gltf_reader::ImportedGltf, name| {
+            gltf_reader::ImportedGltf::read_animation(i_gltf, &jj, &baked, 6, false, Some(name))
+        };
+
+        match read(&i_gltf, "grow") {
+            Err(GltfImportError::ScaleChannels { joints, .. }) => {
+                // identity scale of "bone" is not reported
+                assert_eq!(joints.len(), 1);
+                assert!(joints[0].starts_with("root:"));
+            }
+            _ => panic!("scale channel must be rejected"),
+        }
+
+        i_gltf.scale_channels = ScaleChannels::Bake;
+        let anim = read(&i_gltf, "grow").unwrap().pop().unwrap();
+        let bone = &anim
+            .anim_vec
+            .iter()
+            .find(|c| c.name == "bone")
+            .unwrap()
+            .frames;
+        assert_eq!(bone.len(), 31);
+        assert_eq!(bone[0].translation, [0.0, 1.0, 0.0]);
+        assert!((bone[15].translation[1] - 1.5).abs() < 1e-5);
+        assert!((bone[30].translation[1] - 2.0).abs() < 1e-5);
+
+        match read(&i_gltf, "squash") {
+            Err(GltfImportError::ScaleChannels { joints, .. }) => {
+                assert_eq!(joints.len(), 1);
+                assert!(joints[0].starts_with("bone:"));
+            }
+            _ => panic!("non-uniform scale can not be baked"),
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_scale_for_rdm_skeleton() {
+        let f_path = Path::new("rdm/gltf/skinned_scale.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+
+        // "bone", the child of the scaled "root", only exists in the rdm skeleton
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(f_path).unwrap()).unwrap();
+        json["nodes"][2]["name"] = serde_json::json!("tip");
+        let dir = PathBuf::from("rdm_out/read_gltf_anim_scale_for_rdm_skeleton");
+        fs::create_dir_all(&dir).unwrap();
+        let gltf = dir.join("rdm_only.gltf");
+        fs::write(&gltf, json.to_string()).unwrap();
+        let mut anim_gltf = gltf_reader::ImportedGltf::try_from(gltf.as_path()).unwrap();
+        anim_gltf.scale_channels = ScaleChannels::Bake;
+
+        let anim = gltf_reader::ImportedGltf::read_animation(
+            &anim_gltf,
+            &jj,
+            &baked,
+            6,
+            false,
+            Some("grow"),
+        )
+        .unwrap()
+        .pop()
+        .unwrap();
+        let bone = &anim
+            .anim_vec
+            .iter()
+            .find(|c| c.name == "bone")
+            .unwrap()
+            .frames;
+        // idle keys from the bind pose
+        assert_eq!(bone.len(), 7);
+        assert!(bone.iter().all(|f| f.translation == [0.0, 1.0, 0.0]));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_node_scale() {
+        // "root" has scale (2, 3, 1) and a scale channel repeating it
+        let f_path = Path::new("rdm/gltf/skinned_node_scale.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let frames = |name: &str| {
+            anim.anim_vec
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+                .frames
+                .clone()
+        };
+        let bone = frames("bone");
+        assert_eq!(bone[0].translation, [0.0, 3.0, 0.0]);
+        assert_eq!(bone[30].translation, [0.0, 4.5, 0.0]);
+        // the scaled node itself is not affected
+        assert_eq!(frames("root")[1].translation, [2.0, 0.0, 0.0]);
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_scale_chain() {
+        // "root" has scale (2, 1, 1), its child "bone" is turned by 90° around z, 180° at the end
+        let f_path = Path::new("rdm/gltf/skinned_scale_chain.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let frames = |name: &str| {
+            anim.anim_vec
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+                .frames
+                .clone()
+        };
+        let assert_near = |a: [f32; 3], b: [f32; 3]| {
+            assert!(
+                (0..3).all(|i| (a[i] - b[i]).abs() < 1e-5),
+                "{:?} != {:?}",
+                a,
+                b
+            );
+        };
+        // the parent scale applies along the axes of the parent
+        assert_near(frames("bone")[0].translation, [2.0, 0.0, 0.0]);
+        // the x scale of "root" stretches the y translation of "tip" while "bone" points it along x
+        let tip = frames("tip");
+        assert_near(tip[0].translation, [0.0, 4.0, 0.0]);
+        assert_near(tip[6].translation, [0.0, 2.0, 0.0]);
+
+        // a joint with zero scale has no rotation
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(f_path).unwrap()).unwrap();
+        json["nodes"][1]["scale"] = serde_json::json!([0.0, 1.0, 1.0]);
+        let dir = PathBuf::from("rdm_out/read_gltf_anim_scale_chain");
+        fs::create_dir_all(&dir).unwrap();
+        let gltf = dir.join("zero_scale.gltf");
+        fs::write(&gltf, json.to_string()).unwrap();
+        let result = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &gltf_reader::ImportedGltf::try_from(gltf.as_path()).unwrap(),
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        );
+        assert!(matches!(result, Err(GltfImportError::Transform(_))));
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_duplicate_joint_names() {
+        // both joints are called "root"
+        let f_path = Path::new("rdm/gltf/skinned_duplicate_names.gltf");
+        let i_gltf =
+            gltf_reader::ImportedGltf::try_import(f_path, 0, ResolveNodeName::Disambiguate)
+                .unwrap();
+        assert_eq!(i_gltf.renamed_nodes(), vec![(2, Some("root"), "root/root")]);
+
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        let mut names: Vec<&str> = anim.anim_vec.iter().map(|c| c.name.as_str()).collect();
+        names.sort();
+        assert_eq!(names, vec!["root", "root/root"]);
+        // the channels of the child still reach it
+        let child = anim
+            .anim_vec
+            .iter()
+            .find(|c| c.name == "root/root")
+            .unwrap();
+        assert_eq!(child.frames.len(), 33);
+
+        let dir = PathBuf::from("rdm_out/duplicate_names");
+        fs::create_dir_all(&dir).unwrap();
+        let map = dir.join("out_joints.json");
+        i_gltf.write_node_name_map(&map).unwrap();
+        let json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(map).unwrap()).unwrap();
+        assert_eq!(json["nodes"][0]["rdm"], "root/root");
+        assert_eq!(json["nodes"][0]["gltf"], "root");
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_for_rdm_skeleton() {
+        let i_gltf = gltf_reader::ImportedGltf::try_from(Path::new(
+            "rdm/gltf/stormtrooper_with_tangent.gltf",
+        ))
+        .unwrap();
+        let (rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let dir = PathBuf::from("rdm_out/anim_only");
+        fs::create_dir_all(&dir).unwrap();
+        let target = RdWriter2::new(rdm).write_rdm(Some(dir), false);
+
+        let mut rdm = RdModell::from(target.as_path());
+        rdm.add_skin();
+        let jj = rdm.joints.unwrap();
+
+        // the clip only has a "_rootJoint" node
+        let anim_gltf = gltf_reader::ImportedGltf::try_import_without_mesh(
+            Path::new("rdm/gltf/anim_only.gltf"),
+            ResolveNodeName::UniqueName,
+        )
+        .unwrap();
+        let anim = gltf_reader::ImportedGltf::read_animation(
+            &anim_gltf,
+            &jj,
+            &gltf_reader::BakedTransform::default(),
+            0,
+            false,
+            None,
+        )
+        .unwrap()
+        .pop()
+        .unwrap();
+        assert_eq!(anim.name, "slide");
+        assert_eq!(anim.anim_vec.len(), jj.len());
+        let frames = |name: &str| {
+            anim.anim_vec
+                .iter()
+                .find(|c| c.name == name)
+                .unwrap()
+                .frames
+                .clone()
+        };
+        assert_eq!(frames("_rootJoint")[1].translation, [2.0, 0.0, 0.0]);
+        // idle key from the bind pose of the rdm, which matches the glTF rest pose
+        let spine = frames("mixamorig:Spine1_063");
+        assert_eq!(spine.len(), 1);
+        let expected = [-1.557_474_3e-9, 0.217_453_4, -2.387_515_4e-8];
+        for (v, e) in spine[0].translation.iter().zip(expected) {
+            assert!((v - e).abs() < 1e-3);
+        }
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_anim_range_and_idle_keys() {
+        let f_path = Path::new("rdm/gltf/skinned_resample.gltf");
+        let mut i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b_W4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+
+        i_gltf.anim_fps = Some(10.0);
+        i_gltf.anim_range = Some((0.5, 1.0));
+        let anim = gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None)
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert_eq!(anim.time_max, 500);
+        let root = &anim
+            .anim_vec
+            .iter()
+            .find(|c| c.name == "root")
+            .unwrap()
+            .frames;
+        assert_eq!(root.len(), 6);
+        assert_eq!(root[0].time, 0.0);
+        assert!((root[0].translation[0] - 1.0).abs() < 1e-5);
+        assert!((root[5].translation[0] - 2.0).abs() < 1e-5);
+
+        i_gltf.anim_range = Some((2.0, 3.0));
+        assert!(matches!(
+            gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, &baked, 6, false, None),
+            Err(gltf_reader::GltfImportError::TimeRange { .. })
+        ));
+
+        // idle joints: frames + 1 keys, or a single one
+        let f_path = Path::new("rdm/gltf/stormtrooper.gltf");
+        let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &i_gltf,
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b,
+            true,
+            false,
+            true,
+            None,
+        )
+        .unwrap();
+        let jj = rdm.joints.unwrap();
+        let idle_keys = |frames: usize| {
+            let anim = gltf_reader::ImportedGltf::read_animation(
+                &i_gltf, &jj, &baked, frames, false, None,
+            )
+            .unwrap()
+            .pop()
+            .unwrap();
+            anim.anim_vec.iter().map(|c| c.frames.len()).min().unwrap()
+        };
+        assert_eq!(idle_keys(6), 7);
+        assert_eq!(idle_keys(0), 1);
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     fn read_gltf() {
-        let rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
+        let (rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
             &gltf_reader::ImportedGltf::try_from(Path::new(
                 "rdm/gltf/stormtrooper_with_tangent.gltf",
             ))
@@ -261,7 +1660,8 @@ mod tests {
             false,
             false,
             None,
-        );
+        )
+        .unwrap();
         assert_eq!(rdm.vertex.len(), 5184);
         assert_eq!(rdm.joints.as_ref().unwrap().len(), 72);
 
@@ -280,19 +1680,20 @@ mod tests {
     fn read_gltf_anim() {
         let f_path = Path::new("rdm/gltf/stormtrooper_with_tangent.gltf");
         let i_gltf = gltf_reader::ImportedGltf::try_from(f_path).unwrap();
-        let rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
+        let (rdm, baked) = gltf_reader::ImportedGltf::gltf_to_rdm(
             &i_gltf,
             TargetVertexFormat::P4h_N4b_G4b_B4b_T2h_I4b,
             true,
             false,
             false,
             None,
-        );
+        )
+        .unwrap();
         assert_eq!(rdm.vertex.len(), 5184);
 
         let jj = &rdm.joints.unwrap();
         let mut anims =
-            gltf_reader::ImportedGltf::read_animation(&i_gltf, &jj, 6, 0.33333).unwrap();
+            gltf_reader::ImportedGltf::read_animation(&i_gltf, jj, &baked, 6, false, None).unwrap();
 
         assert_eq!(anims.len(), 1);
         let anim = anims.pop().unwrap();
@@ -302,7 +1703,7 @@ mod tests {
         exp_rdm.write_anim_rdm(Some(dir_dst), false);
 
         check_hash(
-            &PathBuf::from("rdm_out/stormtrooper/anim_0.rdm"),
+            &PathBuf::from("rdm_out/stormtrooper/mixamo_com.rdm"),
             "c2183cd7d5c342a6e6e1c8260b5ab93503fe2ad282d851bbee791bf3a1d6c3f1",
         );
     }
@@ -310,14 +1711,15 @@ mod tests {
     #[test]
     #[cfg_attr(miri, ignore)]
     fn read_gltf_no_skin() {
-        let rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
+        let (rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
             &gltf_reader::ImportedGltf::try_from(Path::new("rdm/gltf/stormtrooper.gltf")).unwrap(),
             TargetVertexFormat::P4h_N4b_G4b_B4b_T2h,
             false,
             false,
             false,
             None,
-        );
+        )
+        .unwrap();
         assert_eq!(rdm.vertex.len(), 5184);
 
         let exp_rdm = RdWriter2::new(rdm);
@@ -330,19 +1732,42 @@ mod tests {
         );
     }
 
+    #[test]
+    #[cfg_attr(miri, ignore)]
+    fn read_gltf_zero_scale_node() {
+        let mut json: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string("rdm/gltf/triangle.gltf").unwrap()).unwrap();
+        json["nodes"][0]["scale"] = serde_json::json!([0.0, 1.0, 1.0]);
+        let dir = PathBuf::from("rdm_out/read_gltf_zero_scale_node");
+        fs::create_dir_all(&dir).unwrap();
+        let gltf = dir.join("zero_scale.gltf");
+        fs::write(&gltf, json.to_string()).unwrap();
+
+        let result = gltf_reader::ImportedGltf::gltf_to_rdm(
+            &gltf_reader::ImportedGltf::try_from(gltf.as_path()).unwrap(),
+            TargetVertexFormat::P4h_N4b_G4b_B4b_T2h,
+            false,
+            false,
+            false,
+            None,
+        );
+        assert!(matches!(result, Err(GltfImportError::Transform(_))));
+    }
+
     #[test]
     #[cfg_attr(miri, ignore)]
     #[ignore]
     fn read_gltf_no_skin2_triangle() {
         // no normals so ignore it !
-        let rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
+        let (rdm, _) = gltf_reader::ImportedGltf::gltf_to_rdm(
             &gltf_reader::ImportedGltf::try_from(Path::new("rdm/gltf/triangle.gltf")).unwrap(),
             TargetVertexFormat::P4h_N4b_G4b_B4b_T2h,
             false,
             false,
             false,
             None,
-        );
+        )
+        .unwrap();
         assert_eq!(rdm.vertex.len(), 3);
 
         let exp_rdm = RdWriter2::new(rdm);
@@ -1,42 +1,93 @@
 extern crate rdm4lib;
 
-use rdm4lib::gltf_reader::ResolveNodeName;
-use rdm4lib::rdm_data_anim::RdAnimWriter2;
+use rdm4lib::cfg::{Cfg, CfgMaterial, CfgModel};
+use rdm4lib::dds_encode::BcEncoding;
+use rdm4lib::gltf_reader::{BakedTransform, MeshSelector, Pivot, ResolveNodeName, SplitMode};
+use rdm4lib::gltf_reader_anim::ScaleChannels;
+use rdm4lib::rdm_data_anim::{anim_file_name, RdAnimWriter2, ANIM_NAME_TEMPLATE};
 use rdm4lib::rdm_data_main::RdWriter2;
-use rdm4lib::{gltf_export::GltfExportFormat, vertex::TargetVertexFormat, RdModell};
+use rdm4lib::vertex_transform::{Axis, TransformContext, UnitConversion};
+use rdm4lib::{gltf_export::GltfExportFormat, vertex::TargetVertexFormat, RdJoint, RdModell};
 
 use rdm4lib::gltf_export;
+use rdm4lib::rda;
 use rdm4lib::rdm_anim::RdAnim;
 
-use rdm4lib::{gltf_reader, rdm_material::RdMaterial};
+use rdm4lib::{
+    gltf_reader,
+    rdm_material::{is_cutout_model, texture_set_name, RdMaterial, TextureExport},
+};
 
 #[macro_use]
 extern crate log;
 
-use clap::{ArgAction, Parser};
+use clap::{ArgAction, ArgGroup, Parser};
 use env_logger::Env;
-use std::path::PathBuf;
+use nalgebra::Matrix4;
+use std::path::{Path, PathBuf};
 
 fn cli_in_is_file(v: &str) -> Result<PathBuf, String> {
     let p = PathBuf::from(v);
-    if p.is_file() {
+    if rda::is_file(&p) {
         Ok(p)
     } else {
         Err(format!("No such file {}", v))
     }
 }
 
+fn cli_transform_matrix(v: &str) -> Result<Matrix4<f32>, String> {
+    let values = v
+        .split(',')
+        .map(|x| x.trim().parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|e| format!("Invalid matrix value: {}", e))?;
+    if values.len() != 16 {
+        return Err(format!("Expected 16 values, got {}", values.len()));
+    }
+    let mat = Matrix4::from_row_slice(&values);
+    TransformContext::new(mat).map_err(|e| e.to_string())?;
+    Ok(mat)
+}
+
+fn cli_time_range(v: &str) -> Result<(f32, f32), String> {
+    let (start, end) = v
+        .split_once(',')
+        .ok_or_else(|| String::from("Expected start,end"))?;
+    let parse = |x: &str| {
+        x.trim()
+            .parse::<f32>()
+            .map_err(|e| format!("Invalid time: {}", e))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start < 0.0 || end <= start {
+        return Err(String::from("Expected 0 <= start < end"));
+    }
+    Ok((start, end))
+}
+
+fn cli_frame_rate(v: &str) -> Result<f32, String> {
+    let fps = v
+        .trim()
+        .parse::<f32>()
+        .map_err(|e| format!("Invalid frame rate: {}", e))?;
+    if !fps.is_finite() || fps <= 0.0 {
+        return Err(String::from("Expected a finite frame rate > 0"));
+    }
+    Ok(fps)
+}
+
 static HEADER_GLTF2RDM: &str = "GLTF TO RDM OPTIONS";
 static HEADER_RDM2GLTF: &str = "RDM TO GLTF OPTIONS";
 
 #[derive(Parser)]
 #[command(
     version = env!("CARGO_PKG_VERSION"),
-    author = "lukts30 <https://github.com/lukts30/rdm4>"
+    author = "lukts30 <https://github.com/lukts30/rdm4>",
+    group(ArgGroup::new("textures").args(["diffusetexture", "find_textures", "cfg"]))
 )]
 struct Opts {
     // start of common options
-    /// Input file
+    /// Input file. rdm files can also be read from an archive: data0.rda!/graphics/foo_lod0.rdm
     #[arg(
         display_order(0),
         short = 'i',
@@ -62,19 +113,63 @@ struct Opts {
     #[arg(display_order(5), short = 's', long = "skeleton")]
     skeleton: bool,
 
-    /// Export (available) animation. RDM to glTF needs external animation file (rdanimation)
+    /// Export (available) animation. RDM to glTF needs external animation file (rdanimation). glTF to RDM: optionally only the animation with this name
     #[arg(
         display_order(6),
         short = 'a',
         long = "animation",
+        value_name = "NAME",
+        num_args = 0..=1,
         requires("skeleton")
     )]
-    animation: bool,
+    animation: Option<Option<String>>,
+
+    /// Applies a transform to mesh, skeleton and animation: 16 comma-separated values of a 4x4 matrix in row-major order
+    #[arg(
+        display_order(7),
+        long,
+        value_name = "m00,m01,...,m33",
+        allow_hyphen_values = true,
+        value_parser = cli_transform_matrix
+    )]
+    transform: Option<Matrix4<f32>>,
+
+    /// Unit scale: rdm units per glTF unit. Import multiplies glTF coordinates by it, export divides rdm coordinates by it
+    #[arg(display_order(8), long, default_value = "1.0")]
+    scale: f32,
+
+    /// Up axis of the glTF side: x | y | z | -x | -y | -z
+    #[arg(
+        display_order(9),
+        long,
+        default_value = "y",
+        allow_hyphen_values = true
+    )]
+    up_axis: Axis,
+
+    /// Forward axis of the glTF side: x | y | z | -x | -y | -z
+    #[arg(
+        display_order(10),
+        long,
+        default_value = "z",
+        allow_hyphen_values = true
+    )]
+    forward_axis: Axis,
 
     /// A level of verbosity, and can be used multiple times
-    #[arg(display_order(7), short, long, action = ArgAction::Count)]
+    #[arg(display_order(11), short, long, action = ArgAction::Count)]
     verbose: u8,
 
+    /// Anno model cfg. RDM to glTF: textures, animation (with -s -a [NAME]) and transform of the model. glTF to RDM: material order by glTF material name
+    #[arg(
+        display_order(12),
+        long,
+        value_name = "*.cfg",
+        value_parser = cli_in_is_file,
+        conflicts_with = "overide_mesh_idx"
+    )]
+    cfg: Option<PathBuf>,
+
     // end of common options
     // start of HEADER_GLTF2RDM
     /// VertexFormat for output rdm: P4h_N4b_G4b_B4b_T2h | P4h_N4b_G4b_B4b_T2h_I4b | P4h_N4b_G4b_B4b_T2h_I4b_W4b
@@ -97,7 +192,58 @@ struct Opts {
     )]
     gltf_mesh_index: u32,
 
-    /// glTF to rdm: Do not apply node transforms. Recommended to use when working with animations.
+    /// Merge every mesh node of the default scene into one rdm. Primitives sharing a glTF material share a material slot.
+    #[arg(
+        display_order(2),
+        long,
+        conflicts_with("gltf_mesh_index"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    merge_scene: bool,
+
+    /// glTF mesh to convert, by mesh name
+    #[arg(
+        display_order(1),
+        long,
+        conflicts_with_all = ["gltf_mesh_index", "node", "merge_scene", "split"],
+        help_heading = HEADER_GLTF2RDM
+    )]
+    mesh: Option<String>,
+
+    /// glTF mesh to convert, by the name of the node instantiating it
+    #[arg(
+        display_order(1),
+        long,
+        conflicts_with_all = ["gltf_mesh_index", "merge_scene", "split"],
+        help_heading = HEADER_GLTF2RDM
+    )]
+    node: Option<String>,
+
+    /// glTF skin to use for the skeleton, by name. Default: skin of the mesh node
+    #[arg(display_order(1), long, requires("skeleton"), help_heading = HEADER_GLTF2RDM)]
+    skin: Option<String>,
+
+    /// Write one rdm per glTF mesh ("mesh") or per mesh node ("node") into the output folder.
+    #[arg(
+        display_order(2),
+        long,
+        value_name = "mesh | node",
+        conflicts_with_all = ["merge_scene", "gltf_mesh_index", "in_is_out_filename"],
+        help_heading = HEADER_GLTF2RDM
+    )]
+    split: Option<SplitMode>,
+
+    /// File name template for --split. {mesh}: glTF mesh name, {node}: node name, {index}: running number. Duplicate names get the suffix _{index}
+    #[arg(
+        display_order(2),
+        long,
+        default_value = "{mesh}",
+        requires("split"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    name_template: String,
+
+    /// glTF to rdm: Do not apply node transforms. Otherwise they are baked into mesh, skeleton and animations.
     #[arg(
         display_order(2),
         long = "no_transform",
@@ -106,15 +252,15 @@ struct Opts {
     )]
     no_transform: bool,
 
-    /// Mirrors the object on the x axis.
-    #[arg(display_order(3), long, conflicts_with_all = ["skeleton", "animation"], help_heading = HEADER_GLTF2RDM)]
+    /// Mirrors the object on the x axis. Also mirrors skeleton and animations.
+    #[arg(display_order(3), long, help_heading = HEADER_GLTF2RDM)]
     negative_x_and_v0v2v1: bool,
 
     /// Overrides MeshInstance mesh indcies. Useful to match the material order of an existing cfg.
     #[arg(display_order(4), long, help_heading = HEADER_GLTF2RDM)]
     overide_mesh_idx: Option<Vec<u32>>,
 
-    /// For glTF joint to rdm bone: source for a unique identifier: "UnstableIndex" | "UniqueName"
+    /// For glTF joint to rdm bone: source for a unique identifier: "UnstableIndex" | "UniqueName" | "Disambiguate". Disambiguate renames duplicate names and writes the renames to <rdm>_joints.json
     #[arg(
         display_order(5),
         long,
@@ -124,6 +270,99 @@ struct Opts {
     )]
     gltf_node_joint_name_src: ResolveNodeName,
 
+    /// New origin of the rdm: "keep" | "bbox-centre" | "bbox-bottom-centre" | "x,y,z"
+    #[arg(
+        display_order(6),
+        long,
+        default_value = "keep",
+        allow_hyphen_values = true,
+        help_heading = HEADER_GLTF2RDM
+    )]
+    pivot: Pivot,
+
+    /// Resample the animations at this frame rate. By default keys are copied and only resampled where required
+    #[arg(
+        display_order(7),
+        long,
+        value_name = "FPS",
+        value_parser = cli_frame_rate,
+        requires("animation"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    anim_fps: Option<f32>,
+
+    /// Keys for joints without animation channel (+1). 0 writes a single key
+    #[arg(
+        display_order(7),
+        long,
+        default_value = "6",
+        requires("animation"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    anim_idle_keys: usize,
+
+    /// Only import the animation between start and end (seconds)
+    #[arg(
+        display_order(7),
+        long,
+        value_name = "start,end",
+        value_parser = cli_time_range,
+        requires("animation"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    anim_range: Option<(f32, f32)>,
+
+    /// File name template for animations. {model}: rdm file name, {anim}: glTF animation name
+    #[arg(
+        display_order(7),
+        long,
+        default_value = ANIM_NAME_TEMPLATE,
+        requires("animation"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    anim_name_template: String,
+
+    /// Animated scale: "reject" fails listing the scaled joints, "bake" folds uniform scale into child joint translations
+    #[arg(
+        display_order(7),
+        long,
+        default_value = "reject",
+        requires("animation"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    anim_scale: ScaleChannels,
+
+    /// Only import the animations, for the skeleton of this rdm. Joints are matched by name
+    #[arg(
+        display_order(8),
+        long,
+        value_name = "rdm FILE",
+        value_parser = cli_in_is_file,
+        requires("animation"),
+        conflicts_with_all = ["gltf", "merge_scene", "split", "mesh", "node"],
+        help_heading = HEADER_GLTF2RDM
+    )]
+    target_rdm: Option<PathBuf>,
+
+    /// Encode the baseColor, normal and metallicRoughness images as "bc7" | "bc3" | "bc1" DDS next to the rdm (<model>_diff_0.dds, ...). The rdm materials record the diffuse texture, --write-cfg also the normal and metal textures
+    #[arg(
+        display_order(9),
+        long,
+        value_name = "ENCODING",
+        requires("gltf"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    encode_textures: Option<BcEncoding>,
+
+    /// Write a <model>.cfg next to the rdm with the model, its materials in rdm order (textures with --encode-textures) and an animation sequence per written animation
+    #[arg(
+        display_order(10),
+        long,
+        requires("gltf"),
+        help_heading = HEADER_GLTF2RDM
+    )]
+    write_cfg: bool,
+
     // end of HEADER_GLTF2RDM
     // start of HEADER_RDM2GLTF
     /// Export format to use for rdm to gltf: "glb", "gltf", "gltfmin"
@@ -159,6 +398,51 @@ struct Opts {
         help_heading = HEADER_RDM2GLTF
     )]
     diffusetexture: Option<Vec<PathBuf>>,
+
+    /// Find the textures of each material: the texture paths stored in the rdm, below --data-root or next to the rdm, and the Anno texture set <model>_diff_0.dds, <model>_norm_0.dds, <model>_metal_0.dds next to the rdm
+    #[arg(long, display_order(2), help_heading = HEADER_RDM2GLTF)]
+    find_textures: bool,
+
+    /// Directory the paths stored in rdm and cfg files are relative to, e.g. the extracted game files or an archive (data0.rda!)
+    #[arg(
+        long,
+        value_name = "DIR",
+        display_order(2),
+        help_heading = HEADER_RDM2GLTF
+    )]
+    data_root: Option<PathBuf>,
+
+    /// Mipmap level of the textures written to PNG. 0 is the full resolution.
+    #[arg(
+        long = "texture-mip",
+        value_name = "LEVEL",
+        default_value_t = 0,
+        display_order(2),
+        requires = "textures",
+        help_heading = HEADER_RDM2GLTF
+    )]
+    texture_mip: u32,
+
+    /// How textures are stored: "png", "dds" (MSFT_texture_dds with PNG fallback), "dds-only". Normal, metal and height maps are always converted to PNG
+    #[arg(
+        long = "texture-export",
+        value_name = "FORMAT",
+        default_value = "png",
+        display_order(2),
+        requires = "textures",
+        help_heading = HEADER_RDM2GLTF
+    )]
+    texture_export: TextureExport,
+
+    /// Export the materials as cutout (alpha mask) with this cutoff. Default: 0.5 for *_cutout_lod<n>.rdm, otherwise opaque
+    #[arg(
+        long,
+        value_name = "CUTOFF",
+        display_order(2),
+        requires = "textures",
+        help_heading = HEADER_RDM2GLTF
+    )]
+    alpha_cutoff: Option<f32>,
     // end of HEADER_RDM2GLTF
 }
 
@@ -191,12 +475,19 @@ fn entry_do_work(mut opts: Opts) {
         }
     }
 
+    if let Err(e) = UnitConversion::new(opts.scale, opts.up_axis, opts.forward_axis) {
+        error!("{}", e);
+        std::process::exit(2);
+    }
+
     info!("overide_mesh_idx: {:?}", &opts.overide_mesh_idx);
     // Gets a value for config if supplied by user, or defaults to "default.conf"
     info!("Using input file: {:?}", opts.input);
     info!("Export skeleton: {:?}", opts.skeleton);
     info!("Export rdanimation: {:?}", opts.rdanimation);
-    if opts.gltf.is_none() {
+    if opts.target_rdm.is_some() {
+        convert_gltf_anim_to_rdm(opts);
+    } else if opts.gltf.is_none() {
         convert_rdm_to_gltf(opts);
     } else {
         convert_gltf_to_rdm(opts);
@@ -205,61 +496,361 @@ fn entry_do_work(mut opts: Opts) {
 
 fn convert_rdm_to_gltf(opts: Opts) {
     let mut rdm = RdModell::from(opts.input.as_path());
-    if opts.skeleton && opts.rdanimation.is_none() {
-        rdm.add_skin();
-        info!("Skin added !");
-    } else if opts.skeleton && opts.rdanimation.is_some() {
+    let cfg = opts.cfg.as_deref().map(open_cfg);
+    let cfg_model = cfg.as_ref().and_then(|cfg| {
+        let model = cfg.model_for(&opts.input);
+        if model.is_none() {
+            warn!("the cfg has no model for {:?}", opts.input);
+        }
+        model
+    });
+
+    let anim_file = match (&opts.rdanimation, &opts.animation, cfg_model) {
+        (Some(file), _, _) => Some(file.clone()),
+        (None, Some(name), Some(model)) => cfg_animation(
+            cfg.as_ref().unwrap(),
+            model,
+            name.as_deref(),
+            opts.data_root.as_deref(),
+        ),
+        _ => None,
+    };
+    if opts.skeleton {
         rdm.add_skin();
-        let anim = RdAnim::from(opts.rdanimation.unwrap().as_path());
-        rdm.add_anim(anim);
-        info!("Skin and anim added !");
+        match anim_file {
+            Some(file) => {
+                let anim = RdAnim::from(file.as_path());
+                rdm.add_anim(anim);
+                info!("Skin and anim added !");
+            }
+            None => info!("Skin added !"),
+        }
     } else {
         warn!("No skin. No anim !");
     }
 
-    if let Some(diffusetexture) = opts.diffusetexture {
-        rdm.mat = Some(RdMaterial::new(diffusetexture));
+    if let Some(transform) = cfg_model.and_then(|m| m.transform) {
+        info!("cfg transform: {}", transform);
+        apply_transform(&mut rdm, transform);
+    }
+    if let Some(transform) = opts.transform {
+        apply_transform(&mut rdm, transform);
+    }
+
+    let mat = match opts.diffusetexture {
+        Some(diffusetexture) => Some(RdMaterial::new(diffusetexture)),
+        None if opts.find_textures => Some(RdMaterial::discover(
+            &opts.input,
+            &rdm.material_textures(),
+            opts.data_root.as_deref(),
+        )),
+        None => cfg_model.map(|model| {
+            cfg.as_ref()
+                .unwrap()
+                .material(model, opts.data_root.as_deref())
+        }),
+    };
+    if let Some(mut mat) = mat {
+        mat.mip_level = opts.texture_mip;
+        mat.texture_export = opts.texture_export;
+        let cutout = is_cutout_model(&opts.input).then_some(0.5);
+        if let Some(cutoff) = opts.alpha_cutoff.or(cutout) {
+            mat.set_alpha_cutoff(cutoff);
+        }
+        rdm.mat = Some(mat);
     }
     info!("running gltf_export ...");
 
-    gltf_export::build(rdm, opts.out, !opts.force, opts.gltf_export_format);
+    let unit_conversion = UnitConversion::new(opts.scale, opts.up_axis, opts.forward_axis).unwrap();
+    gltf_export::build(
+        rdm,
+        opts.out,
+        !opts.force,
+        opts.gltf_export_format,
+        unit_conversion,
+    );
+}
+
+fn apply_transform(rdm: &mut RdModell, transform: Matrix4<f32>) {
+    if let Err(e) = rdm.transform(transform) {
+        error!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+fn open_cfg(path: &Path) -> Cfg {
+    match Cfg::open(path) {
+        Ok(cfg) => cfg,
+        Err(e) => {
+            error!("{:?}: {}", path, e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Animation file of `model`: the first one whose file name contains `name`, or the first one.
+fn cfg_animation(
+    cfg: &Cfg,
+    model: &CfgModel,
+    name: Option<&str>,
+    data_root: Option<&Path>,
+) -> Option<PathBuf> {
+    let anim = match name {
+        Some(name) => model.animations.iter().find(|a| a.file_name.contains(name)),
+        None => model.animations.first(),
+    };
+    let anim = match anim {
+        Some(anim) => anim,
+        None => {
+            warn!("the cfg has no animation {:?}", name);
+            return None;
+        }
+    };
+    let file = cfg.resolve(&anim.file_name, data_root);
+    if file.is_none() {
+        warn!("animation {:?} not found", anim.file_name);
+    }
+    file
 }
 
 fn convert_gltf_to_rdm(opts: Opts) {
     let f_path = opts.input.as_path();
-    let i_gltf = gltf_reader::ImportedGltf::try_import(
-        f_path,
-        opts.gltf_mesh_index,
+    let mesh = match (&opts.mesh, &opts.node) {
+        (Some(name), _) => MeshSelector::MeshName(name.clone()),
+        (None, Some(name)) => MeshSelector::NodeName(name.clone()),
+        (None, None) => MeshSelector::Index(opts.gltf_mesh_index),
+    };
+    let mut i_gltf =
+        match gltf_reader::ImportedGltf::try_import(f_path, mesh, opts.gltf_node_joint_name_src) {
+            Ok(i_gltf) => i_gltf,
+            Err(e) => {
+                error!("{}", e);
+                std::process::exit(1);
+            }
+        };
+    if let Some(skin) = opts.skin.as_ref() {
+        if let Err(e) = i_gltf.select_skin(skin) {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    }
+    apply_gltf_options(&opts, &mut i_gltf);
+    i_gltf.merge_scene = opts.merge_scene;
+
+    match opts.split {
+        Some(mode) => {
+            let dir = opts.out.clone().unwrap_or_else(|| PathBuf::from("rdm_out"));
+            std::fs::create_dir_all(&dir).expect("I/O error");
+            let targets = i_gltf.split_targets(mode);
+            info!("split {:?}: {} rdm files", mode, targets.len());
+            let mut names: Vec<String> = Vec::new();
+            for (index, target) in targets.into_iter().enumerate() {
+                match mode {
+                    SplitMode::Mesh => i_gltf.change_mesh_index(target),
+                    SplitMode::Node => i_gltf.change_mesh_node(target),
+                }
+                let name = i_gltf.output_name(&opts.name_template, index, &names);
+                let mut out = dir.join(&name);
+                out.set_extension("rdm");
+                names.push(name);
+                let load_skin = opts.skeleton && i_gltf.has_skin();
+                if opts.skeleton && !load_skin {
+                    warn!("{:?} has no skin. Skipping skeleton and animation.", out);
+                }
+                info!("writing {:?}", out);
+                gltf_to_rdm_write(&opts, &i_gltf, Some(out), load_skin);
+            }
+        }
+        None => gltf_to_rdm_write(&opts, &i_gltf, opts.out.clone(), opts.skeleton),
+    }
+}
+
+fn apply_gltf_options(opts: &Opts, i_gltf: &mut gltf_reader::ImportedGltf) {
+    i_gltf.unit_conversion =
+        UnitConversion::new(opts.scale, opts.up_axis, opts.forward_axis).unwrap();
+    i_gltf.pivot = opts.pivot;
+    i_gltf.anim_fps = opts.anim_fps;
+    i_gltf.anim_range = opts.anim_range;
+    i_gltf.scale_channels = opts.anim_scale;
+}
+
+fn convert_gltf_anim_to_rdm(opts: Opts) {
+    let target = opts.target_rdm.clone().unwrap();
+    let mut rdm = RdModell::from(target.as_path());
+    rdm.add_skin();
+
+    let mut i_gltf = match gltf_reader::ImportedGltf::try_import_without_mesh(
+        opts.input.as_path(),
         opts.gltf_node_joint_name_src,
-    )
-    .unwrap();
+    ) {
+        Ok(i_gltf) => i_gltf,
+        Err(e) => {
+            error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    apply_gltf_options(&opts, &mut i_gltf);
+
+    // <target>_<anim>.rdm next to the target, unless an output is given
+    let model = match opts.out.as_ref() {
+        Some(dir) if dir.is_dir() => dir.join(target.file_name().unwrap()),
+        Some(file) => file.clone(),
+        None => target.clone(),
+    };
+    let baked = BakedTransform::without_mesh(opts.pivot);
+    write_animations(&opts, &i_gltf, rdm.joints.as_ref().unwrap(), &baked, &model);
+}
 
-    let rdm = gltf_reader::ImportedGltf::gltf_to_rdm(
-        &i_gltf,
-        opts.gltf.unwrap(),
-        opts.skeleton,
+/// Returns the written animation files with their length in ms.
+fn write_animations(
+    opts: &Opts,
+    i_gltf: &gltf_reader::ImportedGltf,
+    jj: &[RdJoint],
+    baked: &BakedTransform,
+    model: &Path,
+) -> Vec<(PathBuf, u32)> {
+    let animation_name = opts.animation.as_ref().unwrap();
+    let mut written = Vec::new();
+    match gltf_reader::ImportedGltf::read_animation(
+        i_gltf,
+        jj,
+        baked,
+        opts.anim_idle_keys,
         opts.negative_x_and_v0v2v1,
-        opts.no_transform,
-        opts.overide_mesh_idx,
-    );
-
-    if opts.skeleton && opts.animation {
-        let jj = rdm.joints.as_ref().unwrap();
+        animation_name.as_deref(),
+    ) {
+        Ok(anims) if anims.is_empty() => {
+            error!("Could not read animation. Does the glTF contain any animations ?")
+        }
+        Ok(mut anims) => {
+            let model_name = model.file_stem().unwrap().to_string_lossy();
+            for mut anim in anims.drain(..) {
+                if let Some(transform) = opts.transform {
+                    // checked by cli_transform_matrix
+                    anim.transform(transform, jj).unwrap();
+                }
+                let file = model.with_file_name(anim_file_name(
+                    &opts.anim_name_template,
+                    &model_name,
+                    &anim.name,
+                ));
+                info!("writing animation {:?}", file);
+                let time_max = anim.time_max;
+                let exp_rdm = RdAnimWriter2::new(anim);
+                exp_rdm.write_anim_rdm_as(file.clone(), !opts.force);
+                written.push((file.with_extension("rdm"), time_max));
+            }
+        }
+        Err(e) => error!("Could not read animation: {}", e),
+    }
+    written
+}
 
-        match gltf_reader::ImportedGltf::read_animation(&i_gltf, jj, 6, 0.33333) {
-            Some(mut anims) => {
-                for anim in anims.drain(..) {
-                    let exp_rdm = RdAnimWriter2::new(anim);
-                    exp_rdm.write_anim_rdm(opts.out.clone(), !opts.force);
+fn gltf_to_rdm_write(
+    opts: &Opts,
+    i_gltf: &gltf_reader::ImportedGltf,
+    out: Option<PathBuf>,
+    load_skin: bool,
+) {
+    // same location and model name as RdWriter2::write_rdm
+    let model = RdWriter2::output_path(out.clone());
+
+    let overide_mesh_idx = match opts.cfg.as_deref() {
+        Some(path) => {
+            let cfg = open_cfg(path);
+            let cfg_model = cfg.model_for(&model).unwrap_or(&cfg.models[0]);
+            match Cfg::material_order(cfg_model, &i_gltf.material_names()) {
+                Ok(order) => {
+                    info!("material order of {:?}: {:?}", path, order);
+                    Some(order)
+                }
+                Err(e) => {
+                    error!("{:?}: {}", path, e);
+                    std::process::exit(1);
                 }
             }
-            None => error!("Could not read animation. Does the glTF contain any animations ?"),
         }
+        None => opts.overide_mesh_idx.clone(),
+    };
+
+    let (mut rdm, baked) = match gltf_reader::ImportedGltf::gltf_to_rdm(
+        i_gltf,
+        opts.gltf.clone().unwrap(),
+        load_skin,
+        opts.negative_x_and_v0v2v1,
+        opts.no_transform,
+        overide_mesh_idx.clone(),
+    ) {
+        Ok(converted) => converted,
+        Err(e) => {
+            error!("Could not convert the glTF: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    let mut animations = Vec::new();
+    if load_skin && opts.animation.is_some() {
+        animations = write_animations(opts, i_gltf, rdm.joints.as_ref().unwrap(), &baked, &model);
+    }
+
+    if let Some(encoding) = opts.encode_textures {
+        let textures = match i_gltf.write_textures(
+            model.parent().unwrap(),
+            &texture_set_name(&model),
+            encoding,
+            overide_mesh_idx.as_deref(),
+        ) {
+            Ok(textures) => textures,
+            Err(e) => {
+                error!("texture encoding failed: {}", e);
+                std::process::exit(1);
+            }
+        };
+        // the rdm only stores the diffuse path, --write-cfg references the normal and metal textures
+        rdm.mat = Some(RdMaterial::from_texture_sets(textures));
     }
 
+    if let Some(transform) = opts.transform {
+        apply_transform(&mut rdm, transform);
+    }
+
+    let cfg = opts.write_cfg.then(|| {
+        let dir = model.parent().unwrap();
+        let textures = rdm.mat.as_ref().map_or(&[][..], |m| m.texture_sets());
+        let materials = i_gltf
+            .rdm_material_names(overide_mesh_idx.as_deref())
+            .into_iter()
+            .enumerate()
+            .map(|(i, name)| {
+                let set = textures.get(i).cloned().unwrap_or_default();
+                let mut material = CfgMaterial::from_texture_set(name, &set, dir);
+                material.vertex_format = Some(format!("{:?}", opts.gltf.as_ref().unwrap()));
+                material
+            })
+            .collect();
+        Cfg::for_model(&model, materials, &animations)
+    });
+
     let exp_rdm = RdWriter2::new(rdm);
-    exp_rdm.write_rdm(opts.out, !opts.force);
-    if opts.skeleton && !opts.no_transform {
-        error!("glTF skeleton is set, but no_transform is not! Animation & Mesh might be severely deformed! Use --no_transform and apply rotation & translation in the cfg file.");
+    let written = exp_rdm.write_rdm(out, !opts.force);
+
+    if let Some(cfg) = cfg {
+        let file = written.with_file_name(format!("{}.cfg", texture_set_name(&written)));
+        info!("writing cfg {:?}", file);
+        if !opts.force && file.exists() {
+            error!("{:?} exists, use --force to overwrite it", file);
+        } else {
+            cfg.write(&file).expect("I/O error");
+        }
+    }
+
+    if load_skin
+        && i_gltf.name_setting == ResolveNodeName::Disambiguate
+        && !i_gltf.renamed_nodes().is_empty()
+    {
+        let stem = written.file_stem().unwrap().to_string_lossy();
+        let map = written.with_file_name(format!("{}_joints.json", stem));
+        info!("renamed nodes: {:?}", map);
+        i_gltf.write_node_name_map(&map).expect("I/O error");
     }
 }